@@ -0,0 +1,306 @@
+use std::{collections::HashMap, io::Error, path::Path};
+
+use crate::{
+    film::Framebuffer,
+    integrator::PathRadiance,
+    objects::object::HitRecord,
+    vec3::{Color3, Vec3},
+};
+
+// Auxiliary (arbitrary output variable) passes rendered next to the beauty image.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AovPass {
+    Depth,
+    Normal,
+    Albedo,
+    MaterialId,
+    ObjectId,
+    DirectLighting,
+    IndirectLighting,
+    SampleCount,
+}
+
+impl AovPass {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AovPass::Depth => "depth",
+            AovPass::Normal => "normal",
+            AovPass::Albedo => "albedo",
+            AovPass::MaterialId => "material_id",
+            AovPass::ObjectId => "object_id",
+            AovPass::DirectLighting => "direct",
+            AovPass::IndirectLighting => "indirect",
+            AovPass::SampleCount => "sample_count",
+        }
+    }
+}
+
+// What the camera ray hit first, integrators hand it back with the radiance.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FirstHit {
+    pub depth: f64,
+    pub normal: Vec3,
+    pub albedo: Color3,
+    pub material_id: usize,
+    pub object_id: usize,
+}
+
+impl FirstHit {
+    pub fn new(hit: &HitRecord) -> Self {
+        FirstHit {
+            depth: hit.ray_scalar,
+            normal: hit.normal,
+            albedo: hit.material.albedo(hit),
+            material_id: hit.material_id,
+            object_id: hit.object_id,
+        }
+    }
+}
+
+// Everything a single camera sample contributes to the beauty and AOV buffers.
+pub struct AovSample {
+    pub radiance: PathRadiance,
+    pub depth: Option<f64>,
+    pub normal: Vec3,
+    pub albedo: Color3,
+    pub material_key: Option<usize>,
+    pub object_id: Option<usize>,
+}
+
+impl AovSample {
+    pub fn new(radiance: PathRadiance) -> Self {
+        match radiance.first_hit {
+            Some(hit) => AovSample {
+                radiance,
+                depth: Some(hit.depth),
+                normal: hit.normal,
+                albedo: hit.albedo,
+                material_key: Some(hit.material_id),
+                object_id: Some(hit.object_id),
+            },
+            None => AovSample {
+                radiance,
+                depth: None,
+                normal: Vec3::ZERO,
+                albedo: Color3::ZERO,
+                material_key: None,
+                object_id: None,
+            },
+        }
+    }
+}
+
+// Per-pixel accumulation of camera samples.
+#[derive(Default)]
+pub struct PixelSamples {
+    pub sample_count: u32,
+    direct: Color3,
+    indirect: Color3,
+    normal: Vec3,
+    albedo: Color3,
+    depth: f64,
+    depth_hits: u32,
+    material_key: Option<usize>,
+    object_id: Option<usize>,
+}
+
+impl PixelSamples {
+    pub fn add(&mut self, sample: &AovSample) {
+        self.sample_count += 1;
+        self.direct += sample.radiance.direct;
+        self.indirect += sample.radiance.indirect;
+        self.normal += sample.normal;
+        self.albedo += sample.albedo;
+        if let Some(depth) = sample.depth {
+            self.depth += depth;
+            self.depth_hits += 1;
+        }
+        // ids cannot be averaged, the first sample decides
+        if self.sample_count == 1 {
+            self.material_key = sample.material_key;
+            self.object_id = sample.object_id;
+        }
+    }
+
+    pub fn color(&self) -> Color3 {
+        self.average(self.direct + self.indirect)
+    }
+
+    pub fn direct(&self) -> Color3 {
+        self.average(self.direct)
+    }
+
+    pub fn indirect(&self) -> Color3 {
+        self.average(self.indirect)
+    }
+
+    pub fn albedo(&self) -> Color3 {
+        self.average(self.albedo)
+    }
+
+    pub fn normal(&self) -> Vec3 {
+        if self.normal.near_zero() {
+            Vec3::ZERO
+        } else {
+            self.normal.normalize()
+        }
+    }
+
+    pub fn depth(&self) -> f64 {
+        if self.depth_hits == 0 {
+            f64::INFINITY
+        } else {
+            self.depth / self.depth_hits as f64
+        }
+    }
+
+    fn average(&self, sum: Color3) -> Color3 {
+        if self.sample_count == 0 {
+            Color3::ZERO
+        } else {
+            sum / self.sample_count as f64
+        }
+    }
+}
+
+pub struct AovBuffers {
+    width: usize,
+    height: usize,
+    passes: Vec<(AovPass, Framebuffer)>,
+    // passes only rendered for internal use (e.g. denoiser guides), they are not written out
+    internal_passes: Vec<AovPass>,
+    material_ids: HashMap<usize, usize>,
+}
+
+impl AovBuffers {
+    pub fn new(passes: &[AovPass], width: usize, height: usize) -> Self {
+        AovBuffers {
+            width,
+            height,
+            passes: passes
+                .iter()
+                .map(|pass| (*pass, Framebuffer::new(width, height)))
+                .collect(),
+            internal_passes: Vec::new(),
+            material_ids: HashMap::new(),
+        }
+    }
+
+    pub fn add_internal_pass(&mut self, pass: AovPass) {
+        if self.get(pass).is_none() {
+            self.passes
+                .push((pass, Framebuffer::new(self.width, self.height)));
+            self.internal_passes.push(pass);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+
+    pub fn get(&self, pass: AovPass) -> Option<&Framebuffer> {
+        self.passes
+            .iter()
+            .find(|(p, _)| *p == pass)
+            .map(|(_, buffer)| buffer)
+    }
+
+    pub fn store(&mut self, x: usize, y: usize, pixel: &PixelSamples) {
+        // ids are offset by one so that 0 means "nothing was hit"
+        let next_material_id = self.material_ids.len() + 1;
+        let material_id = pixel.material_key.map_or(0, |key| {
+            *self.material_ids.entry(key).or_insert(next_material_id)
+        });
+        let object_id = pixel.object_id.map_or(0, |id| id + 1);
+
+        for (pass, buffer) in &mut self.passes {
+            let value = match pass {
+                AovPass::Depth => Color3::from_float(pixel.depth()),
+                AovPass::Normal => pixel.normal(),
+                AovPass::Albedo => pixel.albedo(),
+                AovPass::MaterialId => Color3::from_float(material_id as f64),
+                AovPass::ObjectId => Color3::from_float(object_id as f64),
+                AovPass::DirectLighting => pixel.direct(),
+                AovPass::IndirectLighting => pixel.indirect(),
+                AovPass::SampleCount => Color3::from_float(pixel.sample_count as f64),
+            };
+            buffer.set(x, y, value);
+        }
+    }
+
+    // Passes are written next to the beauty image, e.g. render.ppm -> render.depth.pfm
+    pub fn write(&self, beauty_filename: &str) -> Result<(), Error> {
+        for (pass, buffer) in &self.passes {
+            if self.internal_passes.contains(pass) {
+                continue;
+            }
+            let path = Path::new(beauty_filename).with_extension(format!("{}.pfm", pass.name()));
+            let filename = path.to_string_lossy();
+            buffer.write_pfm(&filename)?;
+            println!("Written to: {}", filename);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::{
+        material::{Lambert, Material, Metallic, Mix},
+        objects::{plane::Plane, sphere::Sphere},
+        ray::Ray,
+        utils::interval::Interval,
+        vec3::Pos3,
+        world::World,
+    };
+
+    fn material_key(world: &World, origin: Pos3) -> Option<usize> {
+        let ray = Ray::new(origin, Vec3::new(0.0, -1.0, 0.0));
+        let hit = world
+            .hit_objects(&ray, &Interval::new(0.0001, f64::MAX))
+            .unwrap();
+        let radiance = PathRadiance {
+            first_hit: Some(FirstHit::new(&hit)),
+            ..PathRadiance::ZERO
+        };
+        AovSample::new(radiance).material_key
+    }
+
+    #[test]
+    fn material_key_is_stable() {
+        // the mix hands every hit its own component
+        let mut world = World::new();
+        world.add_object(Plane::new(
+            Pos3::ZERO,
+            Mix::new(Lambert::new(Color3::WHITE), Metallic::default(), 0.5),
+        ));
+        let keys: Vec<Option<usize>> = (0..100)
+            .map(|_| material_key(&world, Pos3::new(0.0, 1.0, 0.0)))
+            .collect();
+        assert!(keys.iter().all(|key| *key == keys[0]));
+    }
+
+    #[test]
+    fn shared_materials_share_a_key() {
+        let mut world = World::new();
+        let shared: Rc<dyn Material> = Rc::new(Lambert::new(Color3::WHITE));
+        world.add_object(Sphere::shared(
+            Pos3::new(0.0, 0.0, 0.0),
+            0.5,
+            shared.clone(),
+        ));
+        world.add_object(Sphere::shared(Pos3::new(2.0, 0.0, 0.0), 0.5, shared));
+        world.add_object(Sphere::new(
+            Pos3::new(4.0, 0.0, 0.0),
+            0.5,
+            Lambert::new(Color3::WHITE),
+        ));
+        let key = |x| material_key(&world, Pos3::new(x, 1.0, 0.0));
+        assert_eq!(key(0.0), key(2.0));
+        assert_ne!(key(0.0), key(4.0));
+    }
+}
@@ -1,12 +1,17 @@
 use rand::Rng;
-use std::{
-    fs::OpenOptions,
-    io::{Error, Write},
-};
+use std::{f64::consts::PI, io::Error};
 
 use crate::{
-    ray::Ray,
-    utils::helpers::{color_to_ppm, degrees_to_radians, random_in_unit_disk},
+    aov::{AovBuffers, AovPass, AovSample, PixelSamples},
+    color_space::ColorSpace,
+    denoise::DenoiseMethod,
+    film::Framebuffer,
+    integrator::{Integrator, PathRadiance},
+    ray::{Ray, RayDifferential},
+    sampler,
+    spectrum::SampledWavelengths,
+    tonemap::{ToneMapper, ToneMapping},
+    utils::helpers::{degrees_to_radians, random_in_unit_disk},
     vec3::{Color3, Pos3, Vec3},
     world::World,
 };
@@ -18,7 +23,11 @@ pub struct Camera {
     pub aspect_ratio: f64,
     pub render_image_width: i32,
     pub anti_aliasing: AntiAliasingMethod,
-    pub max_ray_bounces: u16,
+    pub rendering_mode: RenderingMode,
+    pub aov_passes: Vec<AovPass>,
+    pub denoise: DenoiseMethod,
+    pub tone_mapper: ToneMapper,
+    pub output_color_space: ColorSpace,
 
     defocus_angle: f64,
     defocus_disk_u: Vec3,
@@ -38,9 +47,38 @@ pub struct CameraSetup {
     pub position: Vec3,
     pub look_at: Vec3,
     pub anti_aliasing: AntiAliasingMethod,
-    pub max_ray_bounces: u16,
+    pub rendering_mode: RenderingMode,
     pub focus_distance: f64,
     pub defocus_angle: f64,
+    pub aov_passes: Vec<AovPass>,
+    pub denoise: DenoiseMethod,
+    pub tone_mapping: ToneMapping,
+    // in EV stops
+    pub exposure: f64,
+    pub output_color_space: ColorSpace,
+}
+
+// Maps points back onto the film, for integrators that connect light paths to the camera.
+#[derive(Clone, Debug)]
+pub struct CameraProjection {
+    position: Pos3,
+    forward: Vec3,
+    defocus_disk_u: Vec3,
+    defocus_disk_v: Vec3,
+    lens_radius: f64,
+    pixel_00_loc: Pos3,
+    pixel_delta_u: Vec3,
+    pixel_delta_v: Vec3,
+    pub width: usize,
+    pub height: usize,
+    // film area on the plane at unit distance in front of the lens
+    film_area: f64,
+}
+
+pub enum RenderingMode {
+    Rgb,
+    // paths carry wavelength samples, needed for dispersion
+    Spectral,
 }
 
 pub enum AntiAliasingMethod {
@@ -49,6 +87,80 @@ pub enum AntiAliasingMethod {
     RandomSuperSampling(u16),
 }
 
+impl CameraProjection {
+    // Point on the lens a connection to the camera ends at.
+    pub fn sample_lens(&self) -> Pos3 {
+        if self.lens_radius <= 0.0 {
+            return self.position;
+        }
+        let p = random_in_unit_disk();
+        self.position + (p.x * self.defocus_disk_u) + (p.y * self.defocus_disk_v)
+    }
+
+    // Camera ray through the continuous raster position (`x`, `y`), pixel (0, 0) covers [0, 1)².
+    pub fn ray(&self, x: f64, y: f64) -> Ray {
+        let lens_point = self.sample_lens();
+        let film_point =
+            self.pixel_00_loc + (x - 0.5) * self.pixel_delta_u + (y - 0.5) * self.pixel_delta_v;
+        Ray::new(lens_point, film_point - lens_point)
+    }
+
+    pub fn lens_area(&self) -> f64 {
+        if self.lens_radius > 0.0 {
+            PI * self.lens_radius * self.lens_radius
+        } else {
+            // pinhole, the position is a delta distribution
+            1.0
+        }
+    }
+
+    // Pixel the ray from `lens_point` through `point` lands on.
+    pub fn raster(&self, lens_point: &Pos3, point: &Pos3) -> Option<(usize, usize)> {
+        let direction = *point - *lens_point;
+        let denominator = Vec3::dot(&direction, &self.forward);
+        if denominator <= 0.0 {
+            return None;
+        }
+        let scalar = Vec3::dot(&(self.pixel_00_loc - *lens_point), &self.forward) / denominator;
+        let offset = *lens_point + scalar * direction - self.pixel_00_loc;
+        // pixel centers sit at whole coordinates
+        let x = Vec3::dot(&offset, &self.pixel_delta_u) / self.pixel_delta_u.length_squared() + 0.5;
+        let y = Vec3::dot(&offset, &self.pixel_delta_v) / self.pixel_delta_v.length_squared() + 0.5;
+        if x < 0.0 || y < 0.0 || x >= self.width as f64 || y >= self.height as f64 {
+            return None;
+        }
+        Some((x as usize, y as usize))
+    }
+
+    fn cos_theta(&self, lens_point: &Pos3, point: &Pos3) -> Option<f64> {
+        self.raster(lens_point, point)?;
+        Some(Vec3::dot(
+            &(*point - *lens_point).normalize(),
+            &self.forward,
+        ))
+    }
+
+    // Importance emitted from `lens_point` towards `point`, normalized over the film.
+    pub fn importance(&self, lens_point: &Pos3, point: &Pos3) -> f64 {
+        match self.cos_theta(lens_point, point) {
+            Some(cos_theta) => 1.0 / (self.film_area * self.lens_area() * cos_theta.powi(4)),
+            None => 0.0,
+        }
+    }
+
+    // Solid angle density of a camera ray from `lens_point` going towards `point`.
+    pub fn pdf_direction(&self, lens_point: &Pos3, point: &Pos3) -> f64 {
+        match self.cos_theta(lens_point, point) {
+            Some(cos_theta) => 1.0 / (self.film_area * cos_theta.powi(3)),
+            None => 0.0,
+        }
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        self.forward
+    }
+}
+
 impl Camera {
     pub fn new(config: CameraSetup) -> Self {
         let image_width = config.image_width;
@@ -92,72 +204,162 @@ impl Camera {
             pixel_delta_u,
             pixel_delta_v,
             anti_aliasing: config.anti_aliasing,
-            max_ray_bounces: config.max_ray_bounces,
+            rendering_mode: config.rendering_mode,
+            aov_passes: config.aov_passes,
+            denoise: config.denoise,
+            tone_mapper: ToneMapper::new(config.tone_mapping, config.exposure),
+            output_color_space: config.output_color_space,
             defocus_disk_u: camera_u * defocus_radius,
             defocus_disk_v: camera_v * defocus_radius,
             defocus_angle,
         }
     }
 
-    pub fn render(&self, world: &World, filename: &str) -> Result<(), Error> {
-        // todo: move file logic outside of the camera
-        let mut file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(filename)?;
+    pub fn projection(&self) -> CameraProjection {
+        let forward = (self.look_at - self.position).normalize();
+        let width = self.render_image_width as usize;
+        let height = self.render_image_heigh as usize;
+        let focus_plane_distance = Vec3::dot(&(self.pixel_00_loc - self.position), &forward);
+        let film_area = width as f64
+            * self.pixel_delta_u.length()
+            * height as f64
+            * self.pixel_delta_v.length()
+            / (focus_plane_distance * focus_plane_distance);
 
-        let mut write_buffer = String::new();
+        CameraProjection {
+            position: self.position,
+            forward,
+            defocus_disk_u: self.defocus_disk_u,
+            defocus_disk_v: self.defocus_disk_v,
+            lens_radius: if self.defocus_angle > 0.0 {
+                self.defocus_disk_u.length()
+            } else {
+                0.0
+            },
+            pixel_00_loc: self.pixel_00_loc,
+            pixel_delta_u: self.pixel_delta_u,
+            pixel_delta_v: self.pixel_delta_v,
+            width,
+            height,
+            film_area,
+        }
+    }
 
-        write_buffer.push_str(
-            format!(
-                "P3\n{} {}\n255\n",
-                self.render_image_width, self.render_image_heigh
-            )
-            .as_str(),
-        );
+    fn samples_per_pixel(&self) -> u16 {
+        match self.anti_aliasing {
+            AntiAliasingMethod::None => 1,
+            AntiAliasingMethod::UniformSuperSampling(samples)
+            | AntiAliasingMethod::RandomSuperSampling(samples) => samples,
+        }
+    }
+
+    pub fn render(
+        &self,
+        world: &World,
+        integrator: &dyn Integrator,
+        filename: &str,
+    ) -> Result<(), Error> {
+        let width = self.render_image_width as usize;
+        let height = self.render_image_heigh as usize;
+        let mut beauty = Framebuffer::new(width, height);
+        let mut aovs = AovBuffers::new(&self.aov_passes, width, height);
+        if let DenoiseMethod::ATrous(_) = self.denoise {
+            aovs.add_internal_pass(AovPass::Albedo);
+            aovs.add_internal_pass(AovPass::Normal);
+        }
 
         for y in 0..self.render_image_heigh {
             eprintln!("Scan-lines processed: {}/{}", y, self.render_image_heigh);
 
             for x in 0..self.render_image_width {
-                let pixel_color = match self.anti_aliasing {
+                let mut pixel = PixelSamples::default();
+                match self.anti_aliasing {
                     AntiAliasingMethod::None => {
                         let pixel_pos = self.pixel_00_loc
                             + y as f64 * self.pixel_delta_v
                             + x as f64 * self.pixel_delta_u;
-                        let ray = Ray::new(self.position, pixel_pos - self.position);
-                        ray.ray_color(world, self.max_ray_bounces)
+                        let ray = self.ray_with_differentials(self.position, pixel_pos);
+                        pixel.add(&self.sample(ray, world, integrator));
                     }
                     AntiAliasingMethod::RandomSuperSampling(samples) => {
-                        let mut color_sum = Color3::ZERO;
                         for _ in 0..samples {
                             let ray = self.get_random_ray(x, y);
-                            let ray_color = ray.ray_color(world, self.max_ray_bounces);
-                            color_sum += ray_color;
+                            pixel.add(&self.sample(ray, world, integrator));
                         }
-                        color_sum / samples as f64
                     }
-                    AntiAliasingMethod::UniformSuperSampling(_samples) => Color3::ZERO,
+                    AntiAliasingMethod::UniformSuperSampling(_samples) => {}
                 };
 
-                write_buffer.push_str(&color_to_ppm(&pixel_color));
+                beauty.set(x as usize, y as usize, pixel.color());
+                aovs.store(x as usize, y as usize, &pixel);
             }
         }
 
-        let write_result = file.write_all(write_buffer.as_bytes());
-        if write_result.is_err() {
-            eprintln!("Failed to write to file");
-            eprintln!("{:?}", write_result.err());
+        // light paths that hit the film are spread over all camera samples
+        if let Some(light_image) = integrator.take_light_image() {
+            let scale = 1.0 / self.samples_per_pixel().max(1) as f64;
+            for y in 0..height {
+                for x in 0..width {
+                    beauty.set(x, y, beauty.get(x, y) + scale * light_image.get(x, y));
+                }
+            }
+        }
+
+        if let DenoiseMethod::ATrous(denoiser) = &self.denoise {
+            eprintln!("Denoising");
+            if let (Some(albedo), Some(normal)) =
+                (aovs.get(AovPass::Albedo), aovs.get(AovPass::Normal))
+            {
+                beauty = denoiser.denoise(&beauty, albedo, normal);
+            }
         }
+
+        self.display_image(&beauty, world.working_space)
+            .write_ppm(filename, self.output_color_space)?;
         println!("Written to: {}", filename);
 
-        Ok(())
+        aovs.write(filename)
+    }
+
+    // Working space radiance to the tone mapped and encoded output color space.
+    fn display_image(&self, beauty: &Framebuffer, working_space: ColorSpace) -> Framebuffer {
+        let output_space = self.output_color_space;
+        let conversion = working_space.conversion_matrix(output_space);
+
+        let mut display = Framebuffer::new(beauty.width, beauty.height);
+        for y in 0..beauty.height {
+            for x in 0..beauty.width {
+                let mapped = self.tone_mapper.map(&(conversion * beauty.get(x, y)));
+                let encoded = Color3::new(
+                    output_space.encode(mapped.x),
+                    output_space.encode(mapped.y),
+                    output_space.encode(mapped.z),
+                );
+                display.set(x, y, encoded);
+            }
+        }
+        display
+    }
+
+    fn sample(&self, mut ray: Ray, world: &World, integrator: &dyn Integrator) -> AovSample {
+        if let RenderingMode::Spectral = self.rendering_mode {
+            ray.wavelengths = Some(SampledWavelengths::sample_uniform(sampler::rng().gen()));
+        }
+
+        let mut radiance = integrator.radiance(&ray, world);
+        if let Some(wavelengths) = radiance.wavelengths(&ray) {
+            radiance = PathRadiance {
+                direct: wavelengths.to_rgb(&radiance.direct, world.working_space),
+                indirect: wavelengths.to_rgb(&radiance.indirect, world.working_space),
+                ..radiance
+            };
+        }
+
+        AovSample::new(radiance)
     }
 
     fn get_random_ray(&self, x: i32, y: i32) -> Ray {
-        let mut rng = rand::thread_rng();
+        let mut rng = sampler::rng();
         let offset_x = rng.gen_range(-0.5..0.5);
         let offset_y = rng.gen_range(-0.5..0.5);
         // println!("rand x: {}, rand y: {}", offset_x, offset_y);
@@ -171,7 +373,20 @@ impl Camera {
             + (x as f64 + offset_x) * self.pixel_delta_u
             + (y as f64 + offset_y) * self.pixel_delta_v;
 
-        Ray::new(ray_origin, pixel_pos - ray_origin)
+        self.ray_with_differentials(ray_origin, pixel_pos)
+    }
+
+    fn ray_with_differentials(&self, origin: Pos3, pixel_pos: Pos3) -> Ray {
+        let mut ray = Ray::new(origin, pixel_pos - origin);
+        // neighbouring rays are closer the more samples share a pixel
+        let spacing = 1.0 / (self.samples_per_pixel().max(1) as f64).sqrt();
+        ray.differentials = Some(RayDifferential {
+            rx_origin: origin,
+            rx_direction: pixel_pos + spacing * self.pixel_delta_u - origin,
+            ry_origin: origin,
+            ry_direction: pixel_pos + spacing * self.pixel_delta_v - origin,
+        });
+        ray
     }
 
     fn defocus_disk_sample(&self) -> Pos3 {
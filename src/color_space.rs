@@ -0,0 +1,160 @@
+use crate::{
+    utils::{
+        helpers::{linear_to_srgb, srgb_to_linear},
+        matrix::Mat3,
+    },
+    vec3::{Color3, Vec3},
+};
+
+// RGB color spaces the renderer can work in and write to. All values inside
+// the renderer are linear, the transfer functions only apply to 8-bit files.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ColorSpace {
+    // Rec. 709 primaries with a D65 white point
+    Srgb,
+    // ACES AP1 primaries with the ACES (~D60) white point
+    AcesCg,
+    DisplayP3,
+}
+
+// CIE xy chromaticities
+const D65: (f64, f64) = (0.3127, 0.3290);
+const ACES_WHITE: (f64, f64) = (0.32168, 0.33767);
+
+// Bradford cone response, used to adapt between white points
+// http://www.brucelindbloom.com/index.html?Eqn_ChromAdapt.html
+const BRADFORD: Mat3 = Mat3::new([
+    [0.8951, 0.2664, -0.1614],
+    [-0.7502, 1.7135, 0.0367],
+    [0.0389, -0.0685, 1.0296],
+]);
+
+impl ColorSpace {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorSpace::Srgb => "sRGB",
+            ColorSpace::AcesCg => "ACEScg",
+            ColorSpace::DisplayP3 => "Display P3",
+        }
+    }
+
+    // red, green, blue and white point chromaticities
+    fn chromaticities(&self) -> [(f64, f64); 4] {
+        match self {
+            ColorSpace::Srgb => [(0.64, 0.33), (0.30, 0.60), (0.15, 0.06), D65],
+            ColorSpace::AcesCg => [(0.713, 0.293), (0.165, 0.830), (0.128, 0.044), ACES_WHITE],
+            ColorSpace::DisplayP3 => [(0.680, 0.320), (0.265, 0.690), (0.150, 0.060), D65],
+        }
+    }
+
+    // XYZ of the white point, normalized to Y = 1
+    pub fn white_point(&self) -> Vec3 {
+        xy_to_xyz(self.chromaticities()[3])
+    }
+
+    pub fn to_xyz(&self) -> Mat3 {
+        let [r, g, b, white] = self.chromaticities();
+        let primaries = Mat3::from_columns(&xy_to_xyz(r), &xy_to_xyz(g), &xy_to_xyz(b));
+        // scale the primaries so that RGB (1, 1, 1) lands on the white point
+        let scale = primaries.inverse() * xy_to_xyz(white);
+        primaries * Mat3::diagonal(&scale)
+    }
+
+    pub fn from_xyz(&self) -> Mat3 {
+        self.to_xyz().inverse()
+    }
+
+    pub fn conversion_matrix(&self, to: ColorSpace) -> Mat3 {
+        if *self == to {
+            return Mat3::IDENTITY;
+        }
+        let adaptation = chromatic_adaptation(&self.white_point(), &to.white_point());
+        to.from_xyz() * adaptation * self.to_xyz()
+    }
+
+    pub fn convert(&self, color: &Color3, to: ColorSpace) -> Color3 {
+        if *self == to {
+            return *color;
+        }
+        &self.conversion_matrix(to) * color
+    }
+
+    // Relative luminance (CIE Y) of a linear color in this space.
+    pub fn luminance(&self, color: &Color3) -> f64 {
+        Vec3::dot(&self.to_xyz().row(1), color)
+    }
+
+    // Transfer function used when storing the color space in 8-bit files.
+    pub fn encode(&self, value: f64) -> f64 {
+        match self {
+            ColorSpace::Srgb | ColorSpace::DisplayP3 => linear_to_srgb(value),
+            ColorSpace::AcesCg => value,
+        }
+    }
+
+    pub fn decode(&self, value: f64) -> f64 {
+        match self {
+            ColorSpace::Srgb | ColorSpace::DisplayP3 => srgb_to_linear(value),
+            ColorSpace::AcesCg => value,
+        }
+    }
+}
+
+// Bradford transform of XYZ values seen under `source_white` to `target_white`.
+pub fn chromatic_adaptation(source_white: &Vec3, target_white: &Vec3) -> Mat3 {
+    let source_cone = BRADFORD * *source_white;
+    let target_cone = BRADFORD * *target_white;
+    BRADFORD.inverse() * Mat3::diagonal(&(target_cone / source_cone)) * BRADFORD
+}
+
+fn xy_to_xyz((x, y): (f64, f64)) -> Vec3 {
+    Vec3::new(x / y, 1.0, (1.0 - x - y) / y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPACES: [ColorSpace; 3] = [ColorSpace::Srgb, ColorSpace::AcesCg, ColorSpace::DisplayP3];
+
+    fn assert_near(a: &Vec3, b: &Vec3) {
+        assert!((a - b).length() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn srgb_to_xyz() {
+        // reference matrix from http://www.brucelindbloom.com/index.html?Eqn_RGB_XYZ_Matrix.html
+        let red = ColorSpace::Srgb.to_xyz() * Color3::RED;
+        assert_near(&red, &Vec3::new(0.4124564, 0.2126729, 0.0193339));
+    }
+
+    #[test]
+    fn white_is_preserved() {
+        for from in SPACES {
+            for to in SPACES {
+                assert_near(&from.convert(&Color3::WHITE, to), &Color3::WHITE);
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let color = Color3::new(0.2, 0.5, 0.9);
+        for from in SPACES {
+            for to in SPACES {
+                assert_near(&to.convert(&from.convert(&color, to), from), &color);
+            }
+        }
+    }
+
+    #[test]
+    fn wide_gamut_contains_srgb() {
+        // sRGB primaries sit inside the P3 and AP1 gamuts
+        for to in [ColorSpace::AcesCg, ColorSpace::DisplayP3] {
+            for primary in [Color3::RED, Color3::GREEN, Color3::BLUE] {
+                let converted = ColorSpace::Srgb.convert(&primary, to);
+                assert!(converted.min(&Vec3::ZERO).length() < 1e-9);
+            }
+        }
+    }
+}
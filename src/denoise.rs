@@ -0,0 +1,180 @@
+use crate::{
+    film::Framebuffer,
+    vec3::{Color3, Vec3},
+};
+
+pub enum DenoiseMethod {
+    None,
+    ATrous(ATrousDenoiser),
+}
+
+// Edge-avoiding À-Trous wavelet filter guided by the albedo and normal feature buffers.
+// https://jo.dreggn.org/home/2010_atrous.pdf
+pub struct ATrousDenoiser {
+    pub iterations: u32,
+    pub color_sigma: f64,
+    pub normal_sigma: f64,
+    pub albedo_sigma: f64,
+}
+
+// B3 spline, the filter is separable but applied as a full 5x5 kernel to keep the edge weights exact
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+impl Default for ATrousDenoiser {
+    fn default() -> Self {
+        ATrousDenoiser {
+            iterations: 5,
+            color_sigma: 0.5,
+            normal_sigma: 0.3,
+            albedo_sigma: 0.1,
+        }
+    }
+}
+
+impl ATrousDenoiser {
+    pub fn denoise(
+        &self,
+        color: &Framebuffer,
+        albedo: &Framebuffer,
+        normal: &Framebuffer,
+    ) -> Framebuffer {
+        // filter the lighting only, texture detail is restored from the albedo afterwards
+        let demodulation = Self::demodulation(albedo);
+        let mut filtered = Framebuffer::new(color.width, color.height);
+        for (i, (pixel, albedo)) in color.pixels().iter().zip(&demodulation).enumerate() {
+            filtered.set(i % color.width, i / color.width, pixel / albedo);
+        }
+
+        for iteration in 0..self.iterations {
+            filtered = self.filter_pass(&filtered, albedo, normal, iteration);
+        }
+
+        for (i, albedo) in demodulation.iter().enumerate() {
+            let (x, y) = (i % color.width, i / color.width);
+            filtered.set(x, y, filtered.get(x, y) * albedo);
+        }
+        filtered
+    }
+
+    fn demodulation(albedo: &Framebuffer) -> Vec<Color3> {
+        // background and black surfaces have nothing to divide out
+        let demodulate = |channel: f64| if channel < 0.001 { 1.0 } else { channel };
+        albedo
+            .pixels()
+            .iter()
+            .map(|a| Color3::new(demodulate(a.x), demodulate(a.y), demodulate(a.z)))
+            .collect()
+    }
+
+    fn filter_pass(
+        &self,
+        color: &Framebuffer,
+        albedo: &Framebuffer,
+        normal: &Framebuffer,
+        iteration: u32,
+    ) -> Framebuffer {
+        let step = 1_i64 << iteration;
+        // the color edge stopping function gets tighter as the image smooths out
+        let color_sigma = self.color_sigma * self.color_sigma / (1 << iteration) as f64;
+        let normal_sigma = self.normal_sigma * self.normal_sigma;
+        let albedo_sigma = self.albedo_sigma * self.albedo_sigma;
+
+        let mut output = Framebuffer::new(color.width, color.height);
+        for y in 0..color.height {
+            for x in 0..color.width {
+                let center_color = color.get(x, y);
+                let center_normal = normal.get(x, y);
+                let center_albedo = albedo.get(x, y);
+
+                let mut color_sum = Color3::ZERO;
+                let mut weight_sum = 0.0;
+                for (j, ky) in KERNEL.iter().enumerate() {
+                    for (i, kx) in KERNEL.iter().enumerate() {
+                        let qx = x as i64 + (i as i64 - 2) * step;
+                        let qy = y as i64 + (j as i64 - 2) * step;
+                        if qx < 0 || qy < 0 || qx >= color.width as i64 || qy >= color.height as i64
+                        {
+                            continue;
+                        }
+                        let (qx, qy) = (qx as usize, qy as usize);
+
+                        let sample = color.get(qx, qy);
+                        let color_weight = edge_weight(&center_color, &sample, color_sigma);
+                        let normal_weight =
+                            edge_weight(&center_normal, &normal.get(qx, qy), normal_sigma);
+                        let albedo_weight =
+                            edge_weight(&center_albedo, &albedo.get(qx, qy), albedo_sigma);
+
+                        let weight = kx * ky * color_weight * normal_weight * albedo_weight;
+                        color_sum += weight * sample;
+                        weight_sum += weight;
+                    }
+                }
+
+                // the center tap always has a weight of at least kernel[2]^2
+                output.set(x, y, color_sum / weight_sum);
+            }
+        }
+        output
+    }
+}
+
+fn edge_weight(a: &Vec3, b: &Vec3, sigma_squared: f64) -> f64 {
+    (-(a - b).length_squared() / sigma_squared).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variance(framebuffer: &Framebuffer) -> f64 {
+        let pixels = framebuffer.pixels();
+        let mean = pixels.iter().map(|p| p.x).sum::<f64>() / pixels.len() as f64;
+        pixels.iter().map(|p| (p.x - mean).powi(2)).sum::<f64>() / pixels.len() as f64
+    }
+
+    #[test]
+    fn removes_noise() {
+        let (width, height) = (32, 32);
+        let mut color = Framebuffer::new(width, height);
+        let mut albedo = Framebuffer::new(width, height);
+        let mut normal = Framebuffer::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                // deterministic "noise" around 0.5
+                let noise = if (x * 7 + y * 13) % 3 == 0 { 0.2 } else { -0.1 };
+                color.set(x, y, Color3::from_float(0.5 + noise));
+                albedo.set(x, y, Color3::WHITE);
+                normal.set(x, y, Vec3::new(0.0, 1.0, 0.0));
+            }
+        }
+
+        let denoised = ATrousDenoiser::default().denoise(&color, &albedo, &normal);
+        assert!(variance(&denoised) < 0.1 * variance(&color));
+    }
+
+    #[test]
+    fn keeps_geometry_edges() {
+        let (width, height) = (16, 16);
+        let mut color = Framebuffer::new(width, height);
+        let mut albedo = Framebuffer::new(width, height);
+        let mut normal = Framebuffer::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let left = x < width / 2;
+                color.set(x, y, Color3::from_float(if left { 0.1 } else { 0.9 }));
+                albedo.set(x, y, Color3::WHITE);
+                let n = if left {
+                    Vec3::new(1.0, 0.0, 0.0)
+                } else {
+                    Vec3::new(0.0, 1.0, 0.0)
+                };
+                normal.set(x, y, n);
+            }
+        }
+
+        let denoised = ATrousDenoiser::default().denoise(&color, &albedo, &normal);
+        assert!((denoised.get(width / 2 - 1, 8).x - 0.1).abs() < 0.01);
+        assert!((denoised.get(width / 2, 8).x - 0.9).abs() < 0.01);
+    }
+}
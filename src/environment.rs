@@ -0,0 +1,85 @@
+use std::{f64::consts::PI, io::Error};
+
+use crate::{
+    color_space::ColorSpace,
+    ray::Ray,
+    sky::Sky,
+    texture::ImageTexture,
+    utils::helpers::random_in_unit_sphere_normalized,
+    vec3::{Color3, Vec3},
+};
+
+// What a ray sees when it leaves the scene.
+pub enum Environment {
+    // blend from white at the nadir to the color at the zenith, in the working space
+    Gradient(Color3),
+    // equirectangular (latitude-longitude) image
+    Map(ImageTexture),
+    // physically based daylight with a sun
+    Sky(Box<Sky>),
+}
+
+impl Environment {
+    // the default light blue sky
+    pub fn gradient(working_space: ColorSpace) -> Self {
+        Environment::Gradient(ColorSpace::Srgb.convert(&Color3::new(0.5, 0.7, 1.0), working_space))
+    }
+
+    pub fn load_map(
+        filename: &str,
+        source_space: ColorSpace,
+        working_space: ColorSpace,
+    ) -> Result<Self, Error> {
+        Ok(Environment::Map(ImageTexture::load(
+            filename,
+            source_space,
+            working_space,
+        )?))
+    }
+
+    pub fn radiance(&self, ray: &Ray) -> Color3 {
+        let dir_normalized = ray.dir.normalize();
+        match self {
+            Environment::Gradient(zenith) => {
+                let y_ratio = 0.5 * (dir_normalized.y + 1.0); // move normalized y-axis from [-1, 1] to [0, 2] and multiply with .5 for [0, 1]
+                (1.0 - y_ratio) * Color3::WHITE + y_ratio * *zenith
+            }
+            Environment::Map(texture) => {
+                let u = 0.5 + dir_normalized.x.atan2(-dir_normalized.z) / (2.0 * PI);
+                let v = 1.0 - dir_normalized.y.clamp(-1.0, 1.0).acos() / PI;
+                texture.sample(u, v)
+            }
+            Environment::Sky(sky) => sky.radiance(&dir_normalized),
+        }
+    }
+
+    // Direction (normalized) towards the environment for light sampling and its solid angle
+    // pdf, uniform over the sphere unless the environment can be importance sampled.
+    pub fn sample_direction(&self) -> (Vec3, f64) {
+        match self {
+            Environment::Sky(sky) => sky.sample_direction(),
+            _ => (random_in_unit_sphere_normalized(), 1.0 / (4.0 * PI)),
+        }
+    }
+
+    pub fn pdf(&self, direction: &Vec3) -> f64 {
+        match self {
+            Environment::Sky(sky) => sky.pdf(&direction.normalize()),
+            _ => 1.0 / (4.0 * PI),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::Pos3;
+
+    #[test]
+    fn gradient_in_working_space() {
+        let up = Ray::new(Pos3::ZERO, Vec3::new(0.0, 1.0, 0.0));
+        let zenith = Environment::gradient(ColorSpace::AcesCg).radiance(&up);
+        let srgb = ColorSpace::AcesCg.convert(&zenith, ColorSpace::Srgb);
+        assert!((srgb - Color3::new(0.5, 0.7, 1.0)).length() < 1e-9);
+    }
+}
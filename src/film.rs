@@ -0,0 +1,268 @@
+use std::{
+    fs::OpenOptions,
+    io::{Error, ErrorKind, Write},
+};
+
+use crate::{color_space::ColorSpace, utils::helpers::color_to_ppm, vec3::Color3};
+
+// Linear (unclamped) image storage the camera renders into, before anything
+// is quantized to an output format.
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<Color3>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![Color3::ZERO; width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Color3 {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, color: Color3) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    pub fn pixels(&self) -> &[Color3] {
+        &self.pixels
+    }
+
+    // Expects display encoded values, the color space is stored as a header comment.
+    pub fn write_ppm(&self, filename: &str, color_space: ColorSpace) -> Result<(), Error> {
+        let mut write_buffer = format!(
+            "P3\n# colorspace: {}\n{} {}\n255\n",
+            color_space.name(),
+            self.width,
+            self.height
+        );
+        for pixel in &self.pixels {
+            write_buffer.push_str(&color_to_ppm(pixel));
+        }
+
+        write_file(filename, write_buffer.as_bytes())
+    }
+
+    // Portable float map: keeps the full linear range, which the auxiliary
+    // passes (depth, normals, ids) need.
+    // http://www.pauldebevec.com/Research/HDR/PFM/
+    pub fn write_pfm(&self, filename: &str) -> Result<(), Error> {
+        let mut write_buffer = format!("PF\n{} {}\n-1.0\n", self.width, self.height).into_bytes();
+
+        // scan-lines are stored bottom to top
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let pixel = self.get(x, y);
+                for channel in [pixel.x, pixel.y, pixel.z] {
+                    write_buffer.extend_from_slice(&(channel as f32).to_le_bytes());
+                }
+            }
+        }
+
+        write_file(filename, &write_buffer)
+    }
+
+    // Reads ASCII (P3) and binary (P6) PPM files, values are scaled to [0, 1] but stay encoded.
+    pub fn read_ppm(filename: &str) -> Result<Framebuffer, Error> {
+        let bytes = std::fs::read(filename)?;
+        let mut reader = HeaderReader {
+            bytes: &bytes,
+            pos: 0,
+        };
+
+        let magic = reader.token()?;
+        let width = reader.number()?;
+        let height = reader.number()?;
+        let max_value = reader.number()? as f64;
+        let mut framebuffer = Framebuffer::new(width, height);
+
+        match magic.as_str() {
+            "P3" => {
+                for pixel in framebuffer.pixels.iter_mut() {
+                    for channel in 0..3 {
+                        pixel[channel] = reader.number()? as f64 / max_value;
+                    }
+                }
+            }
+            "P6" => {
+                // a single whitespace separates the header from the raster
+                let raster = bytes.get(reader.pos + 1..).unwrap_or_default();
+                let bytes_per_channel = if max_value > 255.0 { 2 } else { 1 };
+                if raster.len() < width * height * 3 * bytes_per_channel {
+                    return Err(invalid_data("truncated PPM raster"));
+                }
+                for (i, pixel) in framebuffer.pixels.iter_mut().enumerate() {
+                    for channel in 0..3 {
+                        let offset = (i * 3 + channel) * bytes_per_channel;
+                        let value = if bytes_per_channel == 2 {
+                            u16::from_be_bytes([raster[offset], raster[offset + 1]]) as f64
+                        } else {
+                            raster[offset] as f64
+                        };
+                        pixel[channel] = value / max_value;
+                    }
+                }
+            }
+            _ => return Err(invalid_data("not a PPM file")),
+        }
+
+        Ok(framebuffer)
+    }
+
+    // Reads color (PF) and grayscale (Pf) portable float maps.
+    pub fn read_pfm(filename: &str) -> Result<Framebuffer, Error> {
+        let bytes = std::fs::read(filename)?;
+        let mut reader = HeaderReader {
+            bytes: &bytes,
+            pos: 0,
+        };
+
+        let channels = match reader.token()?.as_str() {
+            "PF" => 3,
+            "Pf" => 1,
+            _ => return Err(invalid_data("not a PFM file")),
+        };
+        let width = reader.number()?;
+        let height = reader.number()?;
+        let little_endian = reader
+            .token()?
+            .parse::<f64>()
+            .map_err(|_| invalid_data("invalid PFM scale"))?
+            < 0.0;
+
+        let raster = bytes.get(reader.pos + 1..).unwrap_or_default();
+        if raster.len() < width * height * channels * 4 {
+            return Err(invalid_data("truncated PFM raster"));
+        }
+        let read_float = |index: usize| {
+            let b: [u8; 4] = raster[index * 4..index * 4 + 4].try_into().unwrap();
+            let value = if little_endian {
+                f32::from_le_bytes(b)
+            } else {
+                f32::from_be_bytes(b)
+            };
+            value as f64
+        };
+
+        let mut framebuffer = Framebuffer::new(width, height);
+        for y in 0..height {
+            // scan-lines are stored bottom to top
+            let row = height - 1 - y;
+            for x in 0..width {
+                let index = (row * width + x) * channels;
+                let color = if channels == 3 {
+                    Color3::new(
+                        read_float(index),
+                        read_float(index + 1),
+                        read_float(index + 2),
+                    )
+                } else {
+                    Color3::from_float(read_float(index))
+                };
+                framebuffer.set(x, y, color);
+            }
+        }
+
+        Ok(framebuffer)
+    }
+}
+
+// Whitespace separated tokens of a netpbm header, skipping # comments.
+struct HeaderReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl HeaderReader<'_> {
+    fn token(&mut self) -> Result<String, Error> {
+        loop {
+            match self.bytes.get(self.pos) {
+                Some(b'#') => {
+                    while self.bytes.get(self.pos).is_some_and(|b| *b != b'\n') {
+                        self.pos += 1;
+                    }
+                }
+                Some(b) if b.is_ascii_whitespace() => self.pos += 1,
+                Some(_) => break,
+                None => return Err(invalid_data("unexpected end of file")),
+            }
+        }
+        let start = self.pos;
+        while self
+            .bytes
+            .get(self.pos)
+            .is_some_and(|b| !b.is_ascii_whitespace())
+        {
+            self.pos += 1;
+        }
+        Ok(String::from_utf8_lossy(&self.bytes[start..self.pos]).into_owned())
+    }
+
+    fn number(&mut self) -> Result<usize, Error> {
+        self.token()?
+            .parse()
+            .map_err(|_| invalid_data("invalid number in header"))
+    }
+}
+
+fn invalid_data(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+fn write_file(filename: &str, bytes: &[u8]) -> Result<(), Error> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(filename)?;
+
+    file.write_all(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_pfm() {
+        let mut framebuffer = Framebuffer::new(2, 1);
+        framebuffer.set(1, 0, Color3::new(1.0, 2.0, 3.0));
+
+        let path = std::env::temp_dir().join("rust_raytracing_write_pfm.pfm");
+        let filename = path.to_str().unwrap();
+        framebuffer.write_pfm(filename).unwrap();
+
+        let bytes = std::fs::read(filename).unwrap();
+        let header = b"PF\n2 1\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+        assert_eq!(bytes.len(), header.len() + 2 * 3 * 4);
+
+        let last = &bytes[bytes.len() - 4..];
+        assert_eq!(f32::from_le_bytes(last.try_into().unwrap()), 3.0);
+    }
+
+    #[test]
+    fn read_images() {
+        let mut framebuffer = Framebuffer::new(2, 2);
+        framebuffer.set(0, 0, Color3::new(0.25, 8.0, -1.0));
+        framebuffer.set(1, 1, Color3::new(1.0, 0.5, 0.0));
+
+        let path = std::env::temp_dir().join("rust_raytracing_read_images.pfm");
+        let filename = path.to_str().unwrap();
+        framebuffer.write_pfm(filename).unwrap();
+        let pfm = Framebuffer::read_pfm(filename).unwrap();
+        assert_eq!(pfm.pixels(), framebuffer.pixels());
+
+        let path = std::env::temp_dir().join("rust_raytracing_read_images.ppm");
+        let filename = path.to_str().unwrap();
+        framebuffer.write_ppm(filename, ColorSpace::Srgb).unwrap();
+        let ppm = Framebuffer::read_ppm(filename).unwrap();
+        assert_eq!(ppm.get(1, 1), Color3::new(1.0, 128.0 / 255.0, 0.0));
+    }
+}
@@ -0,0 +1,45 @@
+use super::{Integrator, PathRadiance};
+use crate::{
+    aov::FirstHit,
+    ray::Ray,
+    utils::{helpers::random_in_unit_sphere_normalized, interval::Interval},
+    vec3::Color3,
+    world::World,
+};
+
+// Fraction of the hemisphere above the first hit that is not blocked within `radius`,
+// cosine weighted. Rays that leave the scene are white.
+pub struct AmbientOcclusion {
+    pub radius: f64,
+}
+
+impl AmbientOcclusion {
+    pub fn new(radius: f64) -> Self {
+        Self { radius }
+    }
+}
+
+impl Integrator for AmbientOcclusion {
+    fn radiance(&self, ray: &Ray, world: &World) -> PathRadiance {
+        let mut radiance = PathRadiance::ZERO;
+        let white = ray.spectral_color(&Color3::WHITE, world);
+        let Some(hit) = world.hit_objects(ray, &Interval::new(0.0001, f64::MAX)) else {
+            radiance.add(0, white);
+            return radiance;
+        };
+        radiance.first_hit = Some(FirstHit::new(&hit));
+
+        let mut direction = hit.normal + random_in_unit_sphere_normalized();
+        if direction.near_zero() {
+            direction = hit.normal;
+        }
+        let occlusion_ray = ray.scattered(hit.point, direction.normalize());
+        if world
+            .hit_objects(&occlusion_ray, &Interval::new(0.0001, self.radius))
+            .is_none()
+        {
+            radiance.add(1, white);
+        }
+        radiance
+    }
+}
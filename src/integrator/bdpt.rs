@@ -0,0 +1,737 @@
+use std::{cell::RefCell, f64::consts::PI};
+
+use rand::Rng;
+
+use super::{sample_lights, Integrator, PathRadiance};
+use crate::{
+    aov::FirstHit,
+    camera::{Camera, CameraProjection},
+    film::Framebuffer,
+    objects::object::HitRecord,
+    ray::Ray,
+    sampler,
+    utils::{helpers::random_in_unit_disk, interval::Interval, onb::Onb},
+    vec3::{Color3, Pos3, Vec3},
+    world::World,
+};
+
+// Bidirectional path tracer: every prefix of a camera subpath is connected to every prefix
+// of a light subpath, and the strategies are weighted with the balance heuristic.
+// Light subpaths start on emissive objects or on a disk in front of the scene bounds for
+// the environment. Connections straight to the camera land on other pixels, they are
+// collected in the light image. Point, spot and directional lights are only reached by
+// shadow rays from the camera subpath.
+// https://pbr-book.org/3ed-2018/Light_Transport_III_Bidirectional_Methods/Bidirectional_Path_Tracing
+pub struct BidirectionalPathTracer {
+    pub max_depth: u16,
+    camera: CameraProjection,
+    light_image: RefCell<Framebuffer>,
+}
+
+enum VertexKind {
+    Camera,
+    // point on an emissive object a light subpath starts from (or a shadow ray ends at)
+    Light(HitRecord),
+    Surface(HitRecord),
+    // light from the environment, the direction points away from the scene
+    Environment(Vec3),
+}
+
+struct Vertex {
+    kind: VertexKind,
+    point: Pos3,
+    // ray that arrived at the vertex along its subpath
+    incoming: Ray,
+    // throughput of the subpath up to and including the vertex
+    beta: Color3,
+    // densities of sampling the vertex along its subpath and in the opposite direction,
+    // per area (per solid angle for the environment)
+    pdf_fwd: f64,
+    pdf_rev: f64,
+    delta: bool,
+}
+
+impl Vertex {
+    fn new(kind: VertexKind, point: Pos3, incoming: Ray, beta: Color3, pdf_fwd: f64) -> Self {
+        Vertex {
+            kind,
+            point,
+            incoming,
+            beta,
+            pdf_fwd,
+            pdf_rev: 0.0,
+            delta: false,
+        }
+    }
+
+    fn normal(&self) -> Option<Vec3> {
+        match &self.kind {
+            // scattering inside a medium has no cosine
+            VertexKind::Surface(hit) if hit.object.as_ref().is_some_and(|o| o.is_medium()) => None,
+            VertexKind::Light(hit) | VertexKind::Surface(hit) => Some(hit.normal),
+            _ => None,
+        }
+    }
+
+    fn is_light(&self) -> bool {
+        match &self.kind {
+            VertexKind::Light(_) | VertexKind::Environment(_) => true,
+            VertexKind::Surface(hit) => hit.material.is_emissive(),
+            VertexKind::Camera => false,
+        }
+    }
+
+    fn is_connectible(&self) -> bool {
+        match &self.kind {
+            VertexKind::Surface(hit) => !hit.material.is_specular(),
+            _ => true,
+        }
+    }
+
+    fn direction_to(&self, other: &Vertex) -> Vec3 {
+        match (&self.kind, &other.kind) {
+            (_, VertexKind::Environment(direction)) => *direction,
+            (VertexKind::Environment(direction), _) => -*direction,
+            _ => (other.point - self.point).normalize(),
+        }
+    }
+
+    // Ray arriving at this vertex from `prev`.
+    fn ray_from(&self, prev: &Vertex) -> Ray {
+        Ray {
+            pos: prev.point,
+            dir: -self.direction_to(prev),
+            ..self.incoming.clone()
+        }
+    }
+
+    // Solid angle density at this vertex to area density at `next`.
+    fn convert_density(&self, pdf: f64, next: &Vertex) -> f64 {
+        if let VertexKind::Environment(_) = next.kind {
+            return pdf;
+        }
+        let to_next = next.point - self.point;
+        let distance_sq = to_next.length_squared();
+        let cos_theta = next.normal().map_or(1.0, |normal| {
+            Vec3::dot(&normal, &(to_next / distance_sq.sqrt())).abs()
+        });
+        pdf * cos_theta / distance_sq
+    }
+
+    // BSDF times cosine at this vertex, for light going between the subpath and `next`.
+    fn scattering(&self, next: &Vertex, world: &World) -> Color3 {
+        let VertexKind::Surface(hit) = &self.kind else {
+            return Color3::BLACK;
+        };
+        let f = hit
+            .material
+            .evaluate(&self.incoming, hit, &self.direction_to(next));
+        self.incoming.spectral_color(&f, world)
+    }
+
+    // Light leaving this vertex towards `next`.
+    fn emitted(&self, next: &Vertex, world: &World) -> Color3 {
+        let color = match &self.kind {
+            VertexKind::Environment(direction) => world.environment.radiance(&Ray {
+                pos: next.point,
+                dir: *direction,
+                ..self.incoming.clone()
+            }),
+            VertexKind::Light(hit) | VertexKind::Surface(hit) => {
+                hit.material.emitted(&self.ray_from(next), hit)
+            }
+            VertexKind::Camera => Color3::BLACK,
+        };
+        self.incoming.spectral_color(&color, world)
+    }
+
+    // Area density of this vertex, reached from `prev`, sampling `next`.
+    fn pdf(
+        &self,
+        prev: Option<&Vertex>,
+        next: &Vertex,
+        world: &World,
+        camera: &CameraProjection,
+    ) -> f64 {
+        let pdf = match &self.kind {
+            VertexKind::Light(_) | VertexKind::Environment(_) => {
+                return self.pdf_light(next, world)
+            }
+            VertexKind::Camera => {
+                camera.pdf_direction(&self.point, &(self.point + self.direction_to(next)))
+            }
+            VertexKind::Surface(hit) => {
+                if hit.material.is_specular() {
+                    0.0
+                } else {
+                    let ray =
+                        prev.map_or_else(|| self.incoming.clone(), |prev| self.ray_from(prev));
+                    hit.material.pdf(&ray, hit, &self.direction_to(next))
+                }
+            }
+        };
+        self.convert_density(pdf, next)
+    }
+
+    // Area density of the light at this vertex emitting towards `next`.
+    fn pdf_light(&self, next: &Vertex, world: &World) -> f64 {
+        let direction = self.direction_to(next);
+        let pdf = match &self.kind {
+            // the position on the disk decides where the light arrives
+            VertexKind::Environment(_) => match scene_sphere(world) {
+                Some((_, radius)) => 1.0 / (PI * radius * radius),
+                None => 0.0,
+            },
+            VertexKind::Light(hit) | VertexKind::Surface(hit) => {
+                let cos_theta = Vec3::dot(&hit.normal, &direction).max(0.0);
+                cos_theta / (PI * (next.point - self.point).length_squared())
+            }
+            VertexKind::Camera => 0.0,
+        };
+        pdf * next
+            .normal()
+            .map_or(1.0, |normal| Vec3::dot(&normal, &direction).abs())
+    }
+
+    // Density of a light subpath starting at this vertex.
+    fn pdf_light_origin(&self, world: &World) -> f64 {
+        match &self.kind {
+            VertexKind::Environment(direction) => {
+                light_choice_pdf(world, None) * world.environment.pdf(direction)
+            }
+            VertexKind::Light(hit) | VertexKind::Surface(hit) => {
+                light_choice_pdf(world, Some(hit.object_id)) / world.objects[hit.object_id].area()
+            }
+            VertexKind::Camera => 0.0,
+        }
+    }
+}
+
+// Probability of picking the emitter (None for the environment). The environment counts
+// as one of the lights, the emitters share the rest by their power.
+fn light_choice_pdf(world: &World, object_id: Option<usize>) -> f64 {
+    let environment = 1.0 / (world.emitters().len() + 1) as f64;
+    match object_id {
+        Some(object_id) => (1.0 - environment) * world.light_tree().power_probability(object_id),
+        None => environment,
+    }
+}
+
+// Emitter (None for the environment) a light subpath or shadow ray starts from, with the
+// probability of the choice.
+fn choose_light(world: &World) -> (Option<usize>, f64) {
+    let (u1, u2) = {
+        let mut rng = sampler::rng();
+        (rng.gen::<f64>(), rng.gen())
+    };
+    let environment = light_choice_pdf(world, None);
+    match world.light_tree().sample_power(u2) {
+        Some((object_id, probability)) if u1 >= environment => {
+            (Some(object_id), (1.0 - environment) * probability)
+        }
+        _ => (None, environment),
+    }
+}
+
+// Sphere around the bounded objects of the scene.
+fn scene_sphere(world: &World) -> Option<(Pos3, f64)> {
+    let bounds = world.bounds()?;
+    Some((bounds.centroid(), 0.5 * bounds.extent().length()))
+}
+
+fn visible(world: &World, a: &Vertex, b: &Vertex) -> bool {
+    let ray = a.incoming.scattered(a.point, b.point - a.point);
+    world
+        .hit_objects(&ray, &Interval::new(0.0001, 1.0 - 0.0001))
+        .is_none()
+}
+
+// Contribution of one strategy, before its MIS weight.
+struct Connection {
+    contribution: Color3,
+    // endpoint sampled for the connection (light for s = 1, camera for t = 1)
+    sampled: Option<Vertex>,
+    pixel: Option<(usize, usize)>,
+}
+
+impl BidirectionalPathTracer {
+    pub fn new(camera: &Camera, max_depth: u16) -> Self {
+        let camera = camera.projection();
+        let light_image = RefCell::new(Framebuffer::new(camera.width, camera.height));
+        Self {
+            max_depth,
+            camera,
+            light_image,
+        }
+    }
+
+    fn camera_subpath(&self, ray: &Ray, world: &World) -> Vec<Vertex> {
+        let max_vertices = self.max_depth as usize + 2;
+        let mut path = Vec::with_capacity(max_vertices);
+        path.push(Vertex::new(
+            VertexKind::Camera,
+            ray.pos,
+            ray.clone(),
+            Color3::WHITE,
+            0.0,
+        ));
+        let pdf_direction = self.camera.pdf_direction(&ray.pos, &ray.cast(1.0));
+        self.random_walk(
+            ray.clone(),
+            Color3::WHITE,
+            pdf_direction,
+            max_vertices,
+            true,
+            world,
+            &mut path,
+        );
+        path
+    }
+
+    // Light subpaths carry the wavelengths of the camera ray.
+    fn light_subpath(&self, camera_ray: &Ray, world: &World) -> Vec<Vertex> {
+        let max_vertices = self.max_depth as usize + 1;
+        let mut path = Vec::with_capacity(max_vertices);
+        let (choice, light_pdf) = choose_light(world);
+
+        if let Some(object_id) = choice {
+            let object = &world.objects[object_id];
+            let Some(mut hit) = object.sample_surface() else {
+                return path;
+            };
+            hit.object_id = object_id;
+            hit.object = Some(object.clone());
+
+            // cosine weighted emission around the normal
+            let disk = random_in_unit_disk();
+            let cos_theta = (1.0 - disk.length_squared()).max(0.0).sqrt();
+            let direction =
+                Onb::from_w(&hit.normal).to_world(&Vec3::new(disk.x, disk.y, cos_theta));
+            let pdf_position = 1.0 / object.area();
+            let pdf_direction = cos_theta / PI;
+
+            let ray = camera_ray.scattered(hit.point, direction);
+            let towards_light = camera_ray.scattered(hit.point + direction, -direction);
+            let emitted = ray.spectral_color(&hit.material.emitted(&towards_light, &hit), world);
+            let beta = emitted * (cos_theta / (light_pdf * pdf_position * pdf_direction));
+
+            let point = hit.point;
+            path.push(Vertex::new(
+                VertexKind::Light(hit),
+                point,
+                ray.clone(),
+                emitted,
+                pdf_position * light_pdf,
+            ));
+            self.random_walk(
+                ray,
+                beta,
+                pdf_direction,
+                max_vertices,
+                false,
+                world,
+                &mut path,
+            );
+        } else {
+            let Some((center, radius)) = scene_sphere(world) else {
+                return path;
+            };
+            // towards the environment, the light enters the scene through a disk facing it
+            let (direction, pdf_direction) = world.environment.sample_direction();
+            let frame = Onb::from_w(&direction);
+            let disk = random_in_unit_disk();
+            let origin = center + radius * (direction + disk.x * frame.u + disk.y * frame.v);
+            let pdf_position = 1.0 / (PI * radius * radius);
+
+            let ray = camera_ray.scattered(origin, -direction);
+            let sky = world
+                .environment
+                .radiance(&camera_ray.scattered(center, direction));
+            let emitted = ray.spectral_color(&sky, world);
+            let beta = emitted / (light_pdf * pdf_position * pdf_direction);
+
+            path.push(Vertex::new(
+                VertexKind::Environment(direction),
+                origin,
+                ray.clone(),
+                emitted,
+                light_pdf * pdf_direction,
+            ));
+            self.random_walk(
+                ray.clone(),
+                beta,
+                pdf_direction,
+                max_vertices,
+                false,
+                world,
+                &mut path,
+            );
+            // the first hit was sampled by its position on the disk
+            if let Some(first) = path.get_mut(1) {
+                let cos_theta = first
+                    .normal()
+                    .map_or(1.0, |normal| Vec3::dot(&normal, &ray.dir).abs());
+                first.pdf_fwd = pdf_position * cos_theta;
+            }
+        }
+
+        path
+    }
+
+    // Extends the subpath by following the material scattering.
+    #[allow(clippy::too_many_arguments)]
+    fn random_walk(
+        &self,
+        mut ray: Ray,
+        mut beta: Color3,
+        mut pdf_fwd: f64,
+        max_vertices: usize,
+        from_camera: bool,
+        world: &World,
+        path: &mut Vec<Vertex>,
+    ) {
+        while path.len() < max_vertices {
+            let Some(hit) = world.hit_objects(&ray, &Interval::new(0.0001, f64::MAX)) else {
+                // camera subpaths that leave the scene end on the environment light
+                if from_camera {
+                    let direction = ray.dir.normalize();
+                    let point = ray.pos;
+                    path.push(Vertex::new(
+                        VertexKind::Environment(direction),
+                        point,
+                        ray,
+                        beta,
+                        pdf_fwd,
+                    ));
+                }
+                break;
+            };
+
+            let scattering = hit.material.reflect(&ray, &hit);
+            let specular = hit.material.is_specular();
+            let (pdf_next, pdf_prev) = match &scattering {
+                Some((_, scattered)) if !specular => {
+                    let reversed = Ray {
+                        pos: hit.point + scattered.dir,
+                        dir: -scattered.dir,
+                        ..ray.clone()
+                    };
+                    (
+                        hit.material.pdf(&ray, &hit, &scattered.dir),
+                        hit.material.pdf(&reversed, &hit, &-ray.dir),
+                    )
+                }
+                _ => (0.0, 0.0),
+            };
+
+            let point = hit.point;
+            let mut vertex = Vertex::new(VertexKind::Surface(hit), point, ray.clone(), beta, 0.0);
+            if let Some(prev) = path.last() {
+                vertex.pdf_fwd = prev.convert_density(pdf_fwd, &vertex);
+            }
+            vertex.delta = specular;
+            path.push(vertex);
+            if path.len() >= max_vertices {
+                break;
+            }
+
+            let Some((attenuation, scattered)) = scattering else {
+                break;
+            };
+            let n = path.len();
+            path[n - 2].pdf_rev = path[n - 1].convert_density(pdf_prev, &path[n - 2]);
+
+            beta *= ray.spectral_color(&attenuation, world);
+            if beta.near_zero() {
+                break;
+            }
+            pdf_fwd = pdf_next;
+            ray = scattered;
+        }
+    }
+
+    // Shadow ray towards a random light, the end point of the s = 1 strategy.
+    fn sample_light(&self, vertex: &Vertex, world: &World) -> Option<Vertex> {
+        let (choice, light_pdf) = choose_light(world);
+
+        let mut light = if let Some(object_id) = choice {
+            let (direction, pdf) = world.objects[object_id].sample_direction(&vertex.point)?;
+            let shadow_ray = vertex.incoming.scattered(vertex.point, direction);
+            let hit = world.hit_objects(&shadow_ray, &Interval::new(0.0001, f64::MAX))?;
+            if hit.object_id != object_id || pdf <= 0.0 {
+                return None;
+            }
+            let emitted = hit.material.emitted(&shadow_ray, &hit);
+            let beta = shadow_ray.spectral_color(&emitted, world) / (pdf * light_pdf);
+            let point = hit.point;
+            Vertex::new(VertexKind::Light(hit), point, shadow_ray, beta, 0.0)
+        } else {
+            let (direction, pdf) = world.environment.sample_direction();
+            let shadow_ray = vertex.incoming.scattered(vertex.point, direction);
+            if world
+                .hit_objects(&shadow_ray, &Interval::new(0.0001, f64::MAX))
+                .is_some()
+            {
+                return None;
+            }
+            let sky = world.environment.radiance(&shadow_ray);
+            let beta = shadow_ray.spectral_color(&sky, world) / (pdf * light_pdf);
+            Vertex::new(
+                VertexKind::Environment(direction),
+                vertex.point + direction,
+                shadow_ray,
+                beta,
+                0.0,
+            )
+        };
+
+        light.pdf_fwd = light.pdf_light_origin(world);
+        Some(light)
+    }
+
+    // Joins the first `s` light and `t` camera vertices into a full path.
+    fn connect(
+        &self,
+        world: &World,
+        light_path: &[Vertex],
+        camera_path: &[Vertex],
+        s: usize,
+        t: usize,
+    ) -> Option<Connection> {
+        let pt = &camera_path[t - 1];
+        if t > 1 && s != 0 && matches!(pt.kind, VertexKind::Environment(_)) {
+            return None;
+        }
+
+        let mut connection = Connection {
+            contribution: Color3::BLACK,
+            sampled: None,
+            pixel: None,
+        };
+        if s == 0 {
+            // the camera subpath found a light on its own
+            if !pt.is_light() {
+                return None;
+            }
+            connection.contribution = pt.beta * pt.emitted(&camera_path[t - 2], world);
+        } else if t == 1 {
+            // light subpath connected to the lens
+            let qs = &light_path[s - 1];
+            if !qs.is_connectible() || !matches!(qs.kind, VertexKind::Surface(_)) {
+                return None;
+            }
+            let lens_point = self.camera.sample_lens();
+            let pixel = self.camera.raster(&lens_point, &qs.point)?;
+            let to_camera = lens_point - qs.point;
+            let cos_camera = Vec3::dot(&-to_camera.normalize(), &self.camera.forward());
+            let pdf = to_camera.length_squared() / (cos_camera * self.camera.lens_area());
+            let importance = self.camera.importance(&lens_point, &qs.point);
+
+            let camera_vertex = Vertex::new(
+                VertexKind::Camera,
+                lens_point,
+                qs.incoming.clone(),
+                Color3::from_float(importance / pdf),
+                0.0,
+            );
+            connection.contribution =
+                qs.beta * qs.scattering(&camera_vertex, world) * camera_vertex.beta;
+            if connection.contribution.near_zero() || !visible(world, qs, &camera_vertex) {
+                return None;
+            }
+            connection.pixel = Some(pixel);
+            connection.sampled = Some(camera_vertex);
+        } else if s == 1 {
+            // next event estimation
+            if !pt.is_connectible() {
+                return None;
+            }
+            let light = self.sample_light(pt, world)?;
+            connection.contribution = pt.beta * pt.scattering(&light, world) * light.beta;
+            connection.sampled = Some(light);
+        } else {
+            let qs = &light_path[s - 1];
+            if !qs.is_connectible() || !pt.is_connectible() {
+                return None;
+            }
+            let contribution =
+                qs.beta * qs.scattering(pt, world) * pt.scattering(qs, world) * pt.beta;
+            if contribution.near_zero() || !visible(world, qs, pt) {
+                return None;
+            }
+            connection.contribution = contribution / (pt.point - qs.point).length_squared();
+        }
+
+        if connection.contribution.near_zero() {
+            return None;
+        }
+        Some(connection)
+    }
+
+    // Balance heuristic weight of the strategy (s, t) against all others that could have
+    // sampled the same path, from the ratios of their densities.
+    fn mis_weight(
+        &self,
+        world: &World,
+        light_path: &[Vertex],
+        camera_path: &[Vertex],
+        sampled: Option<&Vertex>,
+        s: usize,
+        t: usize,
+    ) -> f64 {
+        if s + t == 2 {
+            return 1.0;
+        }
+        let pt = match (t, sampled) {
+            (1, Some(sampled)) => sampled,
+            _ => &camera_path[t - 1],
+        };
+        let qs = match (s, sampled) {
+            (0, _) => None,
+            (1, Some(sampled)) => Some(sampled),
+            _ => Some(&light_path[s - 1]),
+        };
+        let pt_minus = (t > 1).then(|| &camera_path[t - 2]);
+        let qs_minus = (s > 1).then(|| &light_path[s - 2]);
+
+        // (forward density, reverse density, delta) of the vertices as seen by this strategy
+        let densities = |v: &Vertex| (v.pdf_fwd, v.pdf_rev, v.delta);
+        let mut camera: Vec<_> = camera_path[..t].iter().map(densities).collect();
+        let mut light: Vec<_> = light_path[..s].iter().map(densities).collect();
+        if t == 1 {
+            camera[0] = densities(pt);
+        }
+        if let (1, Some(qs)) = (s, qs) {
+            light[0] = densities(qs);
+        }
+
+        // the connection vertices are not degenerate, their reverse densities change
+        camera[t - 1].2 = false;
+        camera[t - 1].1 = match qs {
+            Some(qs) => qs.pdf(qs_minus, pt, world, &self.camera),
+            None => pt.pdf_light_origin(world),
+        };
+        if let Some(pt_minus) = pt_minus {
+            camera[t - 2].1 = match qs {
+                Some(qs) => pt.pdf(Some(qs), pt_minus, world, &self.camera),
+                None => pt.pdf_light(pt_minus, world),
+            };
+        }
+        if let Some(qs) = qs {
+            light[s - 1].2 = false;
+            light[s - 1].1 = pt.pdf(pt_minus, qs, world, &self.camera);
+            if let Some(qs_minus) = qs_minus {
+                light[s - 2].1 = qs.pdf(Some(pt), qs_minus, world, &self.camera);
+            }
+        }
+
+        let remap = |pdf: f64| if pdf != 0.0 { pdf } else { 1.0 };
+        let mut sum = 0.0;
+        let mut ratio = 1.0;
+        for i in (1..t).rev() {
+            ratio *= remap(camera[i].1) / remap(camera[i].0);
+            if !camera[i].2 && !camera[i - 1].2 {
+                sum += ratio;
+            }
+        }
+        ratio = 1.0;
+        for i in (0..s).rev() {
+            ratio *= remap(light[i].1) / remap(light[i].0);
+            let delta_light = i > 0 && light[i - 1].2;
+            if !light[i].2 && !delta_light {
+                sum += ratio;
+            }
+        }
+
+        1.0 / (1.0 + sum)
+    }
+}
+
+impl Integrator for BidirectionalPathTracer {
+    fn radiance(&self, ray: &Ray, world: &World) -> PathRadiance {
+        let camera_path = self.camera_subpath(ray, world);
+        let light_path = self.light_subpath(ray, world);
+        let mut radiance = PathRadiance::ZERO;
+        if let Some(VertexKind::Surface(hit)) = camera_path.get(1).map(|vertex| &vertex.kind) {
+            radiance.first_hit = Some(FirstHit::new(hit));
+        }
+        // the subpaths share the wavelengths of the camera ray, dispersion on either of
+        // them keeps only the hero wavelength for all connections
+        radiance.hero_only = camera_path
+            .iter()
+            .chain(&light_path)
+            .any(|vertex| vertex.incoming.hero_only());
+
+        for t in 1..=camera_path.len() {
+            for s in 0..=light_path.len() {
+                let depth = s + t;
+                if (s == 1 && t == 1) || depth < 2 || depth - 2 > self.max_depth as usize {
+                    continue;
+                }
+                let Some(connection) = self.connect(world, &light_path, &camera_path, s, t) else {
+                    continue;
+                };
+                let weight = self.mis_weight(
+                    world,
+                    &light_path,
+                    &camera_path,
+                    connection.sampled.as_ref(),
+                    s,
+                    t,
+                );
+                let contribution = connection.contribution * weight;
+
+                match connection.pixel {
+                    Some((x, y)) => {
+                        let color = match radiance.wavelengths(ray) {
+                            Some(wavelengths) => {
+                                wavelengths.to_rgb(&contribution, world.working_space)
+                            }
+                            None => contribution,
+                        };
+                        let mut light_image = self.light_image.borrow_mut();
+                        let pixel = light_image.get(x, y);
+                        light_image.set(x, y, pixel + color);
+                    }
+                    None => radiance.add((depth - 2) as u16, contribution),
+                }
+            }
+        }
+
+        // the only strategy for the lights without geometry, unweighted
+        for (depth, vertex) in camera_path.iter().enumerate().skip(1) {
+            if depth > self.max_depth as usize {
+                break;
+            }
+            if let VertexKind::Surface(hit) = &vertex.kind {
+                if vertex.is_connectible() {
+                    let light = sample_lights(&vertex.incoming, hit, world);
+                    radiance.add(depth as u16, vertex.beta * light);
+                }
+            }
+        }
+
+        radiance
+    }
+
+    fn take_light_image(&self) -> Option<Framebuffer> {
+        let empty = Framebuffer::new(self.camera.width, self.camera.height);
+        Some(self.light_image.replace(empty))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        integrator::tests::{assert_matches_path_tracer, reference_scene},
+        material::Dielectric,
+    };
+
+    #[test]
+    fn matches_path_tracer() {
+        let (camera, world) = reference_scene(Dielectric::new(1.5));
+        let bidirectional = BidirectionalPathTracer::new(&camera, 5);
+        assert_matches_path_tracer(&camera, &world, &bidirectional, "bdpt");
+    }
+}
@@ -0,0 +1,90 @@
+use super::{Integrator, PathRadiance};
+use crate::{
+    aov::FirstHit,
+    objects::bvh::TraversalStats,
+    ray::Ray,
+    utils::interval::Interval,
+    vec3::{Color3, Vec3},
+    world::World,
+};
+
+// False color views of the first hit, to inspect scenes and the acceleration structure.
+pub enum DebugView {
+    // world space normals mapped from [-1, 1] to [0, 1]
+    Normals,
+    // white at the camera fading to black at `max_distance`
+    Depth { max_distance: f64 },
+    // u in red, v in green
+    Uv,
+    // BVH nodes visited plus intersection tests, blue (cheap) to red (`max_cost`)
+    TraversalCost { max_cost: u32 },
+}
+
+impl Integrator for DebugView {
+    fn radiance(&self, ray: &Ray, world: &World) -> PathRadiance {
+        let mut stats = TraversalStats::default();
+        let hit = world.hit_objects_with_stats(ray, &Interval::new(0.0001, f64::MAX), &mut stats);
+
+        let color = match (self, &hit) {
+            (DebugView::TraversalCost { max_cost }, _) => {
+                let cost = stats.node_visits + stats.intersection_tests;
+                heat_map((cost as f64 / *max_cost as f64).min(1.0))
+            }
+            (_, None) => Color3::BLACK,
+            (DebugView::Normals, Some(hit)) => 0.5 * (hit.normal + Vec3::ONE),
+            (DebugView::Depth { max_distance }, Some(hit)) => {
+                let distance = hit.ray_scalar * ray.dir.length();
+                Color3::from_float((1.0 - distance / max_distance).max(0.0))
+            }
+            // unbounded coordinates (planes) repeat every unit
+            (DebugView::Uv, Some(hit)) => {
+                Color3::new(hit.u.rem_euclid(1.0), hit.v.rem_euclid(1.0), 0.0)
+            }
+        };
+
+        PathRadiance {
+            direct: ray.spectral_color(&color, world),
+            indirect: Color3::ZERO,
+            first_hit: hit.as_ref().map(FirstHit::new),
+            hero_only: false,
+        }
+    }
+}
+
+// blue -> green -> red
+fn heat_map(t: f64) -> Color3 {
+    if t < 0.5 {
+        let s = 2.0 * t;
+        Color3::new(0.0, s, 1.0 - s)
+    } else {
+        let s = 2.0 * t - 1.0;
+        Color3::new(s, 1.0 - s, 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{material::Lambert, objects::sphere::Sphere, vec3::Pos3};
+
+    #[test]
+    fn debug_views() {
+        let mut world = World::new();
+        world.add_object(Sphere::new(
+            Pos3::new(0.0, 0.0, -2.0),
+            1.0,
+            Lambert::new(Color3::WHITE),
+        ));
+        let ray = Ray::new(Pos3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+
+        let normal = DebugView::Normals.radiance(&ray, &world).direct;
+        assert_eq!(normal, Color3::new(0.5, 0.5, 1.0));
+        let depth = DebugView::Depth { max_distance: 2.0 }
+            .radiance(&ray, &world)
+            .direct;
+        assert_eq!(depth, Color3::from_float(0.5));
+
+        let miss = Ray::new(Pos3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(DebugView::Uv.radiance(&miss, &world).direct, Color3::BLACK);
+    }
+}
@@ -0,0 +1,70 @@
+use super::{
+    environment_weight, sample_emitters, sample_environment, sample_lights, Integrator,
+    PathRadiance,
+};
+use crate::{aov::FirstHit, ray::Ray, utils::interval::Interval, vec3::Color3, world::World};
+
+// Only light that scatters once off a non-specular surface before reaching the camera.
+// Specular surfaces (mirrors, glass) are followed up to `max_bounces` to find it.
+pub struct DirectLighting {
+    pub max_bounces: u16,
+}
+
+impl DirectLighting {
+    pub fn new(max_bounces: u16) -> Self {
+        Self { max_bounces }
+    }
+}
+
+impl Integrator for DirectLighting {
+    fn radiance(&self, ray: &Ray, world: &World) -> PathRadiance {
+        let mut radiance = PathRadiance::ZERO;
+        let mut throughput = Color3::WHITE;
+        let mut ray = ray.clone();
+
+        for bounce in 0..self.max_bounces {
+            let Some(hit) = world.hit_objects(&ray, &Interval::new(0.0001, f64::MAX)) else {
+                let sky_color = ray.spectral_color(&world.environment.radiance(&ray), world);
+                radiance.add(bounce, throughput * sky_color);
+                break;
+            };
+
+            if bounce == 0 {
+                radiance.first_hit = Some(FirstHit::new(&hit));
+            }
+
+            let emitted = hit.material.emitted(&ray, &hit);
+            radiance.add(bounce, throughput * ray.spectral_color(&emitted, world));
+
+            let Some((attenuation, scattered)) = hit.material.reflect(&ray, &hit) else {
+                break;
+            };
+            let scattered_throughput = throughput * ray.spectral_color(&attenuation, world);
+            if hit.material.is_specular() {
+                throughput = scattered_throughput;
+                ray = scattered;
+                continue;
+            }
+
+            // emitters and lights through light sampling, the environment both through light
+            // sampling and the scattered direction
+            let light = sample_emitters(&ray, &hit, world)
+                + sample_lights(&ray, &hit, world)
+                + sample_environment(&ray, &hit, world);
+            radiance.add(bounce + 1, throughput * light);
+            if world
+                .hit_objects(&scattered, &Interval::new(0.0001, f64::MAX))
+                .is_none()
+            {
+                let sky_color =
+                    scattered.spectral_color(&world.environment.radiance(&scattered), world);
+                let weight = environment_weight(&ray, &hit, &scattered.dir, world);
+                radiance.add(bounce + 1, scattered_throughput * sky_color * weight);
+            }
+            break;
+        }
+
+        radiance.hero_only = ray.hero_only();
+        radiance
+    }
+}
@@ -0,0 +1,379 @@
+use std::{
+    cell::{Cell, RefCell},
+    f64::consts::PI,
+    rc::Rc,
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::{path::PathTracer, Integrator, PathRadiance};
+use crate::{
+    aov::FirstHit,
+    camera::{Camera, CameraProjection},
+    film::Framebuffer,
+    ray::Ray,
+    sampler::{self, Sampler},
+    spectrum::SampledWavelengths,
+    utils::interval::Interval,
+    vec3::Color3,
+    world::World,
+};
+
+// Largest f64 below one, primary samples stay in [0, 1).
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+pub struct MetropolisSetup {
+    // independent paths traced to estimate the image brightness and to seed the chains
+    pub bootstrap_samples: usize,
+    pub chains: usize,
+    // probability of a mutation replacing the whole path instead of perturbing it
+    pub large_step_probability: f64,
+    // standard deviation of the perturbations in primary sample space
+    pub sigma: f64,
+    pub max_bounces: u16,
+}
+
+// Primary sample space Metropolis light transport: a path is identified by the uniform
+// numbers its path tracer consumed (film position and wavelengths included). Markov chains
+// mutate these numbers, slightly or by drawing new ones, and visit paths in proportion to
+// their brightness, so a chain that found a hard to reach light path keeps exploring its
+// neighbourhood. The bootstrap phase, run on the first camera sample, estimates the image
+// brightness the result is normalized to. Every camera sample advances one chain by one
+// mutation, the paths are splatted into the light image.
+// https://pbr-book.org/3ed-2018/Light_Transport_III_Bidirectional_Methods/Metropolis_Light_Transport
+pub struct MetropolisLightTransport {
+    camera: CameraProjection,
+    path_tracer: PathTracer,
+    bootstrap_samples: usize,
+    chain_count: usize,
+    large_step_probability: f64,
+    sigma: f64,
+    chains: RefCell<Option<MarkovChains>>,
+    next_chain: Cell<usize>,
+    light_image: RefCell<Framebuffer>,
+}
+
+struct MarkovChains {
+    // average contribution of a uniformly sampled path
+    brightness: f64,
+    chains: Vec<Chain>,
+}
+
+struct Chain {
+    samples: Rc<RefCell<PrimarySamples>>,
+    current: PathSample,
+}
+
+struct PathSample {
+    radiance: Color3,
+    pixel: (usize, usize),
+    // scalar the chains are distributed by
+    contribution: f64,
+}
+
+#[derive(Copy, Clone, Default)]
+struct PrimarySample {
+    value: f64,
+    last_modification: u64,
+    backup: f64,
+    backup_modification: u64,
+}
+
+// Replayable sampler of the Markov chains: the same seed gives the same path, a mutation
+// can be rejected to restore the previous one. Samples are mutated lazily when they are
+// used, a sample skipped by some iterations receives their perturbations at once.
+struct PrimarySamples {
+    rng: StdRng,
+    large_step_probability: f64,
+    sigma: f64,
+    samples: Vec<PrimarySample>,
+    index: usize,
+    iteration: u64,
+    large_step: bool,
+    last_large_step: u64,
+}
+
+impl PrimarySamples {
+    fn new(seed: u64, large_step_probability: f64, sigma: f64) -> Self {
+        PrimarySamples {
+            rng: StdRng::seed_from_u64(seed),
+            large_step_probability,
+            sigma,
+            samples: Vec::new(),
+            index: 0,
+            iteration: 0,
+            // the first path is drawn uniformly
+            large_step: true,
+            last_large_step: 0,
+        }
+    }
+
+    fn start_iteration(&mut self) {
+        self.iteration += 1;
+        self.large_step = self.rng.gen::<f64>() < self.large_step_probability;
+        self.index = 0;
+    }
+
+    fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step = self.iteration;
+        }
+    }
+
+    fn reject(&mut self) {
+        for sample in &mut self.samples {
+            if sample.last_modification == self.iteration {
+                sample.value = sample.backup;
+                sample.last_modification = sample.backup_modification;
+            }
+        }
+        self.iteration -= 1;
+    }
+
+    fn standard_normal(&mut self) -> f64 {
+        // Box-Muller transform
+        let u1: f64 = self.rng.gen();
+        let u2: f64 = self.rng.gen();
+        (-2.0 * (1.0 - u1).ln()).sqrt() * (2.0 * PI * u2).cos()
+    }
+}
+
+impl Sampler for PrimarySamples {
+    fn next_1d(&mut self) -> f64 {
+        if self.index == self.samples.len() {
+            // a dimension the path did not use before starts uniform, not perturbed from zero
+            // (rejection sampling loops would never end)
+            self.samples.push(PrimarySample {
+                value: self.rng.gen(),
+                last_modification: self.iteration,
+                ..Default::default()
+            });
+        }
+        let mut sample = self.samples[self.index];
+
+        // a large step since the last use replaced the sample with a uniform one
+        if sample.last_modification < self.last_large_step {
+            sample.value = self.rng.gen();
+            sample.last_modification = self.last_large_step;
+        }
+
+        sample.backup = sample.value;
+        sample.backup_modification = sample.last_modification;
+        if self.large_step {
+            sample.value = self.rng.gen();
+        } else {
+            let steps = (self.iteration - sample.last_modification) as f64;
+            sample.value += self.standard_normal() * self.sigma * steps.sqrt();
+            sample.value -= sample.value.floor();
+        }
+        sample.value = sample.value.min(ONE_MINUS_EPSILON);
+        sample.last_modification = self.iteration;
+
+        self.samples[self.index] = sample;
+        self.index += 1;
+        sample.value
+    }
+}
+
+impl MetropolisLightTransport {
+    pub fn new(camera: &Camera, setup: MetropolisSetup) -> Self {
+        let camera = camera.projection();
+        let light_image = Framebuffer::new(camera.width, camera.height);
+        MetropolisLightTransport {
+            camera,
+            path_tracer: PathTracer::new(setup.max_bounces),
+            bootstrap_samples: setup.bootstrap_samples,
+            chain_count: setup.chains,
+            large_step_probability: setup.large_step_probability,
+            sigma: setup.sigma,
+            chains: RefCell::new(None),
+            next_chain: Cell::new(0),
+            light_image: RefCell::new(light_image),
+        }
+    }
+
+    fn primary_samples(&self, seed: u64) -> Rc<RefCell<PrimarySamples>> {
+        Rc::new(RefCell::new(PrimarySamples::new(
+            seed,
+            self.large_step_probability,
+            self.sigma,
+        )))
+    }
+
+    // The path the current state of `samples` stands for.
+    fn evaluate(
+        &self,
+        samples: &Rc<RefCell<PrimarySamples>>,
+        world: &World,
+        spectral: bool,
+    ) -> PathSample {
+        sampler::with_sampler(samples.clone(), || {
+            let mut rng = sampler::rng();
+            let x = rng.gen::<f64>() * self.camera.width as f64;
+            let y = rng.gen::<f64>() * self.camera.height as f64;
+            let mut ray = self.camera.ray(x, y);
+            if spectral {
+                ray.wavelengths = Some(SampledWavelengths::sample_uniform(rng.gen()));
+            }
+
+            let path = self.path_tracer.radiance(&ray, world);
+            let mut radiance = path.total();
+            if let Some(wavelengths) = path.wavelengths(&ray) {
+                radiance = wavelengths.to_rgb(&radiance, world.working_space);
+            }
+            PathSample {
+                radiance,
+                pixel: (x as usize, y as usize),
+                // spectral estimates can have negative components
+                contribution: (radiance.x.abs() + radiance.y.abs() + radiance.z.abs()) / 3.0,
+            }
+        })
+    }
+
+    fn bootstrap(&self, world: &World, spectral: bool) -> MarkovChains {
+        let mut cdf = Vec::with_capacity(self.bootstrap_samples);
+        let mut total = 0.0;
+        for seed in 0..self.bootstrap_samples {
+            let samples = self.primary_samples(seed as u64);
+            total += self.evaluate(&samples, world, spectral).contribution;
+            cdf.push(total);
+        }
+        if total <= 0.0 {
+            return MarkovChains {
+                brightness: 0.0,
+                chains: Vec::new(),
+            };
+        }
+
+        // chains start from bootstrap paths picked by their contribution, which replaces
+        // a burn-in phase; the seed replays the path
+        let chains = (0..self.chain_count)
+            .map(|_| {
+                let target = sampler::rng().gen::<f64>() * total;
+                let seed = cdf.partition_point(|&c| c <= target).min(cdf.len() - 1);
+                let samples = self.primary_samples(seed as u64);
+                let current = self.evaluate(&samples, world, spectral);
+                Chain { samples, current }
+            })
+            .collect();
+
+        MarkovChains {
+            brightness: total / self.bootstrap_samples as f64,
+            chains,
+        }
+    }
+
+    fn splat(&self, path: &PathSample, weight: f64, brightness: f64) {
+        if weight <= 0.0 || path.contribution <= 0.0 {
+            return;
+        }
+        let (x, y) = path.pixel;
+        let mut light_image = self.light_image.borrow_mut();
+        let color =
+            light_image.get(x, y) + path.radiance * (weight * brightness / path.contribution);
+        light_image.set(x, y, color);
+    }
+}
+
+impl Integrator for MetropolisLightTransport {
+    // The camera ray only advances the chains, the light image holds the result: with one
+    // mutation per camera sample, splatting brightness / contribution per mutation gives the
+    // pixel values once the camera divides by the samples per pixel.
+    fn radiance(&self, ray: &Ray, world: &World) -> PathRadiance {
+        let spectral = ray.wavelengths.is_some();
+        // the camera ray itself is only traced for the auxiliary passes
+        let radiance = PathRadiance {
+            first_hit: world
+                .hit_objects(ray, &Interval::new(0.0001, f64::MAX))
+                .as_ref()
+                .map(FirstHit::new),
+            ..PathRadiance::ZERO
+        };
+        let mut state = self.chains.borrow_mut();
+        let state = state.get_or_insert_with(|| self.bootstrap(world, spectral));
+        if state.chains.is_empty() {
+            return radiance;
+        }
+
+        let index = self.next_chain.get();
+        self.next_chain.set((index + 1) % state.chains.len());
+        let chain = &mut state.chains[index];
+
+        chain.samples.borrow_mut().start_iteration();
+        let proposed = self.evaluate(&chain.samples, world, spectral);
+        let accept = if chain.current.contribution > 0.0 {
+            (proposed.contribution / chain.current.contribution).min(1.0)
+        } else {
+            1.0
+        };
+
+        // both states are splatted with their expected weights
+        self.splat(&proposed, accept, state.brightness);
+        self.splat(&chain.current, 1.0 - accept, state.brightness);
+
+        if sampler::rng().gen::<f64>() < accept {
+            chain.current = proposed;
+            chain.samples.borrow_mut().accept();
+        } else {
+            chain.samples.borrow_mut().reject();
+        }
+
+        radiance
+    }
+
+    fn take_light_image(&self) -> Option<Framebuffer> {
+        let mut light_image = self.light_image.borrow_mut();
+        let empty = Framebuffer::new(light_image.width, light_image.height);
+        Some(std::mem::replace(&mut *light_image, empty))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        integrator::tests::{assert_matches_path_tracer, reference_scene},
+        material::Lambert,
+    };
+
+    fn draw(samples: &mut PrimarySamples, count: usize) -> Vec<f64> {
+        (0..count).map(|_| samples.next_1d()).collect()
+    }
+
+    #[test]
+    fn replay_and_reject() {
+        let mut samples = PrimarySamples::new(7, 0.0, 0.01);
+        let first = draw(&mut samples, 4);
+        assert_eq!(first, draw(&mut PrimarySamples::new(7, 0.0, 0.01), 4));
+
+        // a small step stays close, rejecting it restores the path
+        samples.start_iteration();
+        let mutated = draw(&mut samples, 4);
+        assert_ne!(mutated, first);
+        for (a, b) in mutated.iter().zip(&first) {
+            let distance = (a - b).abs();
+            assert!(distance.min(1.0 - distance) < 0.1);
+        }
+        samples.reject();
+        samples.start_iteration();
+        samples.reject();
+        let restored: Vec<f64> = samples.samples.iter().map(|s| s.value).collect();
+        assert_eq!(restored, first);
+    }
+
+    #[test]
+    fn matches_path_tracer() {
+        let (camera, world) = reference_scene(Lambert::new(Color3::new(0.8, 0.3, 0.3)));
+        let metropolis = MetropolisLightTransport::new(
+            &camera,
+            MetropolisSetup {
+                bootstrap_samples: 20_000,
+                chains: 64,
+                large_step_probability: 0.3,
+                sigma: 0.01,
+                max_bounces: 5,
+            },
+        );
+        assert_matches_path_tracer(&camera, &world, &metropolis, "mlt");
+    }
+}
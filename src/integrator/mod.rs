@@ -0,0 +1,280 @@
+use rand::Rng;
+
+use crate::{
+    aov::FirstHit,
+    film::Framebuffer,
+    objects::object::HitRecord,
+    ray::Ray,
+    sampler,
+    spectrum::SampledWavelengths,
+    utils::interval::Interval,
+    vec3::{Color3, Vec3},
+    world::World,
+};
+
+pub mod ambient_occlusion;
+pub mod bdpt;
+pub mod debug;
+pub mod direct;
+pub mod mlt;
+pub mod path;
+pub mod photon_map;
+pub mod whitted;
+
+// Light transport algorithm the camera uses to estimate the radiance along its rays.
+pub trait Integrator {
+    fn radiance(&self, ray: &Ray, world: &World) -> PathRadiance;
+
+    // Light that reached the film through paths connected to the camera from the light
+    // side, accumulated since the last call. The camera adds it scaled by 1 / samples per pixel.
+    fn take_light_image(&self) -> Option<Framebuffer> {
+        None
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PathRadiance {
+    pub direct: Color3,
+    pub indirect: Color3,
+    // surface the camera ray hit first, the auxiliary passes are built from it
+    pub first_hit: Option<FirstHit>,
+    // dispersion kept only the hero wavelength somewhere on the path
+    pub hero_only: bool,
+}
+
+impl PathRadiance {
+    pub const ZERO: PathRadiance = PathRadiance {
+        direct: Color3::ZERO,
+        indirect: Color3::ZERO,
+        first_hit: None,
+        hero_only: false,
+    };
+
+    // Light that reached the camera after at most one scattering event counts as direct.
+    pub fn add(&mut self, scattering_events: u16, light: Color3) {
+        if scattering_events <= 1 {
+            self.direct += light;
+        } else {
+            self.indirect += light;
+        }
+    }
+
+    pub fn total(&self) -> Color3 {
+        self.direct + self.indirect
+    }
+
+    // The wavelengths of the camera ray `ray` as the path left them, the radiance is
+    // converted with these.
+    pub fn wavelengths(&self, ray: &Ray) -> Option<SampledWavelengths> {
+        let mut wavelengths = ray.wavelengths?;
+        if self.hero_only {
+            wavelengths.terminate_secondary();
+        }
+        Some(wavelengths)
+    }
+}
+
+// One sample estimate of the light the emissive objects send to `hit` and on along
+// the ray, through a shadow ray towards one emitter picked by the light tree.
+pub fn sample_emitters(ray: &Ray, hit: &HitRecord, world: &World) -> Color3 {
+    match emitter_sample(ray, hit, world) {
+        Some((light, _, _)) => light,
+        None => Color3::BLACK,
+    }
+}
+
+// `sample_emitters` weighted with the balance heuristic against hitting the emitters
+// through the scattered direction, see `emitter_weight`.
+pub fn sample_emitters_weighted(ray: &Ray, hit: &HitRecord, world: &World) -> Color3 {
+    match emitter_sample(ray, hit, world) {
+        Some((light, direction, pdf)) => {
+            light * (pdf / (pdf + hit.material.pdf(ray, hit, &direction)))
+        }
+        None => Color3::BLACK,
+    }
+}
+
+// MIS weight of the emitter hit along `direction` scattered at `hit`, the counterpart of
+// `sample_emitters_weighted`.
+pub fn emitter_weight(
+    ray: &Ray,
+    hit: &HitRecord,
+    direction: &Vec3,
+    emitter_hit: &HitRecord,
+    world: &World,
+) -> f64 {
+    let emitter = &world.objects[emitter_hit.object_id];
+    let light_pdf = world.light_tree().pdf(&hit.point, emitter_hit.object_id)
+        * emitter.direction_pdf(&hit.point, &direction.normalize());
+    let pdf = hit.material.pdf(ray, hit, direction);
+    pdf / (pdf + light_pdf)
+}
+
+// Unweighted light of one emitter sample, the shadow ray direction and its pdf including
+// the choice of the emitter.
+fn emitter_sample(ray: &Ray, hit: &HitRecord, world: &World) -> Option<(Color3, Vec3, f64)> {
+    let u = sampler::rng().gen();
+    let (emitter_id, choice_pdf) = world.light_tree().sample(&hit.point, u)?;
+    let (direction, pdf) = world.objects[emitter_id].sample_direction(&hit.point)?;
+    let scattering = hit.material.evaluate(ray, hit, &direction);
+    if pdf <= 0.0 || scattering.near_zero() {
+        return None;
+    }
+
+    let shadow_ray = ray.scattered(hit.point, direction);
+    let light_hit = world.hit_surfaces(&shadow_ray, &Interval::new(0.0001, f64::MAX))?;
+    if light_hit.object_id != emitter_id {
+        return None;
+    }
+    let emitted = light_hit.material.emitted(&shadow_ray, &light_hit);
+    let transmittance =
+        world.transmittance(&shadow_ray, &Interval::new(0.0001, light_hit.ray_scalar));
+    let light = ray.spectral_color(&scattering, world)
+        * ray.spectral_color(&emitted, world)
+        * (transmittance / (choice_pdf * pdf));
+    Some((light, direction, choice_pdf * pdf))
+}
+
+// Light of the point, spot and directional lights arriving at `hit` and scattered along
+// the ray, with a shadow ray towards each of them. Nothing else can find them, so there
+// is nothing to weight against.
+pub fn sample_lights(ray: &Ray, hit: &HitRecord, world: &World) -> Color3 {
+    let mut light = Color3::BLACK;
+    for source in world.lights() {
+        let Some((direction, distance, irradiance)) = source.sample(&hit.point) else {
+            continue;
+        };
+        let scattering = hit.material.evaluate(ray, hit, &direction);
+        if scattering.near_zero() {
+            continue;
+        }
+
+        let shadow_ray = ray.scattered(hit.point, direction);
+        let interval = Interval::new(0.0001, distance - 0.0001);
+        if world.hit_surfaces(&shadow_ray, &interval).is_some() {
+            continue;
+        }
+        light += ray.spectral_color(&scattering, world)
+            * ray.spectral_color(&irradiance, world)
+            * world.transmittance(&shadow_ray, &interval);
+    }
+    light
+}
+
+// One sample estimate of the light the environment sends to `hit` and on along the ray,
+// through a shadow ray in a direction sampled from the environment. It is weighted with
+// the balance heuristic against finding the environment through the scattered direction,
+// see `environment_weight`.
+pub fn sample_environment(ray: &Ray, hit: &HitRecord, world: &World) -> Color3 {
+    let (direction, pdf) = world.environment.sample_direction();
+    let scattering = hit.material.evaluate(ray, hit, &direction);
+    if pdf <= 0.0 || scattering.near_zero() {
+        return Color3::BLACK;
+    }
+
+    let shadow_ray = ray.scattered(hit.point, direction);
+    let interval = Interval::new(0.0001, f64::MAX);
+    if world.hit_surfaces(&shadow_ray, &interval).is_some() {
+        return Color3::BLACK;
+    }
+    let sky = world.environment.radiance(&shadow_ray);
+    let weight = pdf / (pdf + hit.material.pdf(ray, hit, &direction));
+    let transmittance = world.transmittance(&shadow_ray, &interval);
+    ray.spectral_color(&scattering, world)
+        * ray.spectral_color(&sky, world)
+        * (transmittance * weight / pdf)
+}
+
+// MIS weight of the environment seen along `direction` scattered at `hit`, the
+// counterpart of `sample_environment`.
+pub fn environment_weight(ray: &Ray, hit: &HitRecord, direction: &Vec3, world: &World) -> f64 {
+    let pdf = hit.material.pdf(ray, hit, direction);
+    pdf / (pdf + world.environment.pdf(direction))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        camera::{AntiAliasingMethod, Camera, CameraSetup, RenderingMode},
+        color_space::ColorSpace,
+        denoise::DenoiseMethod,
+        integrator::path::PathTracer,
+        material::{DiffuseLight, Lambert, Material},
+        objects::sphere::Sphere,
+        tonemap::ToneMapping,
+        vec3::Pos3,
+    };
+
+    // Small scene lit by a sphere light: a ground, `subject` in the middle and the camera
+    // looking at it.
+    pub fn reference_scene(subject: impl Material + 'static) -> (Camera, World) {
+        let camera = Camera::new(CameraSetup {
+            image_width: 16,
+            aspect_ratio: 1.0,
+            vfow_deg: 40.0,
+            position: Pos3::new(0.0, 1.0, 6.0),
+            look_at: Pos3::new(0.0, 0.0, 0.0),
+            anti_aliasing: AntiAliasingMethod::RandomSuperSampling(128),
+            rendering_mode: RenderingMode::Rgb,
+            focus_distance: 6.0,
+            defocus_angle: 0.0,
+            aov_passes: vec![],
+            denoise: DenoiseMethod::None,
+            tone_mapping: ToneMapping::Clamp,
+            exposure: -2.0,
+            output_color_space: ColorSpace::Srgb,
+        });
+        let mut world = World::new();
+        world.add_object(Sphere::new(
+            Pos3::new(0.0, -101.0, 0.0),
+            100.0,
+            Lambert::new(Color3::from_float(0.5)),
+        ));
+        world.add_object(Sphere::new(Pos3::new(-1.0, 0.0, 0.0), 1.0, subject));
+        world.add_object(Sphere::new(
+            Pos3::new(1.5, 2.0, 0.0),
+            0.5,
+            DiffuseLight::new(Color3::from_float(4.0)),
+        ));
+        (camera, world)
+    }
+
+    // linear mean of a rendered image
+    pub fn render_mean(
+        camera: &Camera,
+        world: &World,
+        integrator: &dyn Integrator,
+        name: &str,
+    ) -> f64 {
+        let path = std::env::temp_dir().join(format!("rust_raytracing_{}.ppm", name));
+        let filename = path.to_str().unwrap();
+        camera.render(world, integrator, filename).unwrap();
+
+        let image = Framebuffer::read_ppm(filename).unwrap();
+        let sum: f64 = image
+            .pixels()
+            .iter()
+            .map(|p| (0..3).map(|i| ColorSpace::Srgb.decode(p[i])).sum::<f64>())
+            .sum();
+        sum / (3 * image.pixels().len()) as f64
+    }
+
+    // The image `integrator` renders is as bright as the path traced one.
+    pub fn assert_matches_path_tracer(
+        camera: &Camera,
+        world: &World,
+        integrator: &dyn Integrator,
+        name: &str,
+    ) {
+        let path_name = format!("{}_path", name);
+        let path_traced = render_mean(camera, world, &PathTracer::new(5), &path_name);
+        let rendered = render_mean(camera, world, integrator, name);
+        assert!(
+            (path_traced - rendered).abs() < 0.05 * path_traced,
+            "{} != {}",
+            path_traced,
+            rendered
+        );
+    }
+}
@@ -0,0 +1,271 @@
+use rand::Rng;
+
+use super::{
+    emitter_weight, environment_weight, sample_emitters_weighted, sample_environment,
+    sample_lights, Integrator, PathRadiance,
+};
+use crate::{
+    aov::FirstHit, objects::object::HitRecord, ray::Ray, sampler, utils::interval::Interval,
+    vec3::Color3, world::World,
+};
+
+// Bounces that are always traced before Russian roulette may end a path.
+const RUSSIAN_ROULETTE_MIN_BOUNCES: u16 = 3;
+
+// Unidirectional path tracer, follows the scattering of the materials until the
+// path leaves the scene.
+pub struct PathTracer {
+    pub max_bounces: u16,
+}
+
+impl PathTracer {
+    pub fn new(max_bounces: u16) -> Self {
+        Self { max_bounces }
+    }
+}
+
+impl Integrator for PathTracer {
+    fn radiance(&self, ray: &Ray, world: &World) -> PathRadiance {
+        let mut radiance = PathRadiance::ZERO;
+        // fraction of the light at the current vertex that reaches the camera
+        let mut throughput = Color3::WHITE;
+        let mut ray = ray.clone();
+        // the non-specular vertex the ray was scattered from, where the emitters and the
+        // environment were also sampled directly
+        let mut scattered_from: Option<(Ray, HitRecord)> = None;
+
+        for bounce in 0..self.max_bounces {
+            let hit = match world.hit_objects(&ray, &Interval::new(0.0001, f64::MAX)) {
+                Some(hit) => hit,
+                None => {
+                    let sky_color = ray.spectral_color(&world.environment.radiance(&ray), world);
+                    let weight = match &scattered_from {
+                        Some((incoming, from)) => {
+                            environment_weight(incoming, from, &ray.dir, world)
+                        }
+                        None => 1.0,
+                    };
+                    radiance.add(bounce, throughput * sky_color * weight);
+                    break;
+                }
+            };
+
+            if bounce == 0 {
+                radiance.first_hit = Some(FirstHit::new(&hit));
+            }
+
+            let emitted = hit.material.emitted(&ray, &hit);
+            if !emitted.near_zero() {
+                let weight = match &scattered_from {
+                    Some((incoming, from)) => emitter_weight(incoming, from, &ray.dir, &hit, world),
+                    None => 1.0,
+                };
+                radiance.add(
+                    bounce,
+                    throughput * ray.spectral_color(&emitted, world) * weight,
+                );
+            }
+            // point, spot and directional lights can't be hit, they are sampled at every
+            // vertex that isn't specular, like the emitters and the environment
+            let specular = hit.material.is_specular();
+            if !specular {
+                let light = sample_emitters_weighted(&ray, &hit, world)
+                    + sample_lights(&ray, &hit, world)
+                    + sample_environment(&ray, &hit, world);
+                radiance.add(bounce + 1, throughput * light);
+            }
+
+            let Some((attenuation, reflected_ray)) = hit.material.reflect(&ray, &hit) else {
+                break;
+            };
+            throughput *= ray.spectral_color(&attenuation, world);
+            scattered_from = (!specular).then_some((ray, hit));
+            ray = reflected_ray;
+
+            // Russian roulette: paths that carry little energy are terminated randomly,
+            // survivors are weighted up by the inverse probability to stay unbiased
+            if bounce + 1 >= RUSSIAN_ROULETTE_MIN_BOUNCES {
+                let survival = throughput.x.max(throughput.y).max(throughput.z);
+                if survival < 1.0 {
+                    if sampler::rng().gen::<f64>() >= survival {
+                        break;
+                    }
+                    throughput = throughput / survival;
+                }
+            }
+        }
+
+        radiance.hero_only = ray.hero_only();
+        radiance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color_space::ColorSpace,
+        environment::Environment,
+        integrator::direct::DirectLighting,
+        material::{Dielectric, DiffuseLight, Dispersion, Lambert, Metallic},
+        objects::{plane::Plane, sphere::Sphere},
+        sky::{Sky, SkySetup},
+        spectrum::SampledWavelengths,
+        vec3::{Pos3, Vec3},
+    };
+
+    #[test]
+    fn default_ray_color() {
+        let ray_up = Ray::new(Pos3::new(1.0, 2.0, 3.0), Vec3::new(0.0, 1.0, 0.0));
+        let ray_down = Ray::new(Pos3::new(1.0, 2.0, 3.0), Vec3::new(0.0, -1.0, 0.0));
+
+        let world = World::new();
+        let integrator = PathTracer::new(1);
+        let color_up = integrator.radiance(&ray_up, &world).total();
+        let color_down = integrator.radiance(&ray_down, &world).total();
+
+        assert_eq!(color_up, Color3::new(0.5, 0.7, 1.0));
+        assert_eq!(color_down, Color3::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn direct_and_indirect_light() {
+        let ray = Ray::new(Pos3::new(0.0, 0.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let mut world = World::new();
+        world.add_object(Sphere::new(
+            Pos3::new(0.0, -1001.0, 0.0),
+            1000.0,
+            Metallic::new(Color3::WHITE, 0.0),
+        ));
+        let integrator = PathTracer::new(5);
+
+        // ground mirror reflects straight back into the sky
+        let radiance = integrator.radiance(&ray, &world);
+        assert_eq!(radiance.direct, Color3::new(0.5, 0.7, 1.0));
+        assert_eq!(radiance.indirect, Color3::ZERO);
+
+        // an off-center mirror above sends the ray back to the ground before it escapes
+        world.add_object(Sphere::new(
+            Pos3::new(0.5, 3.0, 0.0),
+            1.0,
+            Metallic::new(Color3::WHITE, 0.0),
+        ));
+        let radiance = integrator.radiance(&ray, &world);
+        assert_eq!(radiance.direct, Color3::ZERO);
+        assert!(radiance.indirect.length() > 0.0);
+    }
+
+    #[test]
+    fn long_paths() {
+        // two parallel mirrors trap the ray until the bounce limit, deeper than the stack would allow recursively
+        let ray = Ray::new(Pos3::new(0.0, 0.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let mut world = World::new();
+        for y in [-1001.0, 1001.0] {
+            world.add_object(Sphere::new(
+                Pos3::new(0.0, y, 0.0),
+                1000.0,
+                Metallic::new(Color3::WHITE, 0.0),
+            ));
+        }
+
+        let radiance = PathTracer::new(u16::MAX).radiance(&ray, &world);
+        assert_eq!(radiance.total(), Color3::ZERO);
+    }
+
+    #[test]
+    fn emission() {
+        let ray = Ray::new(Pos3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut world = World::new();
+        world.add_object(Sphere::new(
+            Pos3::new(0.0, 0.0, -3.0),
+            1.0,
+            DiffuseLight::new(Color3::new(4.0, 2.0, 1.0)),
+        ));
+
+        let radiance = PathTracer::new(5).radiance(&ray, &world);
+        assert_eq!(radiance.direct, Color3::new(4.0, 2.0, 1.0));
+        // the camera builds the auxiliary passes from the first hit
+        let first_hit = radiance.first_hit.unwrap();
+        assert_eq!((first_hit.object_id, first_hit.depth), (0, 2.0));
+    }
+
+    // Light at the end of `ray` from the path tracer, against direct lighting which
+    // samples every light source explicitly.
+    fn matches_direct_lighting(ray: &Ray, world: &World) {
+        let n = 2000;
+        let mean = |integrator: &dyn Integrator| {
+            let mut sum = Color3::BLACK;
+            for _ in 0..n {
+                sum += integrator.radiance(ray, world).total();
+            }
+            sum / n as f64
+        };
+        let path = mean(&PathTracer::new(2));
+        let direct = mean(&DirectLighting::new(2));
+        for i in 0..3 {
+            assert!(
+                (path[i] - direct[i]).abs() < 0.05 * direct[i],
+                "{:?} != {:?}",
+                path,
+                direct
+            );
+        }
+    }
+
+    #[test]
+    fn sun_is_sampled() {
+        let ray = Ray::new(Pos3::new(0.0, 1.0, 0.0), Vec3::new(0.3, -1.0, 0.2));
+        let mut world = World::new();
+        world.add_object(Plane::new(
+            Pos3::ZERO,
+            Lambert::new(Color3::from_float(0.5)),
+        ));
+        world.environment =
+            Environment::Sky(Box::new(Sky::new(SkySetup::default(), ColorSpace::Srgb)));
+
+        // the sun is too small to be found through the scattered directions alone
+        matches_direct_lighting(&ray, &world);
+    }
+
+    #[test]
+    fn emitters_are_sampled() {
+        let ray = Ray::new(Pos3::new(0.0, 1.0, 0.0), Vec3::new(0.3, -1.0, 0.2));
+        let mut world = World::new();
+        world.add_object(Plane::new(
+            Pos3::ZERO,
+            Lambert::new(Color3::from_float(0.5)),
+        ));
+        world.add_object(Sphere::new(
+            Pos3::new(0.5, 1.0, 0.0),
+            0.05,
+            DiffuseLight::new(Color3::from_float(10_000.0)),
+        ));
+
+        matches_direct_lighting(&ray, &world);
+    }
+
+    #[test]
+    fn dispersion() {
+        // glass in a white environment stays white, the hero wavelength that continues
+        // alone through the glass stands for all three samples exactly once
+        let mut world = World::new();
+        world.environment = Environment::Gradient(Color3::WHITE);
+        world.add_object(Sphere::new(
+            Pos3::new(0.0, 0.0, -2.0),
+            1.0,
+            Dielectric::with_dispersion(Dispersion::DIAMOND),
+        ));
+        let integrator = PathTracer::new(20);
+        let samples = 80_000;
+        let mut mean = Color3::ZERO;
+        for _ in 0..samples {
+            let mut ray = Ray::new(Pos3::ZERO, Vec3::new(0.0, 0.0, -1.0));
+            ray.wavelengths = Some(SampledWavelengths::sample_uniform(sampler::rng().gen()));
+            let radiance = integrator.radiance(&ray, &world);
+            assert!(radiance.hero_only);
+            let wavelengths = radiance.wavelengths(&ray).unwrap();
+            mean += wavelengths.to_rgb(&radiance.total(), world.working_space) / samples as f64;
+        }
+        assert!((mean - Color3::WHITE).length() < 0.05, "{:?}", mean);
+    }
+}
@@ -0,0 +1,433 @@
+use std::{cell::Cell, f64::consts::PI};
+
+use rand::Rng;
+
+use super::{sample_lights, Integrator, PathRadiance};
+use crate::{
+    aov::FirstHit,
+    light::Light,
+    objects::object::HitRecord,
+    ray::Ray,
+    sampler,
+    utils::{
+        aabb::Aabb,
+        helpers::{cone_pdf, random_in_unit_disk, sample_cone},
+        interval::Interval,
+        onb::Onb,
+    },
+    vec3::{Color3, Pos3, Vec3},
+    world::World,
+};
+
+// Bounces that are always traced before Russian roulette may end a path.
+const RUSSIAN_ROULETTE_MIN_BOUNCES: u16 = 3;
+// Radius reduction of progressive photon mapping, between 0 and 1.
+const PROGRESSIVE_ALPHA: f64 = 2.0 / 3.0;
+
+pub struct PhotonMapperSetup {
+    // photons emitted per pass
+    pub photons: usize,
+    pub passes: usize,
+    // gather radius of the first pass, later passes shrink it
+    pub radius: f64,
+    pub max_bounces: u16,
+}
+
+// Path tracer that takes caustics (light -> specular surfaces -> diffuse surface) from a
+// photon map instead of finding them by chance. Photons are shot from the lights towards
+// the specular objects and stored where they land on diffuse surfaces. Every pass has its
+// own photon map with a smaller radius and the camera samples cycle through them, the
+// average converges like progressive photon mapping.
+// https://www.cs.jhu.edu/~misha/ReadingSeminar/Papers/Knaus11.pdf
+// Photons are traced in RGB, dispersion does not show in the caustics.
+pub struct PhotonMapper {
+    pub max_bounces: u16,
+    passes: Vec<(PhotonMap, f64)>,
+    samples: Cell<usize>,
+}
+
+struct Photon {
+    position: Pos3,
+    // direction the photon travelled in
+    direction: Vec3,
+    normal: Vec3,
+    power: Color3,
+}
+
+// Balanced kd-tree: every sub-slice is a subtree with its root in the middle, `axes`
+// holds the split axis of each root.
+struct PhotonMap {
+    photons: Vec<Photon>,
+    axes: Vec<usize>,
+}
+
+impl PhotonMap {
+    fn build(mut photons: Vec<Photon>) -> Self {
+        let mut axes = vec![0; photons.len()];
+        PhotonMap::build_node(&mut photons, &mut axes);
+        PhotonMap { photons, axes }
+    }
+
+    fn build_node(photons: &mut [Photon], axes: &mut [usize]) {
+        if photons.is_empty() {
+            return;
+        }
+        let first = photons[0].position;
+        let bounds = photons.iter().fold(Aabb::new(first, first), |bounds, p| {
+            bounds.union(&Aabb::new(p.position, p.position))
+        });
+        let axis = bounds.longest_axis();
+        let mid = photons.len() / 2;
+        photons.select_nth_unstable_by(mid, |a, b| a.position[axis].total_cmp(&b.position[axis]));
+        axes[mid] = axis;
+
+        let (left, right) = photons.split_at_mut(mid);
+        let (left_axes, right_axes) = axes.split_at_mut(mid);
+        PhotonMap::build_node(left, left_axes);
+        PhotonMap::build_node(&mut right[1..], &mut right_axes[1..]);
+    }
+
+    fn for_each_within(&self, point: &Pos3, radius: f64, f: &mut impl FnMut(&Photon)) {
+        PhotonMap::query(&self.photons, &self.axes, point, radius * radius, f);
+    }
+
+    fn query(
+        photons: &[Photon],
+        axes: &[usize],
+        point: &Pos3,
+        radius_sq: f64,
+        f: &mut impl FnMut(&Photon),
+    ) {
+        if photons.is_empty() {
+            return;
+        }
+        let mid = photons.len() / 2;
+        let photon = &photons[mid];
+        if (photon.position - *point).length_squared() <= radius_sq {
+            f(photon);
+        }
+
+        let offset = point[axes[mid]] - photon.position[axes[mid]];
+        let (left, right) = (0..mid, mid + 1..photons.len());
+        let (near, far) = if offset < 0.0 {
+            (left, right)
+        } else {
+            (right, left)
+        };
+        PhotonMap::query(&photons[near.clone()], &axes[near], point, radius_sq, f);
+        if offset * offset <= radius_sq {
+            PhotonMap::query(&photons[far.clone()], &axes[far], point, radius_sq, f);
+        }
+    }
+}
+
+// Bounding spheres of the specular objects, the photons are aimed at.
+fn caustic_targets(world: &World) -> Vec<(Pos3, f64)> {
+    world
+        .objects
+        .iter()
+        .filter(|object| object.is_specular())
+        .filter_map(|object| object.bounding_box())
+        .map(|bounds| (bounds.centroid(), 0.5 * bounds.extent().length()))
+        .collect()
+}
+
+// Photon leaving a light towards one of the targets, with its power.
+fn emit_photon(world: &World, targets: &[(Pos3, f64)]) -> Option<(Ray, Color3)> {
+    let mut rng = sampler::rng();
+    let (emitters, lights) = (world.emitters(), world.lights());
+    let light_pdf = 1.0 / (emitters.len() + lights.len() + 1) as f64;
+    let target = targets[rng.gen_range(0..targets.len())];
+    let choice = rng.gen_range(0..=emitters.len() + lights.len());
+
+    if choice < emitters.len() {
+        // the emitters share their chances by power
+        let (object_id, probability) = world.light_tree().sample_power(rng.gen())?;
+        let light_pdf = light_pdf * emitters.len() as f64 * probability;
+        let object = &world.objects[object_id];
+        let hit = object.sample_surface()?;
+        let (direction, pdf_direction) = aim_photon(&hit.point, target, targets);
+        let cos_theta = Vec3::dot(&hit.normal, &direction);
+        if cos_theta <= 0.0 {
+            return None;
+        }
+
+        let towards_light = Ray::new(hit.point + direction, -direction);
+        let emitted = hit.material.emitted(&towards_light, &hit);
+        let pdf_position = 1.0 / object.area();
+        let power = emitted * (cos_theta / (light_pdf * pdf_position * pdf_direction));
+        Some((Ray::new(hit.point, direction), power))
+    } else if let Some(light) = lights.get(choice - emitters.len()) {
+        match light {
+            Light::Directional {
+                direction,
+                irradiance,
+            } => {
+                let (ray, pdf_position) = parallel_photon(world, -*direction, target, targets)?;
+                Some((ray, *irradiance / (light_pdf * pdf_position)))
+            }
+            _ => {
+                let position = light.position()?;
+                let (direction, pdf_direction) = aim_photon(&position, target, targets);
+                let power = light.intensity(&direction) / (light_pdf * pdf_direction);
+                Some((Ray::new(position, direction), power))
+            }
+        }
+    } else {
+        let (direction, pdf_direction) = world.environment.sample_direction();
+        let (ray, pdf_position) = parallel_photon(world, direction, target, targets)?;
+        let sky = world.environment.radiance(&Ray::new(target.0, direction));
+        Some((ray, sky / (light_pdf * pdf_position * pdf_direction)))
+    }
+}
+
+// Direction from `origin` into the cone around `target` and its density, any of the
+// targets could have picked it.
+fn aim_photon(origin: &Pos3, target: (Pos3, f64), targets: &[(Pos3, f64)]) -> (Vec3, f64) {
+    let (direction, _) = sample_cone(origin, &target.0, target.1);
+    let pdf_direction = targets
+        .iter()
+        .map(|(center, radius)| cone_pdf(origin, center, *radius, &direction))
+        .sum::<f64>()
+        / targets.len() as f64;
+    (direction, pdf_direction)
+}
+
+// Parallel light arriving from `direction` (towards the light), entering through a disk in
+// front of the target, and its density on the plane across the light.
+fn parallel_photon(
+    world: &World,
+    direction: Vec3,
+    target: (Pos3, f64),
+    targets: &[(Pos3, f64)],
+) -> Option<(Ray, f64)> {
+    let (target_center, target_radius) = target;
+    let (scene_center, scene_radius) = world
+        .bounds()
+        .map(|bounds| (bounds.centroid(), 0.5 * bounds.extent().length()))?;
+    let frame = Onb::from_w(&direction);
+    let disk = random_in_unit_disk();
+    let distance = (target_center - scene_center).length() + scene_radius;
+    let origin = target_center
+        + distance * direction
+        + target_radius * (disk.x * frame.u + disk.y * frame.v);
+
+    // any target disk covering the line counts
+    let pdf_position = targets
+        .iter()
+        .filter(|(center, radius)| {
+            let to_center = *center - origin;
+            let along = Vec3::dot(&to_center, &direction);
+            (to_center - along * direction).length_squared() <= radius * radius
+        })
+        .map(|(_, radius)| 1.0 / (PI * radius * radius))
+        .sum::<f64>()
+        / targets.len() as f64;
+    Some((Ray::new(origin, -direction), pdf_position))
+}
+
+fn trace_photons(world: &World, count: usize, max_bounces: u16) -> PhotonMap {
+    let targets = caustic_targets(world);
+    let mut photons = Vec::new();
+    if targets.is_empty() {
+        return PhotonMap::build(photons);
+    }
+
+    for _ in 0..count {
+        let Some((mut ray, power)) = emit_photon(world, &targets) else {
+            continue;
+        };
+        let mut power = power / count as f64;
+
+        for bounce in 0..max_bounces {
+            let Some(hit) = world.hit_objects(&ray, &Interval::new(0.0001, f64::MAX)) else {
+                break;
+            };
+            if !hit.material.is_specular() {
+                // only light that went through specular surfaces is a caustic
+                if bounce > 0 {
+                    photons.push(Photon {
+                        position: hit.point,
+                        direction: ray.dir.normalize(),
+                        normal: hit.normal,
+                        power,
+                    });
+                }
+                break;
+            }
+            let Some((attenuation, scattered)) = hit.material.reflect(&ray, &hit) else {
+                break;
+            };
+            power *= attenuation;
+            ray = scattered;
+        }
+    }
+
+    PhotonMap::build(photons)
+}
+
+impl PhotonMapper {
+    pub fn new(world: &World, setup: PhotonMapperSetup) -> Self {
+        let mut radius_sq = setup.radius * setup.radius;
+        let passes = (1..=setup.passes.max(1))
+            .map(|pass| {
+                eprintln!("Photon pass {}/{}", pass, setup.passes.max(1));
+                let map = trace_photons(world, setup.photons, setup.max_bounces);
+                let radius = radius_sq.sqrt();
+                radius_sq *= (pass as f64 + PROGRESSIVE_ALPHA) / (pass as f64 + 1.0);
+                (map, radius)
+            })
+            .collect();
+
+        Self {
+            max_bounces: setup.max_bounces,
+            passes,
+            samples: Cell::new(0),
+        }
+    }
+
+    // Radiance density estimate of the caustic photons around the hit.
+    fn caustics(&self, ray: &Ray, hit: &HitRecord, world: &World) -> Color3 {
+        let sample = self.samples.get();
+        let (map, radius) = &self.passes[sample % self.passes.len()];
+
+        let mut reflected = Color3::BLACK;
+        map.for_each_within(&hit.point, *radius, &mut |photon| {
+            let to_light = -photon.direction;
+            let cos_theta = Vec3::dot(&hit.normal, &to_light);
+            // photons of another surface within the radius; thin transmissive sheets also
+            // gather the photons arriving at their back, opaque materials evaluate them black
+            if cos_theta == 0.0 || Vec3::dot(&hit.normal, &photon.normal).abs() < 0.5 {
+                return;
+            }
+            let bsdf = hit.material.evaluate(ray, hit, &to_light) / cos_theta.abs();
+            reflected += bsdf * photon.power;
+        });
+
+        ray.spectral_color(&(reflected / (PI * radius * radius)), world)
+    }
+}
+
+impl Integrator for PhotonMapper {
+    fn radiance(&self, ray: &Ray, world: &World) -> PathRadiance {
+        let mut radiance = PathRadiance::ZERO;
+        let mut throughput = Color3::WHITE;
+        let mut ray = ray.clone();
+        // specular bounces after a diffuse one: light found now is a caustic, which the
+        // photon map already accounted for
+        let mut caustic_path = false;
+        let mut diffuse_seen = false;
+
+        for bounce in 0..self.max_bounces {
+            let Some(hit) = world.hit_objects(&ray, &Interval::new(0.0001, f64::MAX)) else {
+                if !caustic_path {
+                    let sky_color = ray.spectral_color(&world.environment.radiance(&ray), world);
+                    radiance.add(bounce, throughput * sky_color);
+                }
+                break;
+            };
+
+            if bounce == 0 {
+                radiance.first_hit = Some(FirstHit::new(&hit));
+            }
+
+            if !caustic_path {
+                let emitted = hit.material.emitted(&ray, &hit);
+                radiance.add(bounce, throughput * ray.spectral_color(&emitted, world));
+            }
+            if hit.material.is_specular() {
+                caustic_path = diffuse_seen;
+            } else {
+                radiance.add(bounce + 1, throughput * sample_lights(&ray, &hit, world));
+                radiance.add(bounce + 2, throughput * self.caustics(&ray, &hit, world));
+                diffuse_seen = true;
+                caustic_path = false;
+            }
+
+            let Some((attenuation, reflected_ray)) = hit.material.reflect(&ray, &hit) else {
+                break;
+            };
+            throughput *= ray.spectral_color(&attenuation, world);
+            ray = reflected_ray;
+
+            if bounce + 1 >= RUSSIAN_ROULETTE_MIN_BOUNCES {
+                let survival = throughput.x.max(throughput.y).max(throughput.z);
+                if survival < 1.0 {
+                    if sampler::rng().gen::<f64>() >= survival {
+                        break;
+                    }
+                    throughput = throughput / survival;
+                }
+            }
+        }
+
+        self.samples.set(self.samples.get() + 1);
+        radiance.hero_only = ray.hero_only();
+        radiance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::{Dielectric, DiffuseLight, Lambert},
+        objects::sphere::Sphere,
+    };
+
+    #[test]
+    fn kd_tree_query() {
+        let mut rng = sampler::rng();
+        let photons: Vec<Photon> = (0..500)
+            .map(|_| Photon {
+                position: Vec3::random(-1.0, 1.0),
+                direction: Vec3::new(0.0, -1.0, 0.0),
+                normal: Vec3::new(0.0, 1.0, 0.0),
+                power: Color3::WHITE,
+            })
+            .collect();
+        let points: Vec<Pos3> = photons.iter().map(|p| p.position).collect();
+        let map = PhotonMap::build(photons);
+
+        for _ in 0..20 {
+            let center = Vec3::random(-1.0, 1.0);
+            let radius = rng.gen_range(0.1..0.5);
+            let expected = points
+                .iter()
+                .filter(|p| (**p - center).length() <= radius)
+                .count();
+            let mut found = 0;
+            map.for_each_within(&center, radius, &mut |_| found += 1);
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn caustic_under_glass() {
+        let mut world = World::new();
+        world.add_object(Sphere::new(
+            Pos3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Lambert::new(Color3::WHITE),
+        ));
+        world.add_object(Sphere::new(
+            Pos3::new(0.0, 1.5, 0.0),
+            0.5,
+            Dielectric::new(1.5),
+        ));
+        world.add_object(Sphere::new(
+            Pos3::new(0.0, 4.0, 0.0),
+            0.2,
+            DiffuseLight::new(Color3::from_float(10.0)),
+        ));
+
+        let map = trace_photons(&world, 20000, 10);
+        assert!(!map.photons.is_empty());
+        // the glass focuses the light straight below it
+        let mut below = 0;
+        map.for_each_within(&Pos3::new(0.0, 0.0, 0.0), 0.5, &mut |_| below += 1);
+        let mut aside = 0;
+        map.for_each_within(&Pos3::new(2.0, 0.0, 0.0), 0.5, &mut |_| aside += 1);
+        assert!(below > 10 * aside.max(1), "{} {}", below, aside);
+    }
+}
@@ -0,0 +1,64 @@
+use super::{sample_emitters, sample_lights, Integrator, PathRadiance};
+use crate::{aov::FirstHit, ray::Ray, utils::interval::Interval, vec3::Color3, world::World};
+
+// Whitted-style ray tracer: specular reflection and refraction are followed recursively,
+// other surfaces get the direct light of the emitters and lights and an ambient term from the
+// environment above them. Glass picks reflection or refraction by its Fresnel term
+// instead of spawning both rays.
+pub struct Whitted {
+    pub max_depth: u16,
+}
+
+impl Whitted {
+    pub fn new(max_depth: u16) -> Self {
+        Self { max_depth }
+    }
+
+    fn trace(
+        &self,
+        ray: &Ray,
+        world: &World,
+        depth: u16,
+        throughput: Color3,
+        radiance: &mut PathRadiance,
+    ) {
+        if depth >= self.max_depth {
+            return;
+        }
+        radiance.hero_only |= ray.hero_only();
+        let Some(hit) = world.hit_objects(ray, &Interval::new(0.0001, f64::MAX)) else {
+            let sky_color = ray.spectral_color(&world.environment.radiance(ray), world);
+            radiance.add(depth, throughput * sky_color);
+            return;
+        };
+
+        if depth == 0 {
+            radiance.first_hit = Some(FirstHit::new(&hit));
+        }
+
+        let emitted = hit.material.emitted(ray, &hit);
+        radiance.add(depth, throughput * ray.spectral_color(&emitted, world));
+
+        if hit.material.is_specular() {
+            if let Some((attenuation, scattered)) = hit.material.reflect(ray, &hit) {
+                let throughput = throughput * ray.spectral_color(&attenuation, world);
+                self.trace(&scattered, world, depth + 1, throughput, radiance);
+            }
+            return;
+        }
+
+        let light = sample_emitters(ray, &hit, world) + sample_lights(ray, &hit, world);
+        let up = ray.scattered(hit.point, hit.normal);
+        let ambient = ray.spectral_color(&hit.material.albedo(&hit), world)
+            * ray.spectral_color(&world.environment.radiance(&up), world);
+        radiance.add(depth + 1, throughput * (light + ambient));
+    }
+}
+
+impl Integrator for Whitted {
+    fn radiance(&self, ray: &Ray, world: &World) -> PathRadiance {
+        let mut radiance = PathRadiance::ZERO;
+        self.trace(ray, world, 0, Color3::WHITE, &mut radiance);
+        radiance
+    }
+}
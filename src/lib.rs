@@ -0,0 +1,19 @@
+pub mod aov;
+pub mod camera;
+pub mod color_space;
+pub mod denoise;
+pub mod environment;
+pub mod film;
+pub mod integrator;
+pub mod light;
+pub mod material;
+pub mod objects;
+pub mod ray;
+pub mod sampler;
+pub mod sky;
+pub mod spectrum;
+pub mod texture;
+pub mod tonemap;
+pub mod utils;
+pub mod vec3;
+pub mod world;
@@ -0,0 +1,355 @@
+use std::io::Error;
+
+use crate::{
+    utils::{helpers::degrees_to_radians, onb::Onb},
+    vec3::{Color3, Pos3, Vec3},
+};
+
+// Idealized light sources without geometry. Rays can't hit them, the integrators find them
+// only by casting shadow rays from the shading points towards them.
+pub enum Light {
+    // radiant intensity `intensity` equally in all directions
+    Point { position: Pos3, intensity: Color3 },
+    Spot(Spot),
+    // infinitely far away (the sun): parallel light travelling along `direction`, arriving
+    // with `irradiance` on a surface facing it
+    Directional { direction: Vec3, irradiance: Color3 },
+}
+
+// Point light shining into a cone, fading out smoothly towards its edge, or shaped by a
+// measured IES profile. Profiles are normalized, `intensity` is their brightest direction.
+pub struct Spot {
+    position: Pos3,
+    // axis of the cone, the nadir of the profile
+    frame: Onb,
+    intensity: Color3,
+    cos_total_width: f64,
+    cos_falloff_start: f64,
+    profile: Option<IesProfile>,
+}
+
+// Candela distribution of a luminaire from an IESNA LM-63 photometric file (type C):
+// vertical angles from the nadir, horizontal angles around it, with the symmetries of
+// the file.
+pub struct IesProfile {
+    vertical_angles: Vec<f64>,
+    horizontal_angles: Vec<f64>,
+    // vertical values per horizontal angle, divided by the largest one
+    values: Vec<f64>,
+}
+
+impl Light {
+    pub fn point(position: Pos3, intensity: Color3) -> Self {
+        Light::Point {
+            position,
+            intensity,
+        }
+    }
+
+    pub fn directional(direction: Vec3, irradiance: Color3) -> Self {
+        Light::Directional {
+            direction: direction.normalize(),
+            irradiance,
+        }
+    }
+
+    // Normalized direction from `point` towards the light, its distance (f64::MAX for
+    // directional lights) and the irradiance it brings to a surface facing it.
+    pub fn sample(&self, point: &Pos3) -> Option<(Vec3, f64, Color3)> {
+        let (direction, distance, irradiance) = match self {
+            Light::Directional {
+                direction,
+                irradiance,
+            } => (-*direction, f64::MAX, *irradiance),
+            _ => {
+                let to_light = self.position()? - *point;
+                let distance = to_light.length();
+                let direction = to_light / distance;
+                let intensity = self.intensity(&-direction);
+                (direction, distance, intensity / (distance * distance))
+            }
+        };
+        (distance > 0.0 && !irradiance.near_zero()).then_some((direction, distance, irradiance))
+    }
+
+    // None for directional lights
+    pub fn position(&self) -> Option<Pos3> {
+        match self {
+            Light::Point { position, .. } => Some(*position),
+            Light::Spot(spot) => Some(spot.position),
+            Light::Directional { .. } => None,
+        }
+    }
+
+    // Radiant intensity along the normalized `direction` leaving a point or spot light.
+    pub fn intensity(&self, direction: &Vec3) -> Color3 {
+        match self {
+            Light::Point { intensity, .. } => *intensity,
+            Light::Spot(spot) => spot.intensity * spot.falloff(direction),
+            Light::Directional { .. } => Color3::BLACK,
+        }
+    }
+}
+
+impl Spot {
+    // Cone of `cone_angle` degrees around the axis (half the opening), fading out from
+    // `falloff_start` degrees on.
+    pub fn new(
+        position: Pos3,
+        target: Pos3,
+        intensity: Color3,
+        cone_angle: f64,
+        falloff_start: f64,
+    ) -> Self {
+        let cone_angle = cone_angle.clamp(0.0, 180.0);
+        Self {
+            position,
+            frame: Onb::from_w(&(target - position)),
+            intensity,
+            cos_total_width: degrees_to_radians(cone_angle).cos(),
+            cos_falloff_start: degrees_to_radians(falloff_start.clamp(0.0, cone_angle)).cos(),
+            profile: None,
+        }
+    }
+
+    // Luminaire pointing its nadir at `target`, horizontal angle 0 is along Onb::u.
+    pub fn ies(position: Pos3, target: Pos3, intensity: Color3, profile: IesProfile) -> Self {
+        Self {
+            profile: Some(profile),
+            ..Self::new(position, target, intensity, 180.0, 180.0)
+        }
+    }
+
+    // fraction of the intensity emitted along the normalized `direction`
+    fn falloff(&self, direction: &Vec3) -> f64 {
+        let local = self.frame.to_local(direction);
+        if let Some(profile) = &self.profile {
+            let vertical = local.z.clamp(-1.0, 1.0).acos().to_degrees();
+            let horizontal = local.y.atan2(local.x).to_degrees().rem_euclid(360.0);
+            return profile.value(vertical, horizontal);
+        }
+        if local.z < self.cos_total_width {
+            0.0
+        } else if local.z >= self.cos_falloff_start {
+            1.0
+        } else {
+            let x =
+                (local.z - self.cos_total_width) / (self.cos_falloff_start - self.cos_total_width);
+            x * x * (3.0 - 2.0 * x)
+        }
+    }
+}
+
+impl IesProfile {
+    pub fn load(filename: &str) -> Result<Self, Error> {
+        Self::parse(&std::fs::read_to_string(filename)?)
+    }
+
+    fn parse(text: &str) -> Result<Self, Error> {
+        // keywords up to the tilt line, then only numbers
+        let mut lines = text.lines();
+        let tilt = lines
+            .find(|line| line.trim_start().starts_with("TILT="))
+            .ok_or_else(|| invalid_data("missing TILT line"))?;
+        let mut numbers = lines
+            .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+            .filter(|token| !token.is_empty())
+            .map(|token| token.parse::<f64>());
+        let mut next = || match numbers.next() {
+            Some(Ok(number)) => Ok(number),
+            _ => Err(invalid_data("malformed photometric data")),
+        };
+
+        match tilt.trim() {
+            "TILT=NONE" => {}
+            "TILT=INCLUDE" => {
+                // lamp to luminaire geometry, then the angles and multiplying factors
+                next()?;
+                let count = next()? as usize;
+                for _ in 0..2 * count {
+                    next()?;
+                }
+            }
+            _ => return Err(invalid_data("tilt data in separate files is not supported")),
+        }
+
+        let _lamps = next()?;
+        let _lumens = next()?;
+        let multiplier = next()?;
+        let (vertical_count, horizontal_count) = (next()? as usize, next()? as usize);
+        if next()? != 1.0 {
+            return Err(invalid_data("only type C photometry is supported"));
+        }
+        // units and luminous opening, ballast factor, future use and input watts
+        for _ in 0..7 {
+            next()?;
+        }
+        if vertical_count == 0 || horizontal_count == 0 {
+            return Err(invalid_data("photometric data without angles"));
+        }
+
+        let vertical_angles = (0..vertical_count)
+            .map(|_| next())
+            .collect::<Result<_, _>>()?;
+        let horizontal_angles = (0..horizontal_count)
+            .map(|_| next())
+            .collect::<Result<_, _>>()?;
+        let mut values: Vec<f64> = (0..vertical_count * horizontal_count)
+            .map(|_| next().map(|candela| candela * multiplier))
+            .collect::<Result<_, _>>()?;
+        let max = values.iter().copied().fold(0.0, f64::max);
+        if max > 0.0 {
+            values.iter_mut().for_each(|value| *value /= max);
+        }
+        Ok(Self {
+            vertical_angles,
+            horizontal_angles,
+            values,
+        })
+    }
+
+    // Normalized candela value at the angles in degrees, interpolated bilinearly.
+    pub fn value(&self, vertical: f64, horizontal: f64) -> f64 {
+        let last = *self.horizontal_angles.last().unwrap();
+        // the file covers a quadrant, a half or the full circle, or is rotationally symmetric
+        let mut horizontal = horizontal.rem_euclid(360.0);
+        if last <= 180.0 && horizontal > 180.0 {
+            horizontal = 360.0 - horizontal;
+        }
+        if last <= 90.0 && horizontal > 90.0 {
+            horizontal = 180.0 - horizontal;
+        }
+
+        let Some((v, tv)) = interpolation(&self.vertical_angles, vertical) else {
+            return 0.0;
+        };
+        let (h, th) = interpolation(&self.horizontal_angles, horizontal).unwrap_or((0, 0.0));
+        let column = |h: usize, v: usize| {
+            let h = h.min(self.horizontal_angles.len() - 1);
+            let v = v.min(self.vertical_angles.len() - 1);
+            self.values[h * self.vertical_angles.len() + v]
+        };
+        let at = |h: usize| (1.0 - tv) * column(h, v) + tv * column(h, v + 1);
+        (1.0 - th) * at(h) + th * at(h + 1)
+    }
+}
+
+// Index of the interval of the sorted `angles` containing `angle` and the position in it.
+fn interpolation(angles: &[f64], angle: f64) -> Option<(usize, f64)> {
+    if angle < angles[0] || angle > *angles.last().unwrap() {
+        return None;
+    }
+    let i = angles
+        .partition_point(|a| *a <= angle)
+        .clamp(1, angles.len())
+        - 1;
+    match angles.get(i + 1) {
+        Some(next) if *next > angles[i] => Some((i, (angle - angles[i]) / (next - angles[i]))),
+        _ => Some((i, 0.0)),
+    }
+}
+
+fn invalid_data(message: &str) -> Error {
+    Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        integrator::sample_lights, material::Lambert, objects::plane::Plane, ray::Ray,
+        utils::interval::Interval, world::World,
+    };
+
+    #[test]
+    fn spot_falloff() {
+        let spot = Spot::new(
+            Pos3::ZERO,
+            Pos3::new(0.0, -2.0, 0.0),
+            Color3::WHITE,
+            30.0,
+            20.0,
+        );
+        let at = |degrees: f64| {
+            let angle = degrees_to_radians(degrees);
+            spot.falloff(&Vec3::new(angle.sin(), -angle.cos(), 0.0))
+        };
+        assert_eq!(at(0.0), 1.0);
+        assert_eq!(at(19.0), 1.0);
+        assert!(at(25.0) > 0.0 && at(25.0) < 1.0);
+        assert!(at(24.0) > at(26.0));
+        assert_eq!(at(31.0), 0.0);
+        assert_eq!(at(180.0), 0.0);
+    }
+
+    #[test]
+    fn ies_profile() {
+        let file = "IESNA:LM-63-2002\n[TEST] wide downlight\nTILT=NONE\n\
+            1 1000 2.0 3 2 1 1 0 0 0\n1.0 1.0 10\n\
+            0 45 90\n0 90\n\
+            100 50 0\n200, 100, 0\n";
+        let profile = IesProfile::parse(file).unwrap();
+        // largest value 400 cd after the multiplier
+        assert_eq!(profile.value(0.0, 90.0), 1.0);
+        assert_eq!(profile.value(0.0, 0.0), 0.5);
+        assert!((profile.value(22.5, 0.0) - 0.375).abs() < 1e-12);
+        assert!((profile.value(45.0, 45.0) - 0.375).abs() < 1e-12);
+        // quadrant symmetry, nothing above the last vertical angle
+        assert_eq!(profile.value(45.0, 270.0), profile.value(45.0, 90.0));
+        assert_eq!(profile.value(45.0, 180.0), profile.value(45.0, 0.0));
+        assert_eq!(profile.value(120.0, 0.0), 0.0);
+        assert!(IesProfile::parse("TILT=lamp.tlt\n").is_err());
+        assert!(IesProfile::parse("TILT=NONE\n1 1000 1 3").is_err());
+
+        let spot = Spot::ies(
+            Pos3::ZERO,
+            Pos3::new(0.0, -1.0, 0.0),
+            Color3::WHITE,
+            profile,
+        );
+        let along_u = spot.frame.to_world(&Vec3::new(1.0, 0.0, 1.0)).normalize();
+        assert!((spot.falloff(&along_u) - 0.25).abs() < 1e-9);
+        assert_eq!(spot.falloff(&Vec3::new(0.0, 1.0, 0.0)), 0.0);
+    }
+
+    #[test]
+    fn direct_light() {
+        let mut world = World::new();
+        world.add_object(Plane::new(
+            Pos3::ZERO,
+            Lambert::new(Color3::new(0.5, 0.5, 0.5)),
+        ));
+        world.add_light(Light::point(
+            Pos3::new(0.0, 2.0, 0.0),
+            Color3::from_float(4.0),
+        ));
+        world.add_light(Light::directional(
+            Vec3::new(-1.0, -1.0, 0.0),
+            Color3::new(1.0, 0.0, 0.0),
+        ));
+        // lights below the surface don't reach it
+        world.add_light(Light::point(Pos3::new(0.0, -1.0, 0.0), Color3::WHITE));
+
+        let ray = Ray::new(Pos3::new(0.0, 1.0, 1.0), Vec3::new(0.0, -1.0, -1.0));
+        let hit = world
+            .hit_objects(&ray, &Interval::new(0.0001, f64::MAX))
+            .unwrap();
+        let light = sample_lights(&ray, &hit, &world);
+        // albedo / pi times the irradiance: 4 / 2^2 head on, 1 at 45 degrees
+        let expected = Color3::new(1.0 + 0.5f64.sqrt(), 1.0, 1.0) * (0.5 / std::f64::consts::PI);
+        assert!((light - expected).length() < 1e-9, "{:?}", light);
+
+        // blocked by a sphere in between
+        world.add_object(crate::objects::sphere::Sphere::new(
+            Pos3::new(0.0, 1.0, 0.0),
+            0.5,
+            Lambert::new(Color3::WHITE),
+        ));
+        let hit = world
+            .hit_objects(&ray, &Interval::new(0.0001, f64::MAX))
+            .unwrap();
+        let light = sample_lights(&ray, &hit, &world);
+        assert!((light.x - 0.5f64.sqrt() * 0.5 / std::f64::consts::PI).abs() < 1e-9);
+        assert_eq!(light.y, 0.0);
+    }
+}
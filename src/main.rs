@@ -1,18 +1,15 @@
-mod camera;
-mod material;
-mod objects;
-mod ray;
-mod utils;
-mod vec3;
-mod world;
-
-use crate::vec3::Vec3;
-use crate::world::World;
-use camera::{AntiAliasingMethod, Camera, CameraSetup};
-use material::{Dielectric, Lambert, Metallic};
-use objects::sphere::Sphere;
 use rand::Rng;
-use vec3::{Color3, Pos3};
+use rust_raytracing::{
+    camera::{AntiAliasingMethod, Camera, CameraSetup, RenderingMode},
+    color_space::ColorSpace,
+    denoise::DenoiseMethod,
+    integrator::path::PathTracer,
+    material::{Dielectric, Lambert, Metallic},
+    objects::sphere::Sphere,
+    tonemap::ToneMapping,
+    vec3::{Color3, Pos3, Vec3},
+    world::World,
+};
 
 fn main() -> std::io::Result<()> {
     let filename = "render.ppm";
@@ -34,10 +31,18 @@ fn main() -> std::io::Result<()> {
             y: 2.0,
             z: 3.0,
         },
-        max_ray_bounces: 50,
+        rendering_mode: RenderingMode::Rgb,
         vfow_deg: 20.0,
         defocus_angle: 0.6,
         focus_distance: 10.0,
+        // aov_passes: vec![AovPass::Depth, AovPass::Normal, AovPass::Albedo],
+        aov_passes: vec![],
+        // denoise: DenoiseMethod::ATrous(ATrousDenoiser::default()),
+        denoise: DenoiseMethod::None,
+        // tone_mapping: ToneMapping::AcesFilmic,
+        tone_mapping: ToneMapping::Clamp,
+        exposure: 0.0,
+        output_color_space: ColorSpace::Srgb,
     });
 
     let mut world = World::new();
@@ -85,7 +90,12 @@ fn main() -> std::io::Result<()> {
     let mat_3 = Metallic::new(Color3::new(0.7, 0.6, 0.5), 0.0);
     world.add_object(Sphere::new(Pos3::new(4.0, 1.0, 0.0), 1.0, mat_3));
 
-    match camera.render(&world, filename) {
+    // let integrator = DebugView::Normals;
+    // let integrator = BidirectionalPathTracer::new(&camera, 8);
+    // let integrator = PhotonMapper::new(&world, PhotonMapperSetup { photons: 100_000, passes: 16, radius: 0.05, max_bounces: 50 });
+    // let integrator = MetropolisLightTransport::new(&camera, MetropolisSetup { bootstrap_samples: 100_000, chains: 1000, large_step_probability: 0.3, sigma: 0.01, max_bounces: 50 });
+    let integrator = PathTracer::new(50);
+    match camera.render(&world, &integrator, filename) {
         Ok(_) => println!("Rendering finished"),
         Err(_) => eprintln!("Failed to render image"),
     }
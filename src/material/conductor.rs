@@ -0,0 +1,262 @@
+use rand::Rng;
+
+use crate::{
+    material::{
+        microfacet::{fresnel_complex, Ggx},
+        Material,
+    },
+    objects::object::HitRecord,
+    ray::Ray,
+    sampler,
+    utils::{complex::Complex, helpers::reflect_vector},
+    vec3::{Color3, Vec3},
+};
+
+// Complex index of refraction n + ik of a metal, per color channel.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ComplexIor {
+    pub n: Color3,
+    pub k: Color3,
+}
+
+// A metal with its Fresnel reflectance computed from the complex index of refraction,
+// so it brightens towards white at grazing angles. Rough metals use a GGX microfacet
+// distribution; `Metallic` remains for stylized flat-colored reflections.
+pub struct Conductor {
+    ior: ComplexIor,
+    // None for a perfect mirror
+    distribution: Option<Ggx>,
+}
+
+impl ComplexIor {
+    // measured data reduced to the red, green and blue channel
+    // https://refractiveindex.info
+    pub const GOLD: ComplexIor = ComplexIor::new(
+        Color3 {
+            x: 0.143,
+            y: 0.374,
+            z: 1.442,
+        },
+        Color3 {
+            x: 3.983,
+            y: 2.386,
+            z: 1.603,
+        },
+    );
+    pub const SILVER: ComplexIor = ComplexIor::new(
+        Color3 {
+            x: 0.155,
+            y: 0.117,
+            z: 0.138,
+        },
+        Color3 {
+            x: 4.828,
+            y: 3.122,
+            z: 2.147,
+        },
+    );
+    pub const COPPER: ComplexIor = ComplexIor::new(
+        Color3 {
+            x: 0.200,
+            y: 0.924,
+            z: 1.102,
+        },
+        Color3 {
+            x: 3.912,
+            y: 2.452,
+            z: 2.142,
+        },
+    );
+    pub const ALUMINIUM: ComplexIor = ComplexIor::new(
+        Color3 {
+            x: 1.657,
+            y: 0.880,
+            z: 0.521,
+        },
+        Color3 {
+            x: 9.224,
+            y: 6.270,
+            z: 4.837,
+        },
+    );
+
+    pub const fn new(n: Color3, k: Color3) -> Self {
+        ComplexIor { n, k }
+    }
+
+    pub fn reflectance(&self, cos_theta: f64) -> Color3 {
+        let channel = |i: usize| fresnel_complex(cos_theta, Complex::new(self.n[i], self.k[i]));
+        Color3::new(channel(0), channel(1), channel(2))
+    }
+}
+
+impl Conductor {
+    // roughness in [0, 1] as for Principled, 0 is a mirror
+    pub fn new(ior: ComplexIor, roughness: f64) -> Self {
+        let alpha = roughness.clamp(0.0, 1.0).powi(2);
+        Self::anisotropic(ior, alpha, alpha)
+    }
+
+    // Brushed metal, the highlights stretch along HitRecord::tangent with a larger alpha_x.
+    pub fn anisotropic(ior: ComplexIor, alpha_x: f64, alpha_y: f64) -> Self {
+        Self {
+            ior,
+            // a numerically delta lobe is traced as one
+            distribution: (!Ggx::is_smooth(alpha_x, alpha_y))
+                .then(|| Ggx::anisotropic(alpha_x, alpha_y)),
+        }
+    }
+
+    // half vector of the local directions and the Fresnel reflectance at it
+    fn half_vector(&self, wo: &Vec3, wi: &Vec3) -> Option<(Vec3, Color3)> {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return None;
+        }
+        let wm = (wo + wi).normalize();
+        Some((wm, self.ior.reflectance(Vec3::dot(wo, &wm))))
+    }
+}
+
+impl Material for Conductor {
+    fn reflect(&self, ray: &Ray, hit: &HitRecord) -> Option<(Color3, Ray)> {
+        let unit_dir = ray.dir.normalize();
+        let Some(distribution) = &self.distribution else {
+            let cos_theta = Vec3::dot(&-unit_dir, &hit.normal);
+            let reflected = reflect_vector(&unit_dir, &hit.normal);
+            return Some((
+                self.ior.reflectance(cos_theta),
+                ray.specular_reflection(hit, reflected),
+            ));
+        };
+
+        let frame = hit.anisotropy_frame();
+        let wo = frame.to_local(&-unit_dir);
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let mut rng = sampler::rng();
+        let wm = distribution.sample_visible(&wo, rng.gen(), rng.gen());
+        let wi = 2.0 * Vec3::dot(&wo, &wm) * wm - wo;
+        let (_, fresnel) = self.half_vector(&wo, &wi)?;
+        // f cos / pdf of visible normal sampling
+        let weight = fresnel * (distribution.g(&wo, &wi) / distribution.g1(&wo));
+        Some((weight, ray.scattered(hit.point, frame.to_world(&wi))))
+    }
+
+    fn albedo(&self, _hit: &HitRecord) -> Color3 {
+        self.ior.reflectance(1.0)
+    }
+
+    fn evaluate(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> Color3 {
+        let Some(distribution) = &self.distribution else {
+            return Color3::BLACK;
+        };
+        let frame = hit.anisotropy_frame();
+        let wo = frame.to_local(&-ray.dir.normalize());
+        let wi = frame.to_local(&direction.normalize());
+        let Some((wm, fresnel)) = self.half_vector(&wo, &wi) else {
+            return Color3::BLACK;
+        };
+        fresnel * (distribution.d(&wm) * distribution.g(&wo, &wi) / (4.0 * wo.z))
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> f64 {
+        let Some(distribution) = &self.distribution else {
+            return 0.0;
+        };
+        let frame = hit.anisotropy_frame();
+        let wo = frame.to_local(&-ray.dir.normalize());
+        let wi = frame.to_local(&direction.normalize());
+        let Some((wm, _)) = self.half_vector(&wo, &wi) else {
+            return 0.0;
+        };
+        distribution.pdf_visible(&wo, &wm) / (4.0 * Vec3::dot(&wo, &wm))
+    }
+
+    fn is_specular(&self) -> bool {
+        self.distribution.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+    use crate::{
+        material::{tests::plane_hit, Lambert},
+        vec3::Pos3,
+    };
+
+    #[test]
+    fn presets() {
+        let gold = ComplexIor::GOLD.reflectance(1.0);
+        assert!(gold.x > 0.9 && gold.y > 0.7 && gold.z < 0.4, "{:?}", gold);
+        let aluminium = ComplexIor::ALUMINIUM.reflectance(1.0);
+        assert!(aluminium.x > 0.9 && aluminium.z > 0.9);
+        // every metal turns white at grazing angles
+        let grazing = ComplexIor::COPPER.reflectance(0.01);
+        assert!(grazing.z > 0.9, "{:?}", grazing);
+
+        let ray = Ray::new(Pos3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0));
+        let hit = plane_hit(Lambert::new(Color3::WHITE), &ray);
+        let mirror = Conductor::new(ComplexIor::SILVER, 0.0);
+        let (attenuation, reflected) = mirror.reflect(&ray, &hit).unwrap();
+        assert!(mirror.is_specular());
+        assert_eq!(attenuation, ComplexIor::SILVER.reflectance(0.5f64.sqrt()));
+        assert!((reflected.dir.normalize() - Vec3::new(1.0, 1.0, 0.0).normalize()).length() < 1e-9);
+    }
+
+    #[test]
+    fn rough_sampling_matches_evaluation() {
+        let ray = Ray::new(Pos3::new(-1.0, 1.5, 0.3), Vec3::new(1.0, -1.5, -0.3));
+        let hit = plane_hit(Lambert::new(Color3::WHITE), &ray);
+        let material = Conductor::new(ComplexIor::GOLD, 0.5);
+        let n = 100_000;
+
+        let mut sampled = Color3::BLACK;
+        for _ in 0..n {
+            if let Some((attenuation, _)) = material.reflect(&ray, &hit) {
+                sampled += attenuation;
+            }
+        }
+        let mut evaluated = Color3::BLACK;
+        let mut pdf = 0.0;
+        for _ in 0..n {
+            // uniform over the upper hemisphere
+            let z: f64 = rand::random();
+            let phi = 2.0 * PI * rand::random::<f64>();
+            let r = (1.0 - z * z).sqrt();
+            let direction = Vec3::new(r * phi.cos(), z, r * phi.sin());
+            evaluated += material.evaluate(&ray, &hit, &direction) * 2.0 * PI;
+            pdf += material.pdf(&ray, &hit, &direction) * 2.0 * PI;
+        }
+        let (sampled, evaluated) = (sampled / n as f64, evaluated / n as f64);
+        assert!(
+            (sampled - evaluated).length() < 0.03,
+            "{:?} {:?}",
+            sampled,
+            evaluated
+        );
+        assert!(pdf / (n as f64) < 1.02);
+    }
+
+    #[test]
+    fn brushed_highlights() {
+        // head on to a plane, its tangent is x
+        let ray = Ray::new(Pos3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let hit = plane_hit(Lambert::new(Color3::WHITE), &ray);
+        let material = Conductor::anisotropic(ComplexIor::ALUMINIUM, 0.4, 0.05);
+        assert!(!material.is_specular());
+
+        let (mut along, mut across) = (0.0, 0.0);
+        for _ in 0..10_000 {
+            if let Some((_, scattered)) = material.reflect(&ray, &hit) {
+                let direction = scattered.dir.normalize();
+                along += direction.x.abs();
+                across += direction.z.abs();
+            }
+        }
+        assert!(along > 4.0 * across, "{} {}", along, across);
+    }
+}
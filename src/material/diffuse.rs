@@ -0,0 +1,311 @@
+use std::{f64::consts::PI, rc::Rc};
+
+use rand::Rng;
+
+use crate::{
+    material::Material,
+    objects::object::HitRecord,
+    ray::Ray,
+    sampler,
+    texture::{SolidColor, Texture},
+    tonemap::luminance,
+    utils::onb::Onb,
+    vec3::{Color3, Vec3},
+};
+
+// Energy-preserving Oren-Nayar (EON): the Fujii form of Oren-Nayar plus a multiple
+// scattering term, so a white surface stays white at every roughness.
+// https://arxiv.org/abs/2410.18026
+pub struct OrenNayar {
+    albedo: Rc<dyn Texture>,
+    roughness: f64,
+}
+
+// Fabric: a diffuse base under a "Charlie" sheen lobe that catches light at grazing
+// angles. The base only receives what the sheen leaves.
+// https://blog.selfshadow.com/publications/s2017-shading-course/imageworks/s2017_pbs_imageworks_sheen.pdf
+pub struct Sheen {
+    albedo: Rc<dyn Texture>,
+    sheen_color: Color3,
+    alpha: f64,
+    // directional albedo of the (white) sheen lobe, indexed by cos theta
+    sheen_albedo: [f64; Sheen::ALBEDO_BINS],
+}
+
+const FON_CONSTANT_1: f64 = 0.5 - 2.0 / (3.0 * PI);
+const FON_CONSTANT_2: f64 = 2.0 / 3.0 - 28.0 / (15.0 * PI);
+
+impl OrenNayar {
+    // roughness in [0, 1], 0 is Lambert
+    pub fn new(color: Color3, roughness: f64) -> Self {
+        Self::textured(SolidColor::new(color), roughness)
+    }
+
+    pub fn textured(texture: impl Texture + 'static, roughness: f64) -> Self {
+        Self {
+            albedo: Rc::new(texture),
+            roughness: roughness.clamp(0.0, 1.0),
+        }
+    }
+
+    // BSDF without the cosine, both directions in the local frame above the surface
+    fn f(&self, rho: &Color3, wo: &Vec3, wi: &Vec3) -> Color3 {
+        let r = self.roughness;
+        let (mu_o, mu_i) = (wo.z, wi.z);
+        let s = Vec3::dot(wi, wo) - mu_i * mu_o;
+        let s_over_t = if s > 0.0 { s / mu_i.max(mu_o) } else { s };
+        let a = 1.0 / (1.0 + FON_CONSTANT_1 * r);
+        let single = rho * (a * (1.0 + r * s_over_t) / PI);
+
+        // energy the single scattering term loses, redistributed with albedo rho_ms
+        let average = a * (1.0 + FON_CONSTANT_2 * r);
+        let rho_ms = rho * rho * average / (Color3::WHITE - rho * (1.0 - average));
+        let eps = 1e-7;
+        let multiple = rho_ms
+            * ((1.0 - fon_albedo(mu_o, r)).max(eps) * (1.0 - fon_albedo(mu_i, r)).max(eps)
+                / ((1.0 - average).max(eps) * PI));
+        single + multiple
+    }
+}
+
+// Directional albedo of the Fujii Oren-Nayar term (fitted).
+fn fon_albedo(mu: f64, r: f64) -> f64 {
+    let m = 1.0 - mu;
+    let g_over_pi = m * (0.0571085289 + m * (0.491881867 + m * (-0.332181442 + m * 0.0714429953)));
+    (1.0 + r * g_over_pi) / (1.0 + FON_CONSTANT_1 * r)
+}
+
+impl Material for OrenNayar {
+    fn reflect(&self, ray: &Ray, hit: &HitRecord) -> Option<(Color3, Ray)> {
+        let frame = Onb::from_w(&hit.normal);
+        let wo = frame.to_local(&-ray.dir.normalize());
+        let wi = sample_cosine();
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return None;
+        }
+        // f * cos / (cos / pi)
+        let attenuation = self.f(&self.albedo.value(hit), &wo, &wi) * PI;
+        Some((attenuation, ray.scattered(hit.point, frame.to_world(&wi))))
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Color3 {
+        self.albedo.value(hit)
+    }
+
+    fn evaluate(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> Color3 {
+        let frame = Onb::from_w(&hit.normal);
+        let wo = frame.to_local(&-ray.dir.normalize());
+        let wi = frame.to_local(&direction.normalize());
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Color3::BLACK;
+        }
+        self.f(&self.albedo.value(hit), &wo, &wi) * wi.z
+    }
+
+    fn pdf(&self, _ray: &Ray, hit: &HitRecord, direction: &Vec3) -> f64 {
+        Vec3::dot(&hit.normal, &direction.normalize()).max(0.0) / PI
+    }
+
+    fn is_specular(&self) -> bool {
+        false
+    }
+}
+
+impl Sheen {
+    const ALBEDO_BINS: usize = 32;
+
+    // roughness in [0, 1], low values give a sharp rim (satin), high ones velvet
+    pub fn new(color: Color3, sheen_color: Color3, roughness: f64) -> Self {
+        Self::textured(SolidColor::new(color), sheen_color, roughness)
+    }
+
+    pub fn textured(texture: impl Texture + 'static, sheen_color: Color3, roughness: f64) -> Self {
+        let mut sheen = Self {
+            albedo: Rc::new(texture),
+            sheen_color,
+            // below this the lobe gets too spiky for the visibility approximation
+            alpha: roughness.clamp(0.0, 1.0).powi(2).max(0.07),
+            sheen_albedo: [0.0; Self::ALBEDO_BINS],
+        };
+        sheen.sheen_albedo = std::array::from_fn(|bin| {
+            let mu_o = (bin as f64 + 0.5) / Self::ALBEDO_BINS as f64;
+            sheen.integrate_sheen(mu_o)
+        });
+        sheen
+    }
+
+    // Charlie distribution with the Ashikhmin visibility term, white
+    fn sheen_lobe(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        let wm = (wo + wi).normalize();
+        let sin_theta = (1.0 - wm.z * wm.z).max(0.0).sqrt();
+        let inv_alpha = 1.0 / self.alpha;
+        let d = (2.0 + inv_alpha) * sin_theta.powf(inv_alpha) / (2.0 * PI);
+        let v = 1.0 / (4.0 * (wi.z + wo.z - wi.z * wo.z));
+        d * v
+    }
+
+    // midpoint quadrature over the hemisphere, only run when building the table
+    fn integrate_sheen(&self, mu_o: f64) -> f64 {
+        let n = 32;
+        let wo = Vec3::new((1.0 - mu_o * mu_o).sqrt(), 0.0, mu_o);
+        let mut sum = 0.0;
+        for i in 0..n {
+            // uniform in cos theta, so the solid angle element is constant
+            let mu_i = (i as f64 + 0.5) / n as f64;
+            let sin_i = (1.0 - mu_i * mu_i).sqrt();
+            for j in 0..n {
+                let phi = 2.0 * PI * (j as f64 + 0.5) / n as f64;
+                let wi = Vec3::new(sin_i * phi.cos(), sin_i * phi.sin(), mu_i);
+                sum += self.sheen_lobe(&wo, &wi) * mu_i;
+            }
+        }
+        (sum * 2.0 * PI / (n * n) as f64).min(1.0)
+    }
+
+    fn sheen_albedo(&self, mu: f64) -> f64 {
+        let bin = (mu * Self::ALBEDO_BINS as f64) as usize;
+        self.sheen_albedo[bin.min(Self::ALBEDO_BINS - 1)]
+    }
+
+    // share of the samples drawn for the sheen lobe
+    fn sheen_probability(&self, mu_o: f64) -> f64 {
+        (luminance(&self.sheen_color) * self.sheen_albedo(mu_o)).clamp(0.1, 0.9)
+    }
+
+    fn f(&self, hit: &HitRecord, wo: &Vec3, wi: &Vec3) -> Color3 {
+        let strength = self
+            .sheen_color
+            .x
+            .max(self.sheen_color.y)
+            .max(self.sheen_color.z);
+        let base = 1.0 - strength * self.sheen_albedo(wo.z).max(self.sheen_albedo(wi.z));
+        self.albedo.value(hit) * (base.max(0.0) / PI) + self.sheen_color * self.sheen_lobe(wo, wi)
+    }
+
+    fn pdf_local(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        if wi.z <= 0.0 {
+            return 0.0;
+        }
+        let p = self.sheen_probability(wo.z);
+        // the sheen half is sampled uniformly over the hemisphere
+        (1.0 - p) * wi.z / PI + p / (2.0 * PI)
+    }
+}
+
+impl Material for Sheen {
+    fn reflect(&self, ray: &Ray, hit: &HitRecord) -> Option<(Color3, Ray)> {
+        let frame = Onb::from_w(&hit.normal);
+        let wo = frame.to_local(&-ray.dir.normalize());
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let mut rng = sampler::rng();
+        let wi = if rng.gen::<f64>() < self.sheen_probability(wo.z) {
+            let z: f64 = rng.gen();
+            let r = (1.0 - z * z).max(0.0).sqrt();
+            let phi = 2.0 * PI * rng.gen::<f64>();
+            Vec3::new(r * phi.cos(), r * phi.sin(), z)
+        } else {
+            sample_cosine()
+        };
+        let pdf = self.pdf_local(&wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let attenuation = self.f(hit, &wo, &wi) * (wi.z / pdf);
+        Some((attenuation, ray.scattered(hit.point, frame.to_world(&wi))))
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Color3 {
+        self.albedo.value(hit)
+    }
+
+    fn evaluate(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> Color3 {
+        let frame = Onb::from_w(&hit.normal);
+        let wo = frame.to_local(&-ray.dir.normalize());
+        let wi = frame.to_local(&direction.normalize());
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Color3::BLACK;
+        }
+        self.f(hit, &wo, &wi) * wi.z
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> f64 {
+        let frame = Onb::from_w(&hit.normal);
+        let wo = frame.to_local(&-ray.dir.normalize());
+        self.pdf_local(&wo, &frame.to_local(&direction.normalize()))
+    }
+
+    fn is_specular(&self) -> bool {
+        false
+    }
+}
+
+// cosine weighted direction around +z
+fn sample_cosine() -> Vec3 {
+    let mut rng = sampler::rng();
+    let (u1, u2): (f64, f64) = (rng.gen(), rng.gen());
+    let r = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+    Vec3::new(r * phi.cos(), r * phi.sin(), (1.0 - u1).max(0.0).sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::{tests::plane_hit, Lambert},
+        vec3::Pos3,
+    };
+
+    // average attenuation of the material's own samples, seen at `cos_theta` from the normal
+    fn directional_albedo(material: &dyn Material, cos_theta: f64) -> Color3 {
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let ray = Ray::new(
+            Pos3::new(-sin_theta, cos_theta, 0.0),
+            Vec3::new(sin_theta, -cos_theta, 0.0),
+        );
+        let hit = plane_hit(Lambert::new(Color3::WHITE), &ray);
+
+        let n = 50_000;
+        let mut sum = Color3::BLACK;
+        for _ in 0..n {
+            if let Some((attenuation, _)) = material.reflect(&ray, &hit) {
+                sum += attenuation;
+            }
+        }
+        sum / n as f64
+    }
+
+    #[test]
+    fn white_oren_nayar_keeps_energy() {
+        for roughness in [0.0, 0.5, 1.0] {
+            let material = OrenNayar::new(Color3::WHITE, roughness);
+            for cos_theta in [0.1, 0.5, 0.9] {
+                let albedo = directional_albedo(&material, cos_theta).x;
+                assert!(
+                    (albedo - 1.0).abs() < 0.02,
+                    "{} {}: {}",
+                    roughness,
+                    cos_theta,
+                    albedo
+                );
+            }
+        }
+        // rough gray looks darker than Lambert at normal incidence (no retro-reflection there)
+        let rough = directional_albedo(&OrenNayar::new(Color3::from_float(0.5), 1.0), 1.0).x;
+        assert!(rough < 0.5);
+    }
+
+    #[test]
+    fn sheen_stays_below_one() {
+        let material = Sheen::new(Color3::WHITE, Color3::WHITE, 0.5);
+        for cos_theta in [0.05, 0.5, 1.0] {
+            let albedo = directional_albedo(&material, cos_theta).x;
+            assert!(albedo < 1.02 && albedo > 0.8, "{}: {}", cos_theta, albedo);
+        }
+        // the rim brightens a dark cloth at grazing angles
+        let velvet = Sheen::new(Color3::from_float(0.05), Color3::WHITE, 0.5);
+        assert!(directional_albedo(&velvet, 0.1).x > directional_albedo(&velvet, 1.0).x);
+    }
+}
@@ -0,0 +1,220 @@
+use std::f64::consts::PI;
+
+use crate::{
+    utils::complex::Complex,
+    vec3::{Color3, Vec3},
+};
+
+// Anisotropic GGX (Trowbridge-Reitz) microfacet distribution. All directions are in
+// the local shading frame: z is the normal, x the tangent.
+// https://pbr-book.org/4ed/Reflection_Models/Roughness_Using_Microfacet_Theory
+#[derive(Copy, Clone, Debug)]
+pub struct Ggx {
+    pub alpha_x: f64,
+    pub alpha_y: f64,
+}
+
+impl Ggx {
+    // below this the lobe is numerically a delta, keep it from collapsing
+    const MIN_ALPHA: f64 = 1e-3;
+
+    pub fn new(roughness: f64, anisotropy: f64) -> Self {
+        let (alpha_x, alpha_y) = Self::remap(roughness, anisotropy);
+        Ggx {
+            alpha_x: alpha_x.max(Self::MIN_ALPHA),
+            alpha_y: alpha_y.max(Self::MIN_ALPHA),
+        }
+    }
+
+    // Disney remapping: alpha = roughness^2, anisotropy stretches it along the tangent.
+    pub fn remap(roughness: f64, anisotropy: f64) -> (f64, f64) {
+        let alpha = roughness.clamp(0.0, 1.0).powi(2);
+        let aspect = (1.0 - 0.9 * anisotropy.clamp(0.0, 1.0)).sqrt();
+        (alpha / aspect, alpha * aspect)
+    }
+
+    pub fn isotropic(alpha: f64) -> Self {
+        Self::anisotropic(alpha, alpha)
+    }
+
+    // alpha_x along the tangent, alpha_y across it
+    pub fn anisotropic(alpha_x: f64, alpha_y: f64) -> Self {
+        Ggx {
+            alpha_x: alpha_x.max(Self::MIN_ALPHA),
+            alpha_y: alpha_y.max(Self::MIN_ALPHA),
+        }
+    }
+
+    // true if the lobe is too narrow to be anything but a mirror
+    pub fn is_smooth(alpha_x: f64, alpha_y: f64) -> bool {
+        alpha_x.max(alpha_y) < Self::MIN_ALPHA
+    }
+
+    // Density of microfacet normals, normalized so the projected area is 1.
+    pub fn d(&self, wm: &Vec3) -> f64 {
+        if wm.z <= 0.0 {
+            return 0.0;
+        }
+        let e = (wm.x / self.alpha_x).powi(2) + (wm.y / self.alpha_y).powi(2) + wm.z * wm.z;
+        1.0 / (PI * self.alpha_x * self.alpha_y * e * e)
+    }
+
+    fn lambda(&self, w: &Vec3) -> f64 {
+        if w.z == 0.0 {
+            return f64::INFINITY;
+        }
+        let tan2 = ((self.alpha_x * w.x).powi(2) + (self.alpha_y * w.y).powi(2)) / (w.z * w.z);
+        ((1.0 + tan2).sqrt() - 1.0) / 2.0
+    }
+
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // height correlated masking-shadowing
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Density of the normals visible from `wo`, this is what `sample_visible` draws from.
+    pub fn pdf_visible(&self, wo: &Vec3, wm: &Vec3) -> f64 {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * self.d(wm) * Vec3::dot(wo, wm).max(0.0) / wo.z
+    }
+
+    // Samples a microfacet normal visible from `wo` (wo.z > 0).
+    // https://jcgt.org/published/0007/04/01/
+    pub fn sample_visible(&self, wo: &Vec3, u1: f64, u2: f64) -> Vec3 {
+        // stretch to the hemisphere configuration
+        let vh = Vec3::new(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z).normalize();
+        let len_sq = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if len_sq > 0.0 {
+            Vec3::new(-vh.y, vh.x, 0.0) / len_sq.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = Vec3::cross(&vh, &t1);
+
+        // uniform disk, warped to the projected visible hemisphere
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+
+        // unstretch
+        Vec3::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(1e-6)).normalize()
+    }
+}
+
+// Unpolarized Fresnel reflectance of a dielectric interface, `eta` is the ratio of the
+// refraction index behind the surface to the one in front of it.
+pub fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let (cos_i, eta) = if cos_theta_i < 0.0 {
+        (-cos_theta_i, 1.0 / eta)
+    } else {
+        (cos_theta_i, eta)
+    };
+    let cos_i = cos_i.min(1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_parallel * r_parallel + r_perpendicular * r_perpendicular) / 2.0
+}
+
+// Unpolarized Fresnel reflectance of a conductor with complex refraction index `eta`
+// (n + ik), seen from a dielectric of index 1.
+pub fn fresnel_complex(cos_theta_i: f64, eta: Complex) -> f64 {
+    let cos_i = Complex::from(cos_theta_i.clamp(0.0, 1.0));
+    let sin2_t = Complex::from(1.0 - cos_theta_i.clamp(0.0, 1.0).powi(2)) / (eta * eta);
+    let cos_t = (Complex::ONE - sin2_t).sqrt();
+    let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_parallel.norm_squared() + r_perpendicular.norm_squared()) / 2.0
+}
+
+pub fn fresnel_schlick(f0: &Color3, cos_theta: f64) -> Color3 {
+    let weight = (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5);
+    f0 + (Color3::WHITE - f0) * weight
+}
+
+// Refracts `wo` through a surface with normal `n` (same side as `wo`), None on total
+// internal reflection.
+pub fn refract(wo: &Vec3, n: &Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = Vec3::dot(wo, n);
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-wo / eta + (cos_i / eta - cos_t) * n)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn visible_normals() {
+        // pdf_visible integrates to 1 and the sampled normals follow it
+        let mut rng = rand::thread_rng();
+        let ggx = Ggx::new(0.5, 0.6);
+        let wo = Vec3::new(0.5, -0.3, 0.8).normalize();
+        let n = 200_000;
+
+        let mut uniform = 0.0;
+        for _ in 0..n {
+            let z: f64 = rng.gen();
+            let phi = 2.0 * PI * rng.gen::<f64>();
+            let r = (1.0 - z * z).sqrt();
+            let wm = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+            uniform += ggx.pdf_visible(&wo, &wm) * 2.0 * PI;
+        }
+        assert!((uniform / n as f64 - 1.0).abs() < 0.02);
+
+        // mean tangent component of the samples matches its expectation under the pdf
+        let mut sampled = 0.0;
+        let mut expected = 0.0;
+        for _ in 0..n {
+            let wm = ggx.sample_visible(&wo, rng.gen(), rng.gen());
+            sampled += wm.x;
+            let z: f64 = rng.gen();
+            let phi = 2.0 * PI * rng.gen::<f64>();
+            let r = (1.0 - z * z).sqrt();
+            let wm = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+            expected += wm.x * ggx.pdf_visible(&wo, &wm) * 2.0 * PI;
+        }
+        assert!((sampled - expected).abs() / (n as f64) < 0.01);
+    }
+
+    #[test]
+    fn fresnel() {
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-9);
+        // same interface seen from inside
+        assert!((fresnel_dielectric(-1.0, 1.0 / 1.5) - 0.04).abs() < 1e-9);
+        // beyond the critical angle
+        assert_eq!(fresnel_dielectric(0.3, 1.0 / 1.5), 1.0);
+
+        // a conductor without absorption is a dielectric
+        for cos in [0.1, 0.5, 1.0] {
+            let conductor = fresnel_complex(cos, Complex::new(1.5, 0.0));
+            assert!((conductor - fresnel_dielectric(cos, 1.5)).abs() < 1e-12);
+        }
+        // and reflects everything once it fully absorbs
+        assert!((fresnel_complex(0.7, Complex::new(0.0, 1e6)) - 1.0).abs() < 1e-5);
+
+        let wo = Vec3::new(0.6, 0.0, 0.8);
+        let wt = refract(&wo, &Vec3::new(0.0, 0.0, 1.0), 1.5).unwrap();
+        assert!((wt.length() - 1.0).abs() < 1e-9);
+        assert!((-wt.x * 1.5 - 0.6).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,988 @@
+use std::{f64::consts::PI, rc::Rc};
+
+use rand::Rng;
+
+use crate::{
+    objects::object::HitRecord,
+    ray::Ray,
+    sampler,
+    spectrum::SampledWavelengths,
+    texture::{SolidColor, Texture},
+    utils::helpers::{
+        ensure_valid_reflection, random_in_unit_sphere_normalized, reflect_vector, reflectance,
+        refract_vector,
+    },
+    vec3::{Color3, Vec3},
+};
+
+pub mod conductor;
+pub mod diffuse;
+pub mod microfacet;
+pub mod principled;
+pub mod subsurface;
+pub mod thin;
+pub mod thin_film;
+
+pub trait Material {
+    fn reflect(&self, ray: &Ray, hit: &HitRecord) -> Option<(Color3, Ray)>;
+    // surface color without lighting, used for the albedo pass and denoising
+    fn albedo(&self, hit: &HitRecord) -> Color3;
+
+    // light leaving the surface on its own towards the ray origin
+    fn emitted(&self, _ray: &Ray, _hit: &HitRecord) -> Color3 {
+        Color3::BLACK
+    }
+
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    // Scattering (BSDF times cosine) of light arriving from `direction` towards the ray origin.
+    // Specular materials can only be sampled through `reflect` and return black.
+    fn evaluate(&self, _ray: &Ray, _hit: &HitRecord, _direction: &Vec3) -> Color3 {
+        Color3::BLACK
+    }
+
+    // Solid angle density of `reflect` scattering towards `direction`, zero if specular.
+    fn pdf(&self, _ray: &Ray, _hit: &HitRecord, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    fn is_specular(&self) -> bool {
+        true
+    }
+
+    // Adjusts a hit before it is shaded, World::hit_objects applies it once per hit: normal and
+    // bump maps replace the shading normal, composite materials replace the material with the
+    // component that handles the hit.
+    fn prepare_hit(&self, _ray: &Ray, _hit: &mut HitRecord) {}
+
+    // Probability that a ray stops at the surface instead of passing straight through,
+    // World::hit_objects consults it before prepare_hit.
+    fn opacity(&self, _hit: &HitRecord) -> f64 {
+        1.0
+    }
+}
+
+// Identity of a material for the material id pass, objects built from the same Rc share it.
+pub fn material_id<M: Material + ?Sized>(material: &Rc<M>) -> usize {
+    Rc::as_ptr(material) as *const () as usize
+}
+
+pub struct Lambert {
+    albedo: Rc<dyn Texture>,
+}
+pub struct Metallic {
+    albedo: Color3,
+    fuzz: f64,
+}
+
+pub struct DiffuseLight {
+    emit: Rc<dyn Texture>,
+}
+
+pub struct Dielectric {
+    refraction_index: f64,
+    dispersion: Option<Dispersion>,
+}
+
+// Any material with its shading normals taken from a normal or bump map. The shading normal
+// is kept from facing away from the viewer, and scattering that the shading normal and the
+// geometric normal disagree about (reflection under the surface, light from below) is dropped,
+// which would otherwise leak light or leave black spots.
+pub struct NormalMapped {
+    material: Rc<dyn Material>,
+    perturbation: NormalPerturbation,
+}
+
+#[derive(Clone)]
+pub enum NormalPerturbation {
+    // tangent space normals encoded as color, (0.5, 0.5, 1) is the unperturbed normal and
+    // green points along v; the texture has to hold non-color data (see ImageTexture::load_data)
+    NormalMap(Rc<dyn Texture>),
+    // height along the normal, the average of the texture channels times `scale`
+    Bump { height: Rc<dyn Texture>, scale: f64 },
+}
+
+// Blend of two materials, `b` weighted by the average of the weight texture channels.
+// Every hit picks one of them with the weight as probability instead of blending their
+// scattering, so a hit is either specular or not for all integrators; averaged over the
+// samples it is the blend.
+pub struct Mix {
+    a: Rc<dyn Material>,
+    b: Rc<dyn Material>,
+    weight: Rc<dyn Texture>,
+}
+
+// Smooth dielectric coat (clear coat, varnish) over a base material. The coat mirrors light
+// with its Fresnel reflectance, the rest reaches the base and crosses the coat again on the
+// way out, tinted by the coat on both passes. Light reflected inside the coat is not traced.
+// Like Mix, a hit is handled either by the coat reflection or by the base.
+pub struct Coated {
+    coat: Rc<dyn Material>,
+    base: Rc<dyn Material>,
+    refraction_index: f64,
+}
+
+// Any material with holes cut by an opacity mask (leaves, fences, decals), the opacity is
+// the average of the mask channels. A ray passes through a hit with the probability of its
+// transparency, so partially transparent texels blend over the samples and shadow rays see
+// the same holes. Light sampling of an emissive Cutout ignores the mask.
+pub struct Cutout {
+    material: Rc<dyn Material>,
+    opacity: Rc<dyn Texture>,
+}
+
+// The base of a Coated material seen through the coat.
+struct CoatedBase {
+    material: Rc<dyn Material>,
+    refraction_index: f64,
+    // transmittance of the coat, in and out
+    tint: Color3,
+}
+
+// Wavelength dependent index of refraction, only used in spectral mode.
+#[derive(Copy, Clone, Debug)]
+pub enum Dispersion {
+    // n = a + b / lambda^2, lambda in micrometers
+    Cauchy { a: f64, b: f64 },
+    // n^2 = 1 + sum(b_i * lambda^2 / (lambda^2 - c_i)), lambda in micrometers
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Lambert {
+    pub fn new(color: Color3) -> Self {
+        Self {
+            albedo: Rc::new(SolidColor::new(color)),
+        }
+    }
+
+    pub fn textured(texture: impl Texture + 'static) -> Self {
+        Self {
+            albedo: Rc::new(texture),
+        }
+    }
+}
+impl Material for Lambert {
+    fn reflect(&self, ray: &Ray, hit: &HitRecord) -> Option<(Color3, Ray)> {
+        let mut scattered_dir = hit.normal + random_in_unit_sphere_normalized();
+        if scattered_dir.near_zero() {
+            scattered_dir = hit.normal;
+        }
+        let scattered_ray = ray.scattered(hit.point, scattered_dir);
+        Some((self.albedo.value(hit), scattered_ray))
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Color3 {
+        self.albedo.value(hit)
+    }
+
+    fn evaluate(&self, _ray: &Ray, hit: &HitRecord, direction: &Vec3) -> Color3 {
+        let cos_theta = Vec3::dot(&hit.normal, &direction.normalize()).max(0.0);
+        self.albedo.value(hit) * (cos_theta / PI)
+    }
+
+    fn pdf(&self, _ray: &Ray, hit: &HitRecord, direction: &Vec3) -> f64 {
+        Vec3::dot(&hit.normal, &direction.normalize()).max(0.0) / PI
+    }
+
+    fn is_specular(&self) -> bool {
+        false
+    }
+}
+
+impl DiffuseLight {
+    pub fn new(color: Color3) -> Self {
+        Self {
+            emit: Rc::new(SolidColor::new(color)),
+        }
+    }
+
+    pub fn textured(texture: impl Texture + 'static) -> Self {
+        Self {
+            emit: Rc::new(texture),
+        }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn reflect(&self, _ray: &Ray, _hit: &HitRecord) -> Option<(Color3, Ray)> {
+        None
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Color3 {
+        self.emit.value(hit)
+    }
+
+    // emits from the front side only
+    fn emitted(&self, _ray: &Ray, hit: &HitRecord) -> Color3 {
+        if hit.front_face {
+            self.emit.value(hit)
+        } else {
+            Color3::BLACK
+        }
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+}
+
+impl Dielectric {
+    pub fn new(refraction_index: f64) -> Self {
+        Self {
+            refraction_index,
+            dispersion: None,
+        }
+    }
+
+    pub fn with_dispersion(dispersion: Dispersion) -> Self {
+        Self {
+            // d-line (587.6 nm) index for rendering in RGB
+            refraction_index: dispersion.refraction_index(587.6),
+            dispersion: Some(dispersion),
+        }
+    }
+
+    fn refraction_index(&self, ray: &Ray) -> (f64, Option<SampledWavelengths>) {
+        match (&self.dispersion, ray.wavelengths) {
+            (Some(dispersion), Some(mut wavelengths)) => {
+                wavelengths.terminate_secondary();
+                (
+                    dispersion.refraction_index(wavelengths.hero()),
+                    Some(wavelengths),
+                )
+            }
+            _ => (self.refraction_index, ray.wavelengths),
+        }
+    }
+}
+
+impl Dispersion {
+    // https://refractiveindex.info/?shelf=glass&book=BK7
+    pub const CROWN_GLASS: Dispersion = Dispersion::Sellmeier {
+        b: [1.03961212, 0.231792344, 1.01046945],
+        c: [0.00600069867, 0.0200179144, 103.560653],
+    };
+    // https://refractiveindex.info/?shelf=glass&book=SF11
+    pub const FLINT_GLASS: Dispersion = Dispersion::Sellmeier {
+        b: [1.73759695, 0.313747346, 1.89878101],
+        c: [0.013188707, 0.0623068142, 155.23629],
+    };
+    // https://refractiveindex.info/?shelf=3d&book=crystals&page=diamond
+    pub const DIAMOND: Dispersion = Dispersion::Sellmeier {
+        b: [0.3306, 4.3356, 0.0],
+        c: [0.030625, 0.011236, 0.0],
+    };
+
+    pub fn refraction_index(&self, lambda_nm: f64) -> f64 {
+        let lambda = lambda_nm / 1000.0;
+        let lambda_sq = lambda * lambda;
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / lambda_sq,
+            Dispersion::Sellmeier { b, c } => {
+                let sum: f64 = (0..3).map(|i| b[i] * lambda_sq / (lambda_sq - c[i])).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+}
+
+impl Default for Metallic {
+    fn default() -> Self {
+        Metallic {
+            albedo: Color3::WHITE,
+            fuzz: 0.0,
+        }
+    }
+}
+
+impl Metallic {
+    pub fn new(color: Color3, fuzz: f64) -> Self {
+        Self {
+            albedo: color,
+            fuzz,
+        }
+    }
+}
+
+impl Material for Metallic {
+    fn reflect(&self, ray: &Ray, hit: &HitRecord) -> Option<(Color3, Ray)> {
+        let reflected = reflect_vector(&ray.dir, &hit.normal);
+        let reflected_fuzzed =
+            reflected.normalize() + self.fuzz * random_in_unit_sphere_normalized();
+
+        let has_same_direction = Vec3::dot(&reflected_fuzzed, &hit.normal) > 0.0;
+        if !has_same_direction {
+            return None;
+        }
+        let reflected_ray = ray.specular_reflection(hit, reflected_fuzzed);
+
+        Some((self.albedo, reflected_ray))
+    }
+
+    fn albedo(&self, _hit: &HitRecord) -> Color3 {
+        self.albedo
+    }
+}
+
+impl Material for Dielectric {
+    fn reflect(&self, ray: &Ray, hit: &HitRecord) -> Option<(Color3, Ray)> {
+        let color = Color3::WHITE;
+        let (refraction_index, wavelengths) = self.refraction_index(ray);
+        let reflection_index = if hit.front_face {
+            1.0 / refraction_index
+        } else {
+            refraction_index
+        };
+
+        let unit_dir = &ray.dir.normalize();
+
+        let cos_theta = Vec3::dot(&-unit_dir, &hit.normal).min(1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+        let cannot_reflect = reflection_index * sin_theta > 1.0;
+        // only calculate reflectance if not yet reflected
+        let perfect_reflection = if !cannot_reflect {
+            reflectance(cos_theta, refraction_index) > sampler::rng().gen()
+        } else {
+            false
+        };
+        let mut refracted_ray = if cannot_reflect || perfect_reflection {
+            ray.specular_reflection(hit, reflect_vector(unit_dir, &hit.normal))
+        } else {
+            let refracted_vec = refract_vector(unit_dir, &hit.normal, reflection_index);
+            ray.specular_transmission(hit, refracted_vec, reflection_index)
+        };
+        refracted_ray.wavelengths = wavelengths;
+        Some((color, refracted_ray))
+    }
+
+    fn albedo(&self, _hit: &HitRecord) -> Color3 {
+        Color3::WHITE
+    }
+}
+
+impl Mix {
+    pub fn new(a: impl Material + 'static, b: impl Material + 'static, weight: f64) -> Self {
+        Self::textured(a, b, SolidColor::new(Color3::from_float(weight)))
+    }
+
+    pub fn textured(
+        a: impl Material + 'static,
+        b: impl Material + 'static,
+        weight: impl Texture + 'static,
+    ) -> Self {
+        Self {
+            a: Rc::new(a),
+            b: Rc::new(b),
+            weight: Rc::new(weight),
+        }
+    }
+
+    fn weight(&self, hit: &HitRecord) -> f64 {
+        let weight = self.weight.value(hit);
+        ((weight.x + weight.y + weight.z) / 3.0).clamp(0.0, 1.0)
+    }
+
+    fn choose(&self, hit: &HitRecord) -> &Rc<dyn Material> {
+        if sampler::rng().gen::<f64>() < self.weight(hit) {
+            &self.b
+        } else {
+            &self.a
+        }
+    }
+
+    fn blend(&self, hit: &HitRecord, f: impl Fn(&dyn Material) -> Color3) -> Color3 {
+        let weight = self.weight(hit);
+        (1.0 - weight) * f(self.a.as_ref()) + weight * f(self.b.as_ref())
+    }
+}
+
+impl Material for Mix {
+    fn reflect(&self, ray: &Ray, hit: &HitRecord) -> Option<(Color3, Ray)> {
+        self.choose(hit).reflect(ray, hit)
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Color3 {
+        self.blend(hit, |material| material.albedo(hit))
+    }
+
+    fn emitted(&self, ray: &Ray, hit: &HitRecord) -> Color3 {
+        self.blend(hit, |material| material.emitted(ray, hit))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.a.is_emissive() || self.b.is_emissive()
+    }
+
+    fn evaluate(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> Color3 {
+        self.blend(hit, |material| material.evaluate(ray, hit, direction))
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> f64 {
+        let weight = self.weight(hit);
+        (1.0 - weight) * self.a.pdf(ray, hit, direction) + weight * self.b.pdf(ray, hit, direction)
+    }
+
+    fn is_specular(&self) -> bool {
+        self.a.is_specular() && self.b.is_specular()
+    }
+
+    fn prepare_hit(&self, ray: &Ray, hit: &mut HitRecord) {
+        let chosen = self.choose(hit).clone();
+        hit.material = chosen.clone();
+        chosen.prepare_hit(ray, hit);
+    }
+
+    fn opacity(&self, hit: &HitRecord) -> f64 {
+        let weight = self.weight(hit);
+        (1.0 - weight) * self.a.opacity(hit) + weight * self.b.opacity(hit)
+    }
+}
+
+impl Coated {
+    pub fn new(base: impl Material + 'static, refraction_index: f64) -> Self {
+        Self::tinted(base, refraction_index, Color3::WHITE)
+    }
+
+    // `tint` is the color of the coat, light crossing it once is multiplied by it.
+    pub fn tinted(base: impl Material + 'static, refraction_index: f64, tint: Color3) -> Self {
+        Self {
+            coat: Rc::new(Metallic::default()),
+            base: Rc::new(CoatedBase {
+                material: Rc::new(base),
+                refraction_index,
+                tint: tint * tint,
+            }),
+            refraction_index,
+        }
+    }
+
+    fn choose(&self, ray: &Ray, hit: &HitRecord) -> &Rc<dyn Material> {
+        let cos_theta = Vec3::dot(&-ray.dir.normalize(), &hit.normal).clamp(0.0, 1.0);
+        if sampler::rng().gen::<f64>() < reflectance(cos_theta, self.refraction_index) {
+            &self.coat
+        } else {
+            &self.base
+        }
+    }
+}
+
+impl Material for Coated {
+    fn reflect(&self, ray: &Ray, hit: &HitRecord) -> Option<(Color3, Ray)> {
+        self.choose(ray, hit).reflect(ray, hit)
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Color3 {
+        self.base.albedo(hit)
+    }
+
+    fn emitted(&self, ray: &Ray, hit: &HitRecord) -> Color3 {
+        self.base.emitted(ray, hit)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.base.is_emissive()
+    }
+
+    fn is_specular(&self) -> bool {
+        self.base.is_specular()
+    }
+
+    fn prepare_hit(&self, ray: &Ray, hit: &mut HitRecord) {
+        let chosen = self.choose(ray, hit).clone();
+        hit.material = chosen.clone();
+        chosen.prepare_hit(ray, hit);
+    }
+
+    fn opacity(&self, hit: &HitRecord) -> f64 {
+        self.base.opacity(hit)
+    }
+}
+
+impl CoatedBase {
+    // Light leaving through the coat towards `direction`.
+    fn transmittance(&self, hit: &HitRecord, direction: &Vec3) -> Color3 {
+        let cos_theta = Vec3::dot(&direction.normalize(), &hit.normal).clamp(0.0, 1.0);
+        (1.0 - reflectance(cos_theta, self.refraction_index)) * self.tint
+    }
+}
+
+impl Material for CoatedBase {
+    fn reflect(&self, ray: &Ray, hit: &HitRecord) -> Option<(Color3, Ray)> {
+        let (attenuation, scattered) = self.material.reflect(ray, hit)?;
+        let transmittance = self.transmittance(hit, &scattered.dir);
+        Some((attenuation * transmittance, scattered))
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Color3 {
+        self.material.albedo(hit) * self.tint
+    }
+
+    fn emitted(&self, ray: &Ray, hit: &HitRecord) -> Color3 {
+        self.material.emitted(ray, hit) * self.transmittance(hit, &-ray.dir)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn evaluate(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> Color3 {
+        self.material.evaluate(ray, hit, direction) * self.transmittance(hit, direction)
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> f64 {
+        self.material.pdf(ray, hit, direction)
+    }
+
+    fn is_specular(&self) -> bool {
+        self.material.is_specular()
+    }
+
+    fn prepare_hit(&self, ray: &Ray, hit: &mut HitRecord) {
+        // a composite base picks its component, which still sits under the coat
+        let own = hit.material.clone();
+        self.material.prepare_hit(ray, hit);
+        if !Rc::ptr_eq(&own, &hit.material) {
+            hit.material = Rc::new(CoatedBase {
+                material: hit.material.clone(),
+                refraction_index: self.refraction_index,
+                tint: self.tint,
+            });
+        }
+    }
+
+    fn opacity(&self, hit: &HitRecord) -> f64 {
+        self.material.opacity(hit)
+    }
+}
+
+impl NormalMapped {
+    pub fn new(material: impl Material + 'static, perturbation: NormalPerturbation) -> Self {
+        Self {
+            material: Rc::new(material),
+            perturbation,
+        }
+    }
+
+    pub fn normal_map(material: impl Material + 'static, map: impl Texture + 'static) -> Self {
+        Self::new(material, NormalPerturbation::NormalMap(Rc::new(map)))
+    }
+
+    pub fn bump_map(
+        material: impl Material + 'static,
+        height: impl Texture + 'static,
+        scale: f64,
+    ) -> Self {
+        Self::new(
+            material,
+            NormalPerturbation::Bump {
+                height: Rc::new(height),
+                scale,
+            },
+        )
+    }
+
+    // Perturbed normal on the outward side of the surface.
+    fn outward_normal(&self, hit: &HitRecord) -> Vec3 {
+        let side = if hit.front_face { 1.0 } else { -1.0 };
+        let mut outward = hit.clone();
+        outward.normal = side * hit.normal;
+        outward.dndu = side * hit.dndu;
+        outward.dndv = side * hit.dndv;
+
+        match &self.perturbation {
+            NormalPerturbation::NormalMap(map) => {
+                let color = map.value(hit);
+                let local = Vec3::new(
+                    2.0 * color.x - 1.0,
+                    2.0 * color.y - 1.0,
+                    2.0 * color.z - 1.0,
+                );
+                outward.tangent_frame().to_world(&local).normalize()
+            }
+            NormalPerturbation::Bump { height, scale } => {
+                // displaced surface p + h(u, v) * n, its partials from finite differences
+                // https://pbr-book.org/3ed-2018/Materials/Bump_Mapping
+                let displacement = |u: f64, v: f64| {
+                    let mut shifted = hit.clone();
+                    shifted.u = u;
+                    shifted.v = v;
+                    let value = height.value(&shifted);
+                    scale * (value.x + value.y + value.z) / 3.0
+                };
+                let (du, dv) = match &hit.footprint {
+                    Some(f) => (
+                        0.5 * (f.dudx.abs() + f.dudy.abs()),
+                        0.5 * (f.dvdx.abs() + f.dvdy.abs()),
+                    ),
+                    None => (0.0, 0.0),
+                };
+                let du = if du > 0.0 { du } else { 0.0005 };
+                let dv = if dv > 0.0 { dv } else { 0.0005 };
+
+                let h = displacement(hit.u, hit.v);
+                let dhdu = (displacement(hit.u + du, hit.v) - h) / du;
+                let dhdv = (displacement(hit.u, hit.v + dv) - h) / dv;
+                let n = outward.normal;
+                let dpdu = hit.dpdu + dhdu * n + h * outward.dndu;
+                let dpdv = hit.dpdv + dhdv * n + h * outward.dndv;
+                let bumped = Vec3::cross(&dpdu, &dpdv);
+                if bumped.near_zero() {
+                    return n;
+                }
+                let bumped = bumped.normalize();
+                if Vec3::dot(&bumped, &n) < 0.0 {
+                    -bumped
+                } else {
+                    bumped
+                }
+            }
+        }
+    }
+
+    // The shading and the geometric normal agree on the side of the surface `direction` is on.
+    fn consistent(hit: &HitRecord, direction: &Vec3) -> bool {
+        let shading = Vec3::dot(direction, &hit.normal) > 0.0;
+        let geometric = Vec3::dot(direction, &hit.geometric_normal) > 0.0;
+        shading == geometric
+    }
+}
+
+impl Material for NormalMapped {
+    fn reflect(&self, ray: &Ray, hit: &HitRecord) -> Option<(Color3, Ray)> {
+        let (attenuation, scattered) = self.material.reflect(ray, hit)?;
+        NormalMapped::consistent(hit, &scattered.dir).then_some((attenuation, scattered))
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Color3 {
+        self.material.albedo(hit)
+    }
+
+    fn emitted(&self, ray: &Ray, hit: &HitRecord) -> Color3 {
+        self.material.emitted(ray, hit)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn evaluate(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> Color3 {
+        if !NormalMapped::consistent(hit, direction) {
+            return Color3::BLACK;
+        }
+        self.material.evaluate(ray, hit, direction)
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> f64 {
+        if !NormalMapped::consistent(hit, direction) {
+            return 0.0;
+        }
+        self.material.pdf(ray, hit, direction)
+    }
+
+    fn is_specular(&self) -> bool {
+        self.material.is_specular()
+    }
+
+    fn prepare_hit(&self, ray: &Ray, hit: &mut HitRecord) {
+        let side = if hit.front_face { 1.0 } else { -1.0 };
+        let normal = side * self.outward_normal(hit);
+        let wo = -ray.dir.normalize();
+        hit.normal = ensure_valid_reflection(&hit.geometric_normal, &wo, &normal).normalize();
+
+        // a composite material picked a component, which keeps the perturbed normal
+        let own = hit.material.clone();
+        self.material.prepare_hit(ray, hit);
+        if !Rc::ptr_eq(&own, &hit.material) {
+            hit.material = Rc::new(NormalMapped {
+                material: hit.material.clone(),
+                perturbation: self.perturbation.clone(),
+            });
+        }
+    }
+
+    fn opacity(&self, hit: &HitRecord) -> f64 {
+        self.material.opacity(hit)
+    }
+}
+
+impl Cutout {
+    pub fn new(material: impl Material + 'static, opacity: f64) -> Self {
+        Self::textured(material, SolidColor::new(Color3::from_float(opacity)))
+    }
+
+    // The mask holds non-color data, load images with ImageTexture::load_data.
+    pub fn textured(material: impl Material + 'static, opacity: impl Texture + 'static) -> Self {
+        Self {
+            material: Rc::new(material),
+            opacity: Rc::new(opacity),
+        }
+    }
+}
+
+impl Material for Cutout {
+    fn reflect(&self, ray: &Ray, hit: &HitRecord) -> Option<(Color3, Ray)> {
+        self.material.reflect(ray, hit)
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Color3 {
+        self.material.albedo(hit)
+    }
+
+    fn emitted(&self, ray: &Ray, hit: &HitRecord) -> Color3 {
+        self.material.emitted(ray, hit)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn evaluate(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> Color3 {
+        self.material.evaluate(ray, hit, direction)
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> f64 {
+        self.material.pdf(ray, hit, direction)
+    }
+
+    fn is_specular(&self) -> bool {
+        self.material.is_specular()
+    }
+
+    fn prepare_hit(&self, ray: &Ray, hit: &mut HitRecord) {
+        self.material.prepare_hit(ray, hit);
+    }
+
+    fn opacity(&self, hit: &HitRecord) -> f64 {
+        let opacity = self.opacity.value(hit);
+        ((opacity.x + opacity.y + opacity.z) / 3.0).clamp(0.0, 1.0) * self.material.opacity(hit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color_space::ColorSpace,
+        environment::Environment,
+        film::Framebuffer,
+        integrator::{path::PathTracer, Integrator},
+        objects::{plane::Plane, sphere::Sphere},
+        texture::ImageTexture,
+        utils::interval::Interval,
+        vec3::Pos3,
+        world::World,
+    };
+
+    // height rising along u
+    struct Ramp;
+
+    impl Texture for Ramp {
+        fn value(&self, hit: &HitRecord) -> Color3 {
+            Color3::from_float(hit.u)
+        }
+    }
+
+    // Where `ray` meets a plane of `material` through the origin, facing up.
+    pub fn plane_hit(material: impl Material + 'static, ray: &Ray) -> HitRecord {
+        let mut world = World::new();
+        world.add_object(Plane::new(Pos3::ZERO, material));
+        world
+            .hit_objects(ray, &Interval::new(0.0001, f64::MAX))
+            .unwrap()
+    }
+
+    fn shading_normal(material: impl Material + 'static, ray: &Ray) -> Vec3 {
+        plane_hit(material, ray).normal
+    }
+
+    fn assert_near(a: &Vec3, b: &Vec3) {
+        assert!((a - b).length() < 1e-6, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn normal_map() {
+        let down = Ray::new(Pos3::new(0.3, 1.0, 0.3), Vec3::new(0.0, -1.0, 0.0));
+        let up = Ray::new(Pos3::new(0.3, -1.0, 0.3), Vec3::new(0.0, 1.0, 0.0));
+
+        let flat = || {
+            NormalMapped::normal_map(
+                Lambert::new(Color3::WHITE),
+                SolidColor::new(Color3::new(0.5, 0.5, 1.0)),
+            )
+        };
+        assert_near(&shading_normal(flat(), &down), &Vec3::new(0.0, 1.0, 0.0));
+
+        // tilted towards u (x on the plane), seen from below the tilt is mirrored with the normal
+        let tilted = || {
+            NormalMapped::normal_map(
+                Lambert::new(Color3::WHITE),
+                SolidColor::new(Color3::new(0.8, 0.5, 0.9)),
+            )
+        };
+        assert_near(&shading_normal(tilted(), &down), &Vec3::new(0.6, 0.8, 0.0));
+        assert_near(&shading_normal(tilted(), &up), &Vec3::new(-0.6, -0.8, 0.0));
+    }
+
+    #[test]
+    fn bump_map() {
+        let down = Ray::new(Pos3::new(0.3, 1.0, 0.3), Vec3::new(0.0, -1.0, 0.0));
+        let bumped = NormalMapped::bump_map(Lambert::new(Color3::WHITE), Ramp, 0.5);
+        // the surface rises along x, the normal leans back
+        let expected = Vec3::new(-0.5, 1.0, 0.0).normalize();
+        assert_near(&shading_normal(bumped, &down), &expected);
+    }
+
+    // share of the hits straight down (or at `cos_theta`) the material hands to a specular component
+    fn specular_share(material: impl Material + 'static, cos_theta: f64) -> f64 {
+        let mut world = World::new();
+        world.add_object(Plane::new(Pos3::ZERO, material));
+        let direction = Vec3::new((1.0 - cos_theta * cos_theta).sqrt(), -cos_theta, 0.0);
+        let ray = Ray::new(Pos3::new(0.0, 1.0, 0.0), direction);
+        let count = 10_000;
+        let specular = (0..count)
+            .filter(|_| {
+                let hit = world
+                    .hit_objects(&ray, &Interval::new(0.0001, f64::MAX))
+                    .unwrap();
+                hit.material.is_specular()
+            })
+            .count();
+        specular as f64 / count as f64
+    }
+
+    #[test]
+    fn composite_materials() {
+        let mix = Mix::new(Lambert::new(Color3::WHITE), Metallic::default(), 0.25);
+        assert!((specular_share(mix, 1.0) - 0.25).abs() < 0.03);
+
+        // the coat reflects little head on, a lot at grazing angles (Schlick: 0.04 and 0.61)
+        let coated = || Coated::new(Lambert::new(Color3::WHITE), 1.5);
+        assert!((specular_share(coated(), 1.0) - 0.04).abs() < 0.01);
+        assert!((specular_share(coated(), 0.1) - 0.61).abs() < 0.03);
+    }
+
+    // opaque on the positive x side
+    struct HalfMask;
+
+    impl Texture for HalfMask {
+        fn value(&self, hit: &HitRecord) -> Color3 {
+            Color3::from_float(if hit.point.x > 0.0 { 1.0 } else { 0.0 })
+        }
+    }
+
+    // share of the rays straight down that stop at a cutout plane above an opaque floor
+    fn cutout_share(cutout: Cutout, x: f64) -> f64 {
+        let mut world = World::new();
+        world.add_object(Plane::new(Pos3::ZERO, cutout));
+        world.add_object(Plane::new(
+            Pos3::new(0.0, -1.0, 0.0),
+            Lambert::new(Color3::WHITE),
+        ));
+        let ray = Ray::new(Pos3::new(x, 1.0, 0.3), Vec3::new(0.0, -1.0, 0.0));
+        let count = 10_000;
+        let stopped = (0..count)
+            .filter(|_| {
+                let hit = world
+                    .hit_objects(&ray, &Interval::new(0.0001, f64::MAX))
+                    .unwrap();
+                hit.point.y > -0.5
+            })
+            .count();
+        stopped as f64 / count as f64
+    }
+
+    #[test]
+    fn cutout() {
+        let masked = || Cutout::textured(Lambert::new(Color3::WHITE), HalfMask);
+        assert_eq!(cutout_share(masked(), 0.5), 1.0);
+        assert_eq!(cutout_share(masked(), -0.5), 0.0);
+
+        // partial opacity is stochastic, also through composite materials
+        let partial = Cutout::new(Lambert::new(Color3::WHITE), 0.3);
+        assert!((cutout_share(partial, 0.5) - 0.3).abs() < 0.03);
+        let mixed = Cutout::new(
+            Mix::new(
+                Lambert::new(Color3::WHITE),
+                Cutout::new(Metallic::default(), 0.0),
+                0.5,
+            ),
+            0.8,
+        );
+        assert!((cutout_share(mixed, 0.5) - 0.4).abs() < 0.03);
+    }
+
+    // mean radiance of a sphere of `material` under a uniform white sky
+    fn furnace(material: impl Material + 'static) -> f64 {
+        let mut white = Framebuffer::new(1, 1);
+        white.set(0, 0, Color3::WHITE);
+        let mut world = World::new();
+        world.environment = Environment::Map(ImageTexture::from_framebuffer(
+            &white,
+            false,
+            ColorSpace::Srgb,
+            ColorSpace::Srgb,
+        ));
+        world.add_object(Sphere::new(Pos3::ZERO, 1.0, material));
+
+        let integrator = PathTracer::new(8);
+        let ray = Ray::new(Pos3::new(0.3, 0.2, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let count = 2000;
+        let sum: f64 = (0..count)
+            .map(|_| integrator.radiance(&ray, &world).total().y)
+            .sum();
+        sum / count as f64
+    }
+
+    #[test]
+    fn white_furnace() {
+        // white materials return all light, the coat never adds any; the environment is
+        // also sampled directly, so single paths only return it on average
+        let mixed = furnace(Mix::new(
+            Lambert::new(Color3::WHITE),
+            Metallic::default(),
+            0.5,
+        ));
+        assert!((mixed - 1.0).abs() < 0.03, "{}", mixed);
+        let coated = furnace(Coated::new(Lambert::new(Color3::WHITE), 1.5));
+        assert!(coated < 1.0 && coated > 0.8, "{}", coated);
+    }
+
+    #[test]
+    fn valid_reflection() {
+        let geometric = Vec3::new(0.0, 1.0, 0.0);
+        let wo = Vec3::new(0.95, 0.1, 0.0).normalize();
+        // a normal leaning away from the viewer would mirror wo below the surface
+        let normal = Vec3::new(-0.6, 0.8, 0.0);
+        assert!(Vec3::dot(&reflect_vector(&-wo, &normal), &geometric) < 0.0);
+
+        let valid = ensure_valid_reflection(&geometric, &wo, &normal);
+        assert!((valid.length() - 1.0).abs() < 1e-9);
+        let reflected = reflect_vector(&-wo, &valid);
+        assert!(Vec3::dot(&reflected, &geometric) >= 0.01 - 1e-9);
+        // and it only turns as far as needed
+        assert!(Vec3::dot(&reflected, &geometric) < 0.02);
+        assert_eq!(
+            ensure_valid_reflection(&geometric, &wo, &geometric),
+            geometric
+        );
+    }
+
+    #[test]
+    fn dispersion() {
+        // BK7 at the d-line
+        let n_d = Dispersion::CROWN_GLASS.refraction_index(587.6);
+        assert!((n_d - 1.5168).abs() < 1e-4);
+
+        // normal dispersion: blue bends more than red
+        for dispersion in [
+            Dispersion::CROWN_GLASS,
+            Dispersion::DIAMOND,
+            Dispersion::Cauchy { a: 1.5, b: 0.004 },
+        ] {
+            assert!(dispersion.refraction_index(450.0) > dispersion.refraction_index(650.0));
+        }
+    }
+}
@@ -0,0 +1,604 @@
+use std::{f64::consts::PI, rc::Rc};
+
+use rand::Rng;
+
+use crate::{
+    material::{
+        microfacet::{fresnel_dielectric, fresnel_schlick, refract, Ggx},
+        Material,
+    },
+    objects::object::HitRecord,
+    ray::Ray,
+    sampler,
+    texture::{SolidColor, Texture},
+    tonemap::luminance,
+    utils::onb::Onb,
+    vec3::{Color3, Vec3},
+};
+
+// Parameters of the principled BSDF, all weights are in [0, 1].
+// https://media.disneyanimation.com/uploads/production/publication_asset/48/asset/s2012_pbs_disney_brdf_notes_v3.pdf
+#[derive(Copy, Clone, Debug)]
+pub struct PrincipledSetup {
+    pub base_color: Color3,
+    pub metallic: f64,
+    pub roughness: f64,
+    // scales the dielectric reflectance given by the IOR, 0.5 keeps it unchanged
+    pub specular: f64,
+    // tints dielectric reflections towards the base color
+    pub specular_tint: f64,
+    // grazing retro-reflection of cloth, tinted towards the base color by sheen_tint
+    pub sheen: f64,
+    pub sheen_tint: f64,
+    // second, white GGX layer on top of everything, gloss sharpens it
+    pub clearcoat: f64,
+    pub clearcoat_gloss: f64,
+    // share of the dielectric that is (rough) glass instead of diffuse
+    pub transmission: f64,
+    pub ior: f64,
+    // stretches highlights along the surface tangent (HitRecord::tangent)
+    pub anisotropic: f64,
+}
+
+impl Default for PrincipledSetup {
+    fn default() -> Self {
+        PrincipledSetup {
+            base_color: Color3::from_float(0.8),
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            specular_tint: 0.0,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_gloss: 1.0,
+            transmission: 0.0,
+            ior: 1.5,
+            anisotropic: 0.0,
+        }
+    }
+}
+
+// One material covering plastics, metals, glass and cloth: a diffuse base, GGX
+// reflection whose color blends from dielectric to metal, rough GGX transmission and
+// a clear coat. The dielectric base only diffuses what its specular reflection leaves,
+// so no parameter combination reflects more energy than it receives.
+// At roughness 0 the metal and glass lobes are deltas, a material that also diffuses
+// or has a clear coat picks per hit between those and the rest, like Coated.
+#[derive(Clone)]
+pub struct Principled {
+    base_color: Rc<dyn Texture>,
+    setup: PrincipledSetup,
+    // None for the delta lobes of a smooth surface
+    distribution: Option<Ggx>,
+    clearcoat_distribution: f64,
+    part: Part,
+}
+
+// The lobes a Principled shades, a smooth one is split in its delta and other lobes.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Part {
+    Whole,
+    // metal and glass reflection and refraction of a smooth surface
+    Specular,
+    // diffuse, sheen and clear coat next to smooth specular lobes
+    Rough,
+}
+
+// Lobe weights and sampling probabilities at one hit, for one outgoing direction.
+struct Lobes {
+    frame: Onb,
+    wo: Vec3,
+    base_color: Color3,
+    specular_f0: Color3,
+    sheen_color: Color3,
+    // refraction index behind the surface over the one in front of it
+    eta: f64,
+    metal: f64,
+    diffuse: f64,
+    glass: f64,
+    coat: f64,
+    // sampling probabilities of diffuse, specular reflection, glass and clear coat
+    probabilities: [f64; 4],
+    // probability that this part of the material was picked for the hit
+    share: f64,
+}
+
+impl Principled {
+    pub fn new(setup: PrincipledSetup) -> Self {
+        Self::textured(setup, SolidColor::new(setup.base_color))
+    }
+
+    // `base_color` replaces setup.base_color
+    pub fn textured(setup: PrincipledSetup, base_color: impl Texture + 'static) -> Self {
+        let clearcoat_gloss = setup.clearcoat_gloss.clamp(0.0, 1.0);
+        let (alpha_x, alpha_y) = Ggx::remap(setup.roughness, setup.anisotropic);
+        let smooth = Ggx::is_smooth(alpha_x, alpha_y);
+        let diffuse =
+            (1.0 - setup.metallic.clamp(0.0, 1.0)) * (1.0 - setup.transmission.clamp(0.0, 1.0));
+        Self {
+            base_color: Rc::new(base_color),
+            distribution: (!smooth).then(|| Ggx::new(setup.roughness, setup.anisotropic)),
+            clearcoat_distribution: 0.1 + (0.001 - 0.1) * clearcoat_gloss,
+            part: if smooth && diffuse == 0.0 && setup.clearcoat <= 0.0 {
+                Part::Specular
+            } else {
+                Part::Whole
+            },
+            setup,
+        }
+    }
+
+    fn view(&self, part: Part) -> Self {
+        Self {
+            part,
+            ..self.clone()
+        }
+    }
+
+    // Picks the delta or the other lobes of a smooth surface by their weights.
+    fn choose(&self, ray: &Ray, hit: &HitRecord) -> Part {
+        let [_, p_specular, p_glass, _] = self.lobes(ray, hit).probabilities;
+        if sampler::rng().gen::<f64>() < p_specular + p_glass {
+            Part::Specular
+        } else {
+            Part::Rough
+        }
+    }
+
+    fn is_split(&self) -> bool {
+        self.part == Part::Whole && self.distribution.is_none()
+    }
+
+    fn lobes(&self, ray: &Ray, hit: &HitRecord) -> Lobes {
+        let setup = &self.setup;
+        let frame = hit.anisotropy_frame();
+        let wo = frame.to_local(&-ray.dir.normalize());
+
+        let base_color = self.base_color.value(hit);
+        let tint = match luminance(&base_color) {
+            l if l > 0.0 => base_color / l,
+            _ => Color3::WHITE,
+        };
+        let f0 = ((setup.ior - 1.0) / (setup.ior + 1.0)).powi(2) * 2.0 * setup.specular;
+        let specular_f0 =
+            (f0 * mix(&Color3::WHITE, &tint, setup.specular_tint)).min(&Color3::WHITE);
+        let sheen_color = mix(&Color3::WHITE, &tint, setup.sheen_tint).min(&Color3::WHITE);
+
+        let metal = setup.metallic.clamp(0.0, 1.0);
+        let transmission = setup.transmission.clamp(0.0, 1.0);
+        let coat = 0.25 * setup.clearcoat.clamp(0.0, 1.0);
+
+        let mut lobes = Lobes {
+            frame,
+            wo,
+            base_color,
+            specular_f0,
+            sheen_color,
+            eta: if hit.front_face {
+                setup.ior
+            } else {
+                1.0 / setup.ior
+            },
+            metal,
+            diffuse: (1.0 - metal) * (1.0 - transmission),
+            glass: (1.0 - metal) * transmission,
+            coat,
+            probabilities: [0.0; 4],
+            share: 0.0,
+        };
+
+        let specular = luminance(&fresnel_schlick(&lobes.specular_f0, wo.z));
+        let below_coat = 1.0 - lobes.coat_fresnel(wo.z);
+        let weights = [
+            below_coat * lobes.diffuse * (1.0 - specular),
+            below_coat * (lobes.metal + lobes.diffuse * specular),
+            below_coat * lobes.glass,
+            lobes.coat_fresnel(wo.z),
+        ];
+        let total: f64 = weights.iter().sum();
+        let kept = match self.part {
+            Part::Whole => weights,
+            Part::Specular => [0.0, weights[1], weights[2], 0.0],
+            Part::Rough => [weights[0], 0.0, 0.0, weights[3]],
+        };
+        let kept_total: f64 = kept.iter().sum();
+        if kept_total > 0.0 {
+            lobes.probabilities = kept.map(|weight| weight / kept_total);
+            lobes.share = kept_total / total;
+        }
+        lobes
+    }
+
+    // BSDF value (without the cosine) for the local incoming direction `wi`.
+    fn f(&self, lobes: &Lobes, wi: &Vec3) -> Color3 {
+        let wo = &lobes.wo;
+        if wo.z <= 0.0 || wi.z == 0.0 || lobes.share == 0.0 || self.part == Part::Specular {
+            return Color3::BLACK;
+        }
+        let below_coat_o = 1.0 - lobes.coat_fresnel(wo.z);
+
+        if wi.z < 0.0 {
+            // only the glass transmits
+            let Some(ggx) = &self.distribution.filter(|_| lobes.glass > 0.0) else {
+                return Color3::BLACK;
+            };
+            let Some(wm) = transmission_half_vector(wo, wi, lobes.eta) else {
+                return Color3::BLACK;
+            };
+            let transmitted = 1.0 - fresnel_dielectric(Vec3::dot(wo, &wm), lobes.eta);
+            let denom = Vec3::dot(wi, &wm) + Vec3::dot(wo, &wm) / lobes.eta;
+            let f = transmitted
+                * ggx.d(&wm)
+                * ggx.g(wo, wi)
+                * (Vec3::dot(wi, &wm) * Vec3::dot(wo, &wm) / (denom * denom * wi.z * wo.z)).abs()
+                / (lobes.eta * lobes.eta);
+            return lobes.base_color * (below_coat_o * lobes.glass * f / lobes.share);
+        }
+
+        let wm = (wo + wi).normalize();
+        let cos_d = Vec3::dot(wi, &wm);
+        // the delta lobes of a smooth surface have no value in any given direction
+        let microfacet = match &self.distribution {
+            Some(ggx) => ggx.d(&wm) * ggx.g(wo, wi) / (4.0 * wi.z * wo.z),
+            None => 0.0,
+        };
+
+        let specular = (fresnel_schlick(&lobes.base_color, cos_d) * lobes.metal
+            + fresnel_schlick(&lobes.specular_f0, cos_d) * lobes.diffuse)
+            * microfacet;
+
+        // the diffuse base gets what the dielectric reflection lets through, both ways
+        let through = (Color3::WHITE - fresnel_schlick(&lobes.specular_f0, wi.z))
+            * (Color3::WHITE - fresnel_schlick(&lobes.specular_f0, wo.z));
+        // sheen fades the base color into the sheen color at grazing angles
+        let sheen = self.setup.sheen.clamp(0.0, 1.0) * (1.0 - cos_d).powi(5);
+        let diffuse =
+            mix(&lobes.base_color, &lobes.sheen_color, sheen) * through * (lobes.diffuse / PI);
+
+        let glass = fresnel_dielectric(Vec3::dot(wo, &wm), lobes.eta) * microfacet * lobes.glass;
+
+        let below_coat = below_coat_o * (1.0 - lobes.coat_fresnel(wi.z));
+        let coat = lobes.coat_fresnel(cos_d)
+            * gtr1(wm.z, self.clearcoat_distribution)
+            * Ggx::isotropic(0.25).g(wo, wi)
+            / (4.0 * wi.z * wo.z);
+
+        ((specular + diffuse + Color3::from_float(glass)) * below_coat + Color3::from_float(coat))
+            / lobes.share
+    }
+
+    fn pdf_local(&self, lobes: &Lobes, wi: &Vec3) -> f64 {
+        let wo = &lobes.wo;
+        let [p_diffuse, p_specular, p_glass, p_coat] = lobes.probabilities;
+        if wo.z <= 0.0 || self.part == Part::Specular {
+            return 0.0;
+        }
+
+        if wi.z < 0.0 {
+            let Some(ggx) = &self.distribution else {
+                return 0.0;
+            };
+            let Some(wm) = transmission_half_vector(wo, wi, lobes.eta) else {
+                return 0.0;
+            };
+            let transmitted = 1.0 - fresnel_dielectric(Vec3::dot(wo, &wm), lobes.eta);
+            let denom = Vec3::dot(wi, &wm) + Vec3::dot(wo, &wm) / lobes.eta;
+            let dwm_dwi = Vec3::dot(wi, &wm).abs() / (denom * denom);
+            return p_glass * transmitted * ggx.pdf_visible(wo, &wm) * dwm_dwi;
+        }
+
+        let wm = (wo + wi).normalize();
+        let reflection = match &self.distribution {
+            Some(ggx) => ggx.pdf_visible(wo, &wm) / (4.0 * Vec3::dot(wo, &wm).abs()),
+            None => 0.0,
+        };
+        let reflected = fresnel_dielectric(Vec3::dot(wo, &wm), lobes.eta);
+        let coat =
+            gtr1(wm.z, self.clearcoat_distribution) * wm.z / (4.0 * Vec3::dot(wo, &wm).abs());
+
+        p_diffuse * wi.z / PI
+            + p_specular * reflection
+            + p_glass * reflected * reflection
+            + p_coat * coat
+    }
+
+    fn sample(&self, lobes: &Lobes) -> Option<Vec3> {
+        let wo = &lobes.wo;
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let mut rng = sampler::rng();
+        let [p_diffuse, p_specular, p_glass, _] = lobes.probabilities;
+        let choice: f64 = rng.gen();
+        let (u1, u2): (f64, f64) = (rng.gen(), rng.gen());
+
+        let wi = if choice < p_diffuse {
+            let r = u1.sqrt();
+            let phi = 2.0 * PI * u2;
+            Vec3::new(r * phi.cos(), r * phi.sin(), (1.0 - u1).max(0.0).sqrt())
+        } else if choice < p_diffuse + p_specular {
+            let wm = self.distribution?.sample_visible(wo, u1, u2);
+            reflect(wo, &wm)
+        } else if choice < p_diffuse + p_specular + p_glass {
+            let wm = self.distribution?.sample_visible(wo, u1, u2);
+            let reflected = fresnel_dielectric(Vec3::dot(wo, &wm), lobes.eta);
+            match refract(wo, &wm, lobes.eta) {
+                Some(wt) if rng.gen::<f64>() >= reflected => wt,
+                _ => reflect(wo, &wm),
+            }
+        } else {
+            let alpha2 = self.clearcoat_distribution.powi(2);
+            let cos_theta = ((1.0 - alpha2.powf(1.0 - u1)) / (1.0 - alpha2)).sqrt();
+            let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+            let phi = 2.0 * PI * u2;
+            let wm = Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+            reflect(wo, &wm)
+        };
+        Some(wi)
+    }
+
+    // Samples the delta lobes of a smooth surface, with their weight f * cos / pdf.
+    fn sample_specular(&self, lobes: &Lobes) -> Option<(Color3, Vec3)> {
+        let wo = &lobes.wo;
+        if wo.z <= 0.0 || lobes.share == 0.0 {
+            return None;
+        }
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        let mirror = Vec3::new(-wo.x, -wo.y, wo.z);
+        let below_coat = 1.0 - lobes.coat_fresnel(wo.z);
+        let [_, p_specular, p_glass, _] = lobes.probabilities;
+
+        let mut rng = sampler::rng();
+        if rng.gen::<f64>() * (p_specular + p_glass) < p_specular {
+            let weight = (fresnel_schlick(&lobes.base_color, wo.z) * lobes.metal
+                + fresnel_schlick(&lobes.specular_f0, wo.z) * lobes.diffuse)
+                * below_coat;
+            return Some((weight / (lobes.share * p_specular), mirror));
+        }
+
+        // Fresnel picks reflection or refraction, so it cancels out of the weight
+        let weight = below_coat * lobes.glass / (lobes.share * p_glass);
+        let reflected = fresnel_dielectric(wo.z, lobes.eta);
+        match refract(wo, &normal, lobes.eta) {
+            Some(wt) if rng.gen::<f64>() >= reflected => {
+                Some((lobes.base_color * (weight / (lobes.eta * lobes.eta)), wt))
+            }
+            _ => Some((Color3::from_float(weight), mirror)),
+        }
+    }
+}
+
+impl Lobes {
+    fn coat_fresnel(&self, cos_theta: f64) -> f64 {
+        if self.coat == 0.0 {
+            return 0.0;
+        }
+        self.coat * fresnel_schlick(&Color3::from_float(0.04), cos_theta.abs()).x
+    }
+}
+
+impl Material for Principled {
+    fn reflect(&self, ray: &Ray, hit: &HitRecord) -> Option<(Color3, Ray)> {
+        if self.is_split() {
+            return self.view(self.choose(ray, hit)).reflect(ray, hit);
+        }
+        let lobes = self.lobes(ray, hit);
+        if self.part == Part::Specular {
+            let (attenuation, wi) = self.sample_specular(&lobes)?;
+            let direction = lobes.frame.to_world(&wi);
+            return Some((attenuation, ray.scattered(hit.point, direction)));
+        }
+        let wi = self.sample(&lobes)?;
+        let pdf = self.pdf_local(&lobes, &wi);
+        if pdf <= 0.0 || !wi.is_finite() {
+            return None;
+        }
+        let attenuation = self.f(&lobes, &wi) * (wi.z.abs() / pdf);
+        let direction = lobes.frame.to_world(&wi);
+        Some((attenuation, ray.scattered(hit.point, direction)))
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Color3 {
+        self.base_color.value(hit)
+    }
+
+    fn evaluate(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> Color3 {
+        let lobes = self.lobes(ray, hit);
+        let wi = lobes.frame.to_local(&direction.normalize());
+        self.f(&lobes, &wi) * wi.z.abs()
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> f64 {
+        let lobes = self.lobes(ray, hit);
+        let wi = lobes.frame.to_local(&direction.normalize());
+        self.pdf_local(&lobes, &wi)
+    }
+
+    fn is_specular(&self) -> bool {
+        self.part == Part::Specular
+    }
+
+    fn prepare_hit(&self, ray: &Ray, hit: &mut HitRecord) {
+        if self.is_split() {
+            hit.material = Rc::new(self.view(self.choose(ray, hit)));
+        }
+    }
+}
+
+fn mix(a: &Color3, b: &Color3, t: f64) -> Color3 {
+    a * (1.0 - t) + b * t
+}
+
+fn reflect(wo: &Vec3, wm: &Vec3) -> Vec3 {
+    2.0 * Vec3::dot(wo, wm) * wm - wo
+}
+
+// Generalized half vector of a refraction, on the side of `wo` (wo.z > 0), None if the
+// pair is not a valid refraction.
+fn transmission_half_vector(wo: &Vec3, wi: &Vec3, eta: f64) -> Option<Vec3> {
+    let wm = wi * eta + wo;
+    if wm.near_zero() {
+        return None;
+    }
+    let mut wm = wm.normalize();
+    if wm.z < 0.0 {
+        wm = -wm;
+    }
+    // both directions have to face the microfacet from opposite sides
+    if Vec3::dot(&wm, wo) <= 0.0 || Vec3::dot(&wm, wi) >= 0.0 {
+        return None;
+    }
+    Some(wm)
+}
+
+// Berry (GTR1) distribution of the clear coat, normalized over projected area.
+fn gtr1(cos_theta: f64, alpha: f64) -> f64 {
+    if cos_theta <= 0.0 {
+        return 0.0;
+    }
+    let alpha2 = alpha * alpha;
+    let t = 1.0 + (alpha2 - 1.0) * cos_theta * cos_theta;
+    (alpha2 - 1.0) / (PI * alpha2.ln() * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::{tests::plane_hit, Lambert},
+        vec3::Pos3,
+    };
+
+    #[test]
+    fn sampling_matches_evaluation() {
+        let ray = Ray::new(Pos3::new(-1.0, 1.5, 0.3), Vec3::new(1.0, -1.5, -0.3));
+        let n = 100_000;
+        let setups = [
+            PrincipledSetup::default(),
+            PrincipledSetup {
+                base_color: Color3::new(0.9, 0.6, 0.3),
+                metallic: 1.0,
+                roughness: 0.6,
+                anisotropic: 0.8,
+                ..Default::default()
+            },
+            PrincipledSetup {
+                base_color: Color3::WHITE,
+                roughness: 0.7,
+                transmission: 1.0,
+                ..Default::default()
+            },
+            PrincipledSetup {
+                base_color: Color3::WHITE,
+                sheen: 1.0,
+                clearcoat: 1.0,
+                clearcoat_gloss: 0.3,
+                ..Default::default()
+            },
+        ];
+
+        let hit = plane_hit(Lambert::new(Color3::WHITE), &ray);
+        for setup in setups {
+            let material = Principled::new(setup);
+
+            // estimated with the material's own sampling ...
+            let mut sampled = Color3::BLACK;
+            for _ in 0..n {
+                if let Some((attenuation, _)) = material.reflect(&ray, &hit) {
+                    sampled += attenuation;
+                }
+            }
+            let sampled = sampled / n as f64;
+
+            // ... and with uniform directions over the sphere
+            let mut evaluated = Color3::BLACK;
+            let mut pdf = 0.0;
+            for _ in 0..n {
+                let direction = Vec3::random(-1.0, 1.0);
+                if direction.length_squared() > 1.0 || direction.near_zero() {
+                    continue;
+                }
+                evaluated += material.evaluate(&ray, &hit, &direction) * 4.0 * PI;
+                pdf += material.pdf(&ray, &hit, &direction) * 4.0 * PI;
+            }
+            // rejection keeps pi / 6 of the candidates
+            let kept = n as f64 * PI / 6.0;
+            let evaluated = evaluated / kept;
+
+            // microfacet reflections below the horizon are lost, so at most 1
+            assert!(pdf / kept < 1.1, "{:?}: pdf {}", setup, pdf / kept);
+            for i in 0..3 {
+                assert!(sampled[i] <= 1.0, "{:?}: albedo {:?}", setup, sampled);
+                assert!(
+                    (sampled[i] - evaluated[i]).abs() < 0.05,
+                    "{:?}: {:?} != {:?}",
+                    setup,
+                    sampled,
+                    evaluated
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn smooth_metal_is_a_mirror() {
+        let ray = Ray::new(Pos3::new(-1.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 0.0));
+        let hit = plane_hit(Lambert::new(Color3::WHITE), &ray);
+        let material = Principled::new(PrincipledSetup {
+            base_color: Color3::WHITE,
+            metallic: 1.0,
+            roughness: 0.0,
+            ..Default::default()
+        });
+        assert!(material.is_specular());
+        let mirror = Vec3::new(1.0, 1.0, 0.0).normalize();
+        for _ in 0..100 {
+            let (attenuation, scattered) = material.reflect(&ray, &hit).unwrap();
+            assert!((attenuation - Color3::WHITE).length() < 1e-3);
+            assert!((scattered.dir.normalize() - mirror).length() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn smooth_plastic_picks_lobes_per_hit() {
+        let ray = Ray::new(Pos3::new(-1.0, 1.5, 0.3), Vec3::new(1.0, -1.5, -0.3));
+        let smooth = PrincipledSetup {
+            roughness: 0.0,
+            clearcoat: 1.0,
+            ..Default::default()
+        };
+        let hit = plane_hit(Lambert::new(Color3::WHITE), &ray);
+        let material = Principled::new(smooth);
+        let nearly_smooth = Principled::new(PrincipledSetup {
+            roughness: 0.05,
+            ..smooth
+        });
+        assert!(!material.is_specular());
+
+        let n = 100_000;
+        let (mut split, mut reference) = (Color3::BLACK, Color3::BLACK);
+        let mut specular = 0;
+        for _ in 0..n {
+            let mut prepared = hit.clone();
+            material.prepare_hit(&ray, &mut prepared);
+            if prepared.material.is_specular() {
+                specular += 1;
+            }
+            if let Some((attenuation, _)) = prepared.material.reflect(&ray, &prepared) {
+                split += attenuation;
+            }
+            if let Some((attenuation, _)) = nearly_smooth.reflect(&ray, &hit) {
+                reference += attenuation;
+            }
+        }
+        assert!(specular > 0 && specular < n);
+        let (split, reference) = (split / n as f64, reference / n as f64);
+        assert!(
+            (split - reference).length() < 0.02,
+            "{:?} != {:?}",
+            split,
+            reference
+        );
+    }
+}
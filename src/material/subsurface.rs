@@ -0,0 +1,180 @@
+use std::rc::Rc;
+
+use rand::Rng;
+
+use crate::{
+    material::Material,
+    objects::object::HitRecord,
+    ray::Ray,
+    sampler,
+    texture::{SolidColor, Texture},
+    utils::{helpers::random_in_unit_sphere_normalized, interval::Interval},
+    vec3::Color3,
+};
+
+// Subsurface scattering by a random walk through the volume of the hit object: the
+// path enters with a diffuse transmission, scatters isotropically inside until it
+// crosses the object's boundary again and leaves diffusely from there. Wrap it in
+// `Coated` for the glossy top of skin or marble.
+// https://graphics.pixar.com/library/PathTracedSubsurface/paper.pdf
+pub struct Subsurface {
+    // color of a thick slab seen from outside (the multiple scattering albedo)
+    color: Rc<dyn Texture>,
+    // average distance light travels inside between two scattering events, per channel
+    mean_free_path: Color3,
+}
+
+impl Subsurface {
+    // the walk is cut off (absorbed) after this many scattering events
+    const MAX_STEPS: usize = 1024;
+
+    pub fn new(color: Color3, mean_free_path: Color3) -> Self {
+        Self::textured(SolidColor::new(color), mean_free_path)
+    }
+
+    pub fn textured(texture: impl Texture + 'static, mean_free_path: Color3) -> Self {
+        Self {
+            color: Rc::new(texture),
+            mean_free_path: mean_free_path.max(&Color3::from_float(1e-6)),
+        }
+    }
+
+    // Single scattering albedo that makes a random walk come out with `color`.
+    // Chiang et al. 2016, "Practical and Controllable Subsurface Scattering for Production"
+    fn single_scattering_albedo(color: &Color3) -> Color3 {
+        let invert = |a: f64| {
+            let a = a.clamp(0.0, 0.999);
+            let x = 4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt();
+            1.0 - x * x
+        };
+        Color3::new(invert(color.x), invert(color.y), invert(color.z))
+    }
+
+    // Walks from the entry point to an exit point of the object, returns the path
+    // weight and the exit hit.
+    fn walk(&self, ray: &Ray, hit: &HitRecord) -> Option<(Color3, HitRecord)> {
+        let object = hit.object.as_ref()?;
+        let mut rng = sampler::rng();
+        let sigma_t = Color3::WHITE / self.mean_free_path;
+        let albedo = Self::single_scattering_albedo(&self.color.value(hit));
+
+        // diffuse transmission into the surface
+        let mut direction = -hit.normal + random_in_unit_sphere_normalized();
+        if direction.near_zero() {
+            direction = -hit.normal;
+        }
+        let mut position = hit.point;
+        let mut weight = Color3::WHITE;
+
+        for _ in 0..Self::MAX_STEPS {
+            // distances are sampled from one channel, weighted by all three (spectral MIS)
+            let channel = rng.gen_range(0..3);
+            let distance = -(1.0 - rng.gen::<f64>()).ln() / sigma_t[channel];
+            let transmittance = Color3::new(
+                (-sigma_t.x * distance).exp(),
+                (-sigma_t.y * distance).exp(),
+                (-sigma_t.z * distance).exp(),
+            );
+
+            let step = ray.scattered(position, direction.normalize());
+            if let Some(exit) = object.hit(&step, &Interval::new(0.0001, distance)) {
+                if exit.front_face {
+                    // a grazing step slipped out of the object, drop the walk
+                    return None;
+                }
+                // reaching the boundary: probability of flying at least this far
+                let transmittance = Color3::new(
+                    (-sigma_t.x * exit.ray_scalar).exp(),
+                    (-sigma_t.y * exit.ray_scalar).exp(),
+                    (-sigma_t.z * exit.ray_scalar).exp(),
+                );
+                let pdf = (transmittance.x + transmittance.y + transmittance.z) / 3.0;
+                weight = weight * transmittance / pdf;
+                return Some((weight, exit));
+            }
+
+            let density = sigma_t * transmittance;
+            let pdf = (density.x + density.y + density.z) / 3.0;
+            weight = weight * albedo * density / pdf;
+            position = step.cast(distance);
+            direction = random_in_unit_sphere_normalized();
+        }
+        None
+    }
+}
+
+impl Material for Subsurface {
+    fn reflect(&self, ray: &Ray, hit: &HitRecord) -> Option<(Color3, Ray)> {
+        if !hit.front_face {
+            // started inside, e.g. a camera in the volume: leave the surface untouched
+            return Some((Color3::WHITE, ray.scattered(hit.point, ray.dir)));
+        }
+        let (weight, exit) = self.walk(ray, hit)?;
+
+        // diffuse transmission out of the exit point, its normal faces inwards
+        let mut direction = -exit.normal + random_in_unit_sphere_normalized();
+        if direction.near_zero() {
+            direction = -exit.normal;
+        }
+        Some((weight, ray.scattered(exit.point, direction)))
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Color3 {
+        self.color.value(hit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        objects::sphere::Sphere,
+        vec3::{Pos3, Vec3},
+        world::World,
+    };
+
+    fn average_weight(color: Color3, mean_free_path: f64) -> (Color3, f64) {
+        let mut world = World::new();
+        world.add_object(Sphere::new(
+            Pos3::ZERO,
+            1.0,
+            Subsurface::new(color, Color3::from_float(mean_free_path)),
+        ));
+        let ray = Ray::new(Pos3::new(0.0, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = world
+            .hit_objects(&ray, &Interval::new(0.0001, f64::MAX))
+            .unwrap();
+
+        let n = 5_000;
+        let mut sum = Color3::BLACK;
+        let mut spread = 0.0;
+        for _ in 0..n {
+            if let Some((weight, scattered)) = hit.material.reflect(&ray, &hit) {
+                sum += weight;
+                spread += (scattered.pos - hit.point).length();
+                // leaves from the sphere surface, outwards
+                assert!((scattered.pos.length() - 1.0).abs() < 1e-6);
+                assert!(Vec3::dot(&scattered.dir, &scattered.pos) > 0.0);
+            }
+        }
+        (sum / n as f64, spread / n as f64)
+    }
+
+    #[test]
+    fn random_walk() {
+        // without absorption everything comes out again
+        let (white, _) = average_weight(Color3::WHITE, 0.1);
+        assert!((white.x - 1.0).abs() < 0.02, "{:?}", white);
+
+        // a thick object reflects about its color, channels stay independent
+        let (colored, _) = average_weight(Color3::new(0.8, 0.5, 0.2), 0.05);
+        for (got, expected) in [(colored.x, 0.8), (colored.y, 0.5), (colored.z, 0.2)] {
+            assert!((got - expected).abs() < 0.1, "{:?}", colored);
+        }
+
+        // longer free paths carry the light further from the entry point
+        let (_, short) = average_weight(Color3::from_float(0.8), 0.05);
+        let (_, long) = average_weight(Color3::from_float(0.8), 0.5);
+        assert!(long > 2.0 * short);
+    }
+}
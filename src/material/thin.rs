@@ -0,0 +1,220 @@
+use std::{f64::consts::PI, rc::Rc};
+
+use rand::Rng;
+
+use crate::{
+    material::{microfacet::fresnel_dielectric, Material},
+    objects::object::HitRecord,
+    ray::Ray,
+    sampler,
+    texture::{SolidColor, Texture},
+    utils::helpers::{random_in_unit_sphere_normalized, reflect_vector},
+    vec3::{Color3, Vec3},
+};
+
+// Dielectric sheet of negligible thickness (window panes, bubbles, planes standing in for
+// glass): light is mirrored or passes straight through without bending, both sides look
+// the same. The reflectance includes the light bouncing back and forth between the faces.
+pub struct ThinDielectric {
+    refraction_index: f64,
+}
+
+// Thin diffuse sheet (paper, leaves, lamp shades) scattering `reflectance` back to the
+// side the light comes from and `transmittance` to the other side.
+pub struct DiffuseTransmission {
+    reflectance: Rc<dyn Texture>,
+    transmittance: Rc<dyn Texture>,
+}
+
+impl ThinDielectric {
+    pub fn new(refraction_index: f64) -> Self {
+        Self { refraction_index }
+    }
+
+    fn reflectance(&self, cos_theta: f64) -> f64 {
+        let r = fresnel_dielectric(cos_theta, self.refraction_index);
+        if r >= 1.0 {
+            return 1.0;
+        }
+        // sum of the paths reflected 1, 3, 5, ... times inside the sheet
+        let t = 1.0 - r;
+        r + t * t * r / (1.0 - r * r)
+    }
+}
+
+impl Material for ThinDielectric {
+    fn reflect(&self, ray: &Ray, hit: &HitRecord) -> Option<(Color3, Ray)> {
+        let unit_dir = ray.dir.normalize();
+        let cos_theta = Vec3::dot(&-unit_dir, &hit.normal).clamp(0.0, 1.0);
+        let scattered = if sampler::rng().gen::<f64>() < self.reflectance(cos_theta) {
+            ray.specular_reflection(hit, reflect_vector(&unit_dir, &hit.normal))
+        } else {
+            ray.specular_transmission(hit, unit_dir, 1.0)
+        };
+        Some((Color3::WHITE, scattered))
+    }
+
+    fn albedo(&self, _hit: &HitRecord) -> Color3 {
+        Color3::WHITE
+    }
+}
+
+impl DiffuseTransmission {
+    pub fn new(reflectance: Color3, transmittance: Color3) -> Self {
+        Self::textured(SolidColor::new(reflectance), SolidColor::new(transmittance))
+    }
+
+    pub fn textured(
+        reflectance: impl Texture + 'static,
+        transmittance: impl Texture + 'static,
+    ) -> Self {
+        Self {
+            reflectance: Rc::new(reflectance),
+            transmittance: Rc::new(transmittance),
+        }
+    }
+
+    // reflectance, transmittance and the probability of sampling the transmission
+    fn lobes(&self, hit: &HitRecord) -> (Color3, Color3, f64) {
+        let reflectance = self.reflectance.value(hit);
+        let transmittance = self.transmittance.value(hit);
+        let (r, t) = (
+            reflectance.x + reflectance.y + reflectance.z,
+            transmittance.x + transmittance.y + transmittance.z,
+        );
+        let probability = if r + t > 0.0 { t / (r + t) } else { 0.5 };
+        (reflectance, transmittance, probability)
+    }
+}
+
+impl Material for DiffuseTransmission {
+    fn reflect(&self, ray: &Ray, hit: &HitRecord) -> Option<(Color3, Ray)> {
+        let (reflectance, transmittance, probability) = self.lobes(hit);
+        // the shading normal faces the incoming ray, transmission leaves around its opposite
+        let (side, weight) = if sampler::rng().gen::<f64>() < probability {
+            (-hit.normal, transmittance / probability)
+        } else {
+            (hit.normal, reflectance / (1.0 - probability))
+        };
+        let mut direction = side + random_in_unit_sphere_normalized();
+        if direction.near_zero() {
+            direction = side;
+        }
+        Some((weight, ray.scattered(hit.point, direction)))
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Color3 {
+        self.reflectance.value(hit)
+    }
+
+    fn evaluate(&self, _ray: &Ray, hit: &HitRecord, direction: &Vec3) -> Color3 {
+        let (reflectance, transmittance, _) = self.lobes(hit);
+        let cos_theta = Vec3::dot(&hit.normal, &direction.normalize());
+        if cos_theta >= 0.0 {
+            reflectance * (cos_theta / PI)
+        } else {
+            transmittance * (-cos_theta / PI)
+        }
+    }
+
+    fn pdf(&self, _ray: &Ray, hit: &HitRecord, direction: &Vec3) -> f64 {
+        let (_, _, probability) = self.lobes(hit);
+        let cos_theta = Vec3::dot(&hit.normal, &direction.normalize());
+        if cos_theta >= 0.0 {
+            (1.0 - probability) * cos_theta / PI
+        } else {
+            probability * -cos_theta / PI
+        }
+    }
+
+    fn is_specular(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::{tests::plane_hit, Lambert},
+        vec3::Pos3,
+    };
+
+    #[test]
+    fn thin_dielectric() {
+        let pane = ThinDielectric::new(1.5);
+        // 4% per face, 2R / (1 + R) for the sheet
+        assert!((pane.reflectance(1.0) - 0.08 / 1.04).abs() < 1e-9);
+        assert!(pane.reflectance(0.01) > 0.9);
+
+        // the same from both sides, transmitted light keeps its direction
+        for y in [1.0, -1.0] {
+            let ray = Ray::new(Pos3::new(-1.0, y, 0.0), Vec3::new(1.0, -y, 0.0));
+            let hit = plane_hit(ThinDielectric::new(1.5), &ray);
+            let n = 10_000;
+            let mut reflected = 0;
+            for _ in 0..n {
+                let (attenuation, scattered) = pane.reflect(&ray, &hit).unwrap();
+                assert_eq!(attenuation, Color3::WHITE);
+                if scattered.dir.y * y > 0.0 {
+                    reflected += 1;
+                } else {
+                    let direction = scattered.dir.normalize();
+                    assert!((direction - ray.dir.normalize()).length() < 1e-9);
+                }
+            }
+            let expected = pane.reflectance(0.5f64.sqrt());
+            assert!(((reflected as f64 / n as f64) - expected).abs() < 0.02);
+        }
+    }
+
+    #[test]
+    fn diffuse_transmission() {
+        let material =
+            || DiffuseTransmission::new(Color3::new(0.6, 0.3, 0.1), Color3::new(0.2, 0.5, 0.0));
+        for y in [1.0, -1.0] {
+            let ray = Ray::new(Pos3::new(-0.5, y, 0.2), Vec3::new(0.5, -y, -0.2));
+            let hit = plane_hit(material(), &ray);
+            let sheet = material();
+            let n = 100_000;
+
+            let (mut sampled, mut transmitted) = (Color3::BLACK, Color3::BLACK);
+            for _ in 0..n {
+                let (attenuation, scattered) = sheet.reflect(&ray, &hit).unwrap();
+                sampled += attenuation;
+                if scattered.dir.y * y < 0.0 {
+                    transmitted += attenuation;
+                }
+            }
+            let (mut evaluated, mut pdf) = (Color3::BLACK, 0.0);
+            for _ in 0..n {
+                // uniform over the sphere
+                let direction = random_in_unit_sphere_normalized();
+                evaluated += sheet.evaluate(&ray, &hit, &direction) * 4.0 * PI;
+                pdf += sheet.pdf(&ray, &hit, &direction) * 4.0 * PI;
+            }
+            let n = n as f64;
+            let expected = Color3::new(0.8, 0.8, 0.1);
+            assert!(
+                (sampled / n - expected).length() < 0.02,
+                "{:?}",
+                sampled / n
+            );
+            assert!(
+                (evaluated / n - expected).length() < 0.03,
+                "{:?}",
+                evaluated / n
+            );
+            assert!((pdf / n - 1.0).abs() < 0.03);
+            // the far side receives the transmittance
+            let transmitted = transmitted / n;
+            assert!((transmitted - Color3::new(0.2, 0.5, 0.0)).length() < 0.02);
+        }
+
+        // opaque materials stay black from behind
+        let ray = Ray::new(Pos3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let hit = plane_hit(Lambert::new(Color3::WHITE), &ray);
+        let behind = Vec3::new(0.0, -1.0, 0.0);
+        assert_eq!(hit.material.evaluate(&ray, &hit, &behind), Color3::BLACK);
+    }
+}
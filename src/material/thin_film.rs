@@ -0,0 +1,238 @@
+use rand::Rng;
+
+use crate::{
+    material::{Dielectric, Material, Metallic},
+    objects::object::HitRecord,
+    ray::Ray,
+    sampler,
+    spectrum::{reflectance_to_rgb, rgb_to_spectrum},
+    utils::{
+        complex::Complex,
+        helpers::{reflect_vector, refract_vector},
+    },
+    vec3::{Color3, Vec3},
+};
+
+// A thin transparent film (soap, oil, anti-reflective coating) on a dielectric or a
+// metal. Waves reflected at the top and the bottom of the film interfere, so the
+// Fresnel reflectance of the base becomes a function of wavelength and angle.
+// https://www.gamedev.net/tutorials/programming/graphics/thin-film-interference-for-computer-graphics-r2962/
+pub struct ThinFilm {
+    base: FilmBase,
+    // RGB reflectance of film and base by cos theta, light from outside and from inside
+    outside: [Color3; ThinFilm::BINS],
+    inside: [Color3; ThinFilm::BINS],
+}
+
+enum FilmBase {
+    Dielectric(Dielectric),
+    Metallic(Metallic),
+}
+
+impl ThinFilm {
+    const BINS: usize = 64;
+
+    // `thickness` in nanometers, a soap bubble is Dielectric::new(1.0) under a film of 1.33
+    pub fn dielectric(base: Dielectric, thickness: f64, refraction_index: f64) -> Self {
+        let n = base.refraction_index;
+        Self::build(
+            FilmBase::Dielectric(base),
+            |lambda, cos| airy_reflectance(1.0, refraction_index, n.into(), thickness, lambda, cos),
+            |lambda, cos| airy_reflectance(n, refraction_index, 1.0.into(), thickness, lambda, cos),
+        )
+    }
+
+    // The metal is modeled as a conductor whose normal incidence reflectance is its albedo.
+    pub fn metallic(base: Metallic, thickness: f64, refraction_index: f64) -> Self {
+        let albedo = base.albedo;
+        let film = move |lambda: f64, cos: f64| {
+            let reflectivity = rgb_to_spectrum(&albedo, lambda);
+            let ior = conductor_ior(reflectivity);
+            airy_reflectance(1.0, refraction_index, ior, thickness, lambda, cos)
+        };
+        Self::build(FilmBase::Metallic(base), film, film)
+    }
+
+    fn build(
+        base: FilmBase,
+        outside: impl Fn(f64, f64) -> f64,
+        inside: impl Fn(f64, f64) -> f64,
+    ) -> Self {
+        let cos = |bin: usize| bin as f64 / (Self::BINS - 1) as f64;
+        Self {
+            base,
+            outside: std::array::from_fn(|bin| {
+                reflectance_to_rgb(|lambda| outside(lambda, cos(bin)))
+            }),
+            inside: std::array::from_fn(|bin| {
+                reflectance_to_rgb(|lambda| inside(lambda, cos(bin)))
+            }),
+        }
+    }
+
+    fn reflectance(&self, cos_theta: f64, front_face: bool) -> Color3 {
+        let table = if front_face {
+            &self.outside
+        } else {
+            &self.inside
+        };
+        let x = cos_theta.clamp(0.0, 1.0) * (Self::BINS - 1) as f64;
+        let i = (x as usize).min(Self::BINS - 2);
+        let t = x - i as f64;
+        table[i] * (1.0 - t) + table[i + 1] * t
+    }
+}
+
+impl Material for ThinFilm {
+    fn reflect(&self, ray: &Ray, hit: &HitRecord) -> Option<(Color3, Ray)> {
+        let unit_dir = ray.dir.normalize();
+        let cos_theta = Vec3::dot(&-unit_dir, &hit.normal).min(1.0);
+        let reflectance = self.reflectance(cos_theta, hit.front_face);
+
+        match &self.base {
+            FilmBase::Metallic(metallic) => {
+                let (_, reflected) = metallic.reflect(ray, hit)?;
+                Some((reflectance, reflected))
+            }
+            FilmBase::Dielectric(dielectric) => {
+                // the film is parallel to the surface, so it does not change the refraction
+                let (refraction_index, wavelengths) = dielectric.refraction_index(ray);
+                let ratio = if hit.front_face {
+                    1.0 / refraction_index
+                } else {
+                    refraction_index
+                };
+                let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+                let probability = ((reflectance.x + reflectance.y + reflectance.z) / 3.0).min(1.0);
+
+                let mut scattered = if ratio * sin_theta > 1.0 {
+                    let reflected = reflect_vector(&unit_dir, &hit.normal);
+                    (Color3::WHITE, ray.specular_reflection(hit, reflected))
+                } else if sampler::rng().gen::<f64>() < probability {
+                    let reflected = reflect_vector(&unit_dir, &hit.normal);
+                    (
+                        reflectance / probability,
+                        ray.specular_reflection(hit, reflected),
+                    )
+                } else {
+                    let refracted = refract_vector(&unit_dir, &hit.normal, ratio);
+                    (
+                        (Color3::WHITE - reflectance) / (1.0 - probability),
+                        ray.specular_transmission(hit, refracted, ratio),
+                    )
+                };
+                scattered.1.wavelengths = wavelengths;
+                Some(scattered)
+            }
+        }
+    }
+
+    fn albedo(&self, hit: &HitRecord) -> Color3 {
+        match &self.base {
+            FilmBase::Dielectric(dielectric) => dielectric.albedo(hit),
+            FilmBase::Metallic(metallic) => metallic.albedo(hit),
+        }
+    }
+}
+
+// Reflectance of a film of index `n2` and `thickness` (nm) between the media `n1` (where
+// the light comes from, at `cos_theta` to the normal) and `n3`, at wavelength `lambda`.
+// Sums all internal reflections of both polarizations (Airy).
+fn airy_reflectance(
+    n1: f64,
+    n2: f64,
+    n3: Complex,
+    thickness: f64,
+    lambda: f64,
+    cos_theta: f64,
+) -> f64 {
+    let (n1, n2) = (Complex::from(n1), Complex::from(n2));
+    let cos1 = Complex::from(cos_theta);
+    // Snell's law, complex beyond the critical angle and in conductors
+    let sin1_squared = Complex::from(1.0 - cos_theta * cos_theta);
+    let cos_in = |n: Complex| (Complex::ONE - sin1_squared * (n1 * n1) / (n * n)).sqrt();
+    let cos2 = cos_in(n2);
+    let cos3 = cos_in(n3);
+
+    // phase difference of one round trip through the film
+    let phase = Complex::new(0.0, 4.0 * std::f64::consts::PI * thickness / lambda) * n2 * cos2;
+    let delay = phase.exp();
+    let airy = |r12: Complex, r23: Complex| {
+        ((r12 + r23 * delay) / (Complex::ONE + r12 * r23 * delay)).norm_squared()
+    };
+
+    let s = airy(
+        (n1 * cos1 - n2 * cos2) / (n1 * cos1 + n2 * cos2),
+        (n2 * cos2 - n3 * cos3) / (n2 * cos2 + n3 * cos3),
+    );
+    let p = airy(
+        (n2 * cos1 - n1 * cos2) / (n2 * cos1 + n1 * cos2),
+        (n3 * cos2 - n2 * cos3) / (n3 * cos2 + n2 * cos3),
+    );
+    ((s + p) / 2.0).clamp(0.0, 1.0)
+}
+
+// Complex index of a conductor with normal incidence `reflectivity`, its edge tint set
+// to the same color.
+// https://jcgt.org/published/0003/04/03/
+fn conductor_ior(reflectivity: f64) -> Complex {
+    let r = reflectivity.clamp(0.0, 0.99);
+    let g = r;
+    let n = g * (1.0 - r) / (1.0 + r) + (1.0 - g) * (1.0 + r.sqrt()) / (1.0 - r.sqrt());
+    let k_squared = (r * (n + 1.0).powi(2) - (n - 1.0).powi(2)) / (1.0 - r);
+    Complex::new(n, k_squared.max(0.0).sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::microfacet::fresnel_dielectric;
+
+    #[test]
+    fn without_film_thickness() {
+        // reduces to the plain Fresnel reflectance of the base
+        let film = ThinFilm::dielectric(Dielectric::new(1.5), 0.0, 1.33);
+        for cos in [0.2, 0.6, 1.0] {
+            let expected = fresnel_dielectric(cos, 1.5);
+            for side in [true, false] {
+                let expected = if side {
+                    expected
+                } else {
+                    fresnel_dielectric(cos, 1.0 / 1.5)
+                };
+                let r = film.reflectance(cos, side);
+                assert!(
+                    (r - Color3::from_float(expected)).length() < 2e-3,
+                    "{:?}",
+                    r
+                );
+            }
+        }
+        // metals keep their albedo head on
+        let gold = Color3::new(1.0, 0.78, 0.34);
+        let film = ThinFilm::metallic(Metallic::new(gold, 0.0), 0.0, 1.5);
+        assert!((film.reflectance(1.0, true) - gold).length() < 0.1);
+    }
+
+    #[test]
+    fn iridescence() {
+        let bubble = ThinFilm::dielectric(Dielectric::new(1.0), 400.0, 1.33);
+        let head_on = bubble.reflectance(1.0, true);
+        let grazing = bubble.reflectance(0.4, true);
+        // colored, and a different color at another angle
+        for i in 0..3 {
+            assert!(head_on[i] <= 1.0 && grazing[i] <= 1.0);
+        }
+        let spread = |c: Color3| c.x.max(c.y).max(c.z) - c.x.min(c.y).min(c.z);
+        assert!(spread(head_on) > 0.02, "{:?}", head_on);
+        assert!((head_on - grazing).length() > 0.02);
+
+        // a quarter wave coating of index sqrt(1.5) on glass cancels green reflections
+        let coating = ThinFilm::dielectric(
+            Dielectric::new(1.5),
+            550.0 / (4.0 * 1.5f64.sqrt()),
+            1.5f64.sqrt(),
+        );
+        assert!(coating.reflectance(1.0, true).y < 0.01);
+    }
+}
@@ -0,0 +1,241 @@
+use std::rc::Rc;
+
+use super::object::{HitRecord, Object};
+use crate::{
+    ray::Ray,
+    utils::{aabb::Aabb, interval::Interval},
+};
+
+// Objects per leaf before a node is split further.
+const MAX_LEAF_SIZE: usize = 2;
+
+// Work done while tracing a ray, shown by the traversal cost debug view.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TraversalStats {
+    pub node_visits: u32,
+    pub intersection_tests: u32,
+}
+
+enum BvhNode {
+    // `count` object indices starting at `start` in Bvh::indices
+    Leaf {
+        bounds: Aabb,
+        start: usize,
+        count: usize,
+    },
+    Interior {
+        bounds: Aabb,
+        left: usize,
+        right: usize,
+        axis: usize,
+    },
+}
+
+// Bounding volume hierarchy over the objects of a world, stored as a flat node list.
+// Objects without bounds (infinite planes) are tested for every ray, participating media
+// are left to World.
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    indices: Vec<usize>,
+    unbounded: Vec<usize>,
+}
+
+impl Bvh {
+    pub fn build(objects: &[Rc<dyn Object>]) -> Self {
+        let mut bounded = Vec::new();
+        let mut unbounded = Vec::new();
+        for (index, object) in objects.iter().enumerate() {
+            if object.is_medium() {
+                continue;
+            }
+            match object.bounding_box() {
+                Some(bounds) => bounded.push((index, bounds)),
+                None => unbounded.push(index),
+            }
+        }
+
+        let mut bvh = Bvh {
+            nodes: Vec::new(),
+            indices: Vec::with_capacity(bounded.len()),
+            unbounded,
+        };
+        if !bounded.is_empty() {
+            bvh.build_node(&mut bounded);
+        }
+        bvh
+    }
+
+    // Bounds of all bounded objects.
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.nodes.first().map(|node| match node {
+            BvhNode::Leaf { bounds, .. } | BvhNode::Interior { bounds, .. } => *bounds,
+        })
+    }
+
+    fn build_node(&mut self, primitives: &mut [(usize, Aabb)]) -> usize {
+        let bounds = primitives
+            .iter()
+            .fold(primitives[0].1, |bounds, (_, b)| bounds.union(b));
+        let node = self.nodes.len();
+
+        if primitives.len() <= MAX_LEAF_SIZE {
+            self.nodes.push(BvhNode::Leaf {
+                bounds,
+                start: self.indices.len(),
+                count: primitives.len(),
+            });
+            self.indices
+                .extend(primitives.iter().map(|(index, _)| *index));
+            return node;
+        }
+
+        // median split along the axis the centroids spread the most
+        let first_centroid = primitives[0].1.centroid();
+        let centroid_bounds = primitives.iter().fold(
+            Aabb::new(first_centroid, first_centroid),
+            |bounds, (_, b)| bounds.union(&Aabb::new(b.centroid(), b.centroid())),
+        );
+        let axis = centroid_bounds.longest_axis();
+        let mid = primitives.len() / 2;
+        primitives.select_nth_unstable_by(mid, |(_, a), (_, b)| {
+            a.centroid()[axis].total_cmp(&b.centroid()[axis])
+        });
+
+        // children are filled in once they are built
+        self.nodes.push(BvhNode::Leaf {
+            bounds,
+            start: 0,
+            count: 0,
+        });
+        let (left_primitives, right_primitives) = primitives.split_at_mut(mid);
+        let left = self.build_node(left_primitives);
+        let right = self.build_node(right_primitives);
+        self.nodes[node] = BvhNode::Interior {
+            bounds,
+            left,
+            right,
+            axis,
+        };
+        node
+    }
+
+    pub fn hit(
+        &self,
+        objects: &[Rc<dyn Object>],
+        ray: &Ray,
+        t_interval: &Interval,
+        stats: &mut TraversalStats,
+    ) -> Option<HitRecord> {
+        let mut hit: Option<HitRecord> = None;
+        let mut nearest_hit = t_interval.max;
+        let mut test_object = |object_id: usize, nearest_hit: &mut f64| {
+            stats.intersection_tests += 1;
+            let interval = Interval::new(t_interval.min, *nearest_hit);
+            if let Some(mut h) = objects[object_id].hit(ray, &interval) {
+                *nearest_hit = h.ray_scalar;
+                h.object_id = object_id;
+                hit = Some(h);
+            }
+        };
+
+        for &object_id in &self.unbounded {
+            test_object(object_id, &mut nearest_hit);
+        }
+
+        let mut node_visits = 0;
+        let mut stack = Vec::with_capacity(32);
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(node) = stack.pop() {
+            node_visits += 1;
+            match &self.nodes[node] {
+                BvhNode::Leaf {
+                    bounds,
+                    start,
+                    count,
+                } => {
+                    if bounds.hit(ray, &Interval::new(t_interval.min, nearest_hit)) {
+                        for &object_id in &self.indices[*start..start + count] {
+                            test_object(object_id, &mut nearest_hit);
+                        }
+                    }
+                }
+                BvhNode::Interior {
+                    bounds,
+                    left,
+                    right,
+                    axis,
+                } => {
+                    if bounds.hit(ray, &Interval::new(t_interval.min, nearest_hit)) {
+                        // visit the nearer child first so the far one can be culled by the hit distance
+                        if ray.dir[*axis] < 0.0 {
+                            stack.push(*left);
+                            stack.push(*right);
+                        } else {
+                            stack.push(*right);
+                            stack.push(*left);
+                        }
+                    }
+                }
+            }
+        }
+
+        stats.node_visits += node_visits;
+        hit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::Lambert,
+        objects::{plane::Plane, sphere::Sphere},
+        vec3::{Color3, Pos3, Vec3},
+    };
+
+    #[test]
+    fn matches_linear_search() {
+        let mut objects: Vec<Rc<dyn Object>> = vec![Rc::new(Plane::new(
+            Pos3::new(0.0, -1.0, 0.0),
+            Lambert::new(Color3::WHITE),
+        ))];
+        for i in 0..50 {
+            let position = Pos3::new(
+                (i % 7) as f64 - 3.0,
+                (i / 7) as f64 * 0.5,
+                -(i as f64) * 0.3,
+            );
+            objects.push(Rc::new(Sphere::new(
+                position,
+                0.2,
+                Lambert::new(Color3::WHITE),
+            )));
+        }
+        let bvh = Bvh::build(&objects);
+        let interval = Interval::new(0.0001, f64::MAX);
+
+        for i in 0..100 {
+            let angle = i as f64 * 0.05;
+            let ray = Ray::new(
+                Pos3::new(0.0, 1.0, 5.0),
+                Vec3::new(angle.sin() * 0.5, angle.cos() * 0.3 - 0.2, -1.0),
+            );
+
+            let mut expected: Option<(usize, f64)> = None;
+            for (object_id, object) in objects.iter().enumerate() {
+                if let Some(h) = object.hit(&ray, &interval) {
+                    if expected.is_none_or(|(_, t)| h.ray_scalar < t) {
+                        expected = Some((object_id, h.ray_scalar));
+                    }
+                }
+            }
+
+            let mut stats = TraversalStats::default();
+            let hit = bvh.hit(&objects, &ray, &interval, &mut stats);
+            assert_eq!(hit.map(|h| h.object_id), expected.map(|(id, _)| id));
+            assert!(stats.intersection_tests < objects.len() as u32);
+        }
+    }
+}
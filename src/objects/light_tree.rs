@@ -0,0 +1,283 @@
+use std::{f64::consts::PI, rc::Rc};
+
+use super::object::Object;
+use crate::{
+    ray::Ray,
+    utils::{aabb::Aabb, distribution::AliasTable},
+    vec3::Pos3,
+};
+
+// Surface points per emitter for the estimate of its power.
+const POWER_SAMPLES: usize = 16;
+
+enum LightNode {
+    // index into LightTree::emitters
+    Leaf {
+        bounds: Aabb,
+        power: f64,
+        emitter: usize,
+    },
+    Interior {
+        bounds: Aabb,
+        power: f64,
+        left: usize,
+        right: usize,
+    },
+}
+
+// Chooses the emitters of a world for light sampling. Light subpaths pick them by their
+// power from an alias table. At a shading point a binary tree over their bounds is walked
+// down towards the clusters with the most power for their distance, so the few lights that
+// matter get the samples in scenes with many of them.
+// https://fpsunflower.github.io/ckulla/data/many-lights-hpg2018.pdf
+pub struct LightTree {
+    nodes: Vec<LightNode>,
+    // parent of each node, for the probability of reaching a leaf
+    parents: Vec<Option<usize>>,
+    // leaf of each emitter, None for those left out of the tree
+    leaves: Vec<Option<usize>>,
+    // object indices of the emitters, in the order of the power table
+    emitters: Vec<usize>,
+    power: Option<AliasTable>,
+}
+
+impl LightTree {
+    pub fn build(objects: &[Rc<dyn Object>], emitters: &[usize]) -> Self {
+        let mut powers: Vec<f64> = emitters
+            .iter()
+            .map(|&object_id| estimate_power(objects[object_id].as_ref()))
+            .collect();
+        // a textured emission the estimate missed still gets some samples
+        let sampleable = powers.iter().filter(|power| **power > 0.0).count().max(1);
+        let floor = 0.01 * powers.iter().sum::<f64>() / sampleable as f64;
+        for (power, &object_id) in powers.iter_mut().zip(emitters) {
+            if *power == 0.0 && objects[object_id].sample_surface().is_some() {
+                *power = floor;
+            }
+        }
+
+        // emitters that can't be sampled are left out of the tree
+        let mut primitives: Vec<(usize, Aabb, f64)> = emitters
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| powers[*i] > 0.0)
+            .filter_map(|(i, &object_id)| Some((i, objects[object_id].bounding_box()?, powers[i])))
+            .collect();
+        let mut tree = LightTree {
+            nodes: Vec::new(),
+            parents: Vec::new(),
+            leaves: vec![None; emitters.len()],
+            emitters: emitters.to_vec(),
+            power: (!powers.is_empty()).then(|| AliasTable::new(&powers)),
+        };
+        if !primitives.is_empty() {
+            tree.build_node(&mut primitives);
+        }
+        tree
+    }
+
+    fn build_node(&mut self, primitives: &mut [(usize, Aabb, f64)]) -> usize {
+        let bounds = primitives
+            .iter()
+            .fold(primitives[0].1, |bounds, (_, b, _)| bounds.union(b));
+        let power = primitives.iter().map(|(_, _, power)| power).sum();
+        let node = self.nodes.len();
+        self.parents.push(None);
+
+        if let [(emitter, _, _)] = primitives {
+            self.nodes.push(LightNode::Leaf {
+                bounds,
+                power,
+                emitter: *emitter,
+            });
+            self.leaves[*emitter] = Some(node);
+            return node;
+        }
+
+        // median split along the axis the centroids spread the most, as in Bvh
+        let first_centroid = primitives[0].1.centroid();
+        let centroid_bounds = primitives.iter().fold(
+            Aabb::new(first_centroid, first_centroid),
+            |bounds, (_, b, _)| bounds.union(&Aabb::new(b.centroid(), b.centroid())),
+        );
+        let axis = centroid_bounds.longest_axis();
+        let mid = primitives.len() / 2;
+        primitives.select_nth_unstable_by(mid, |(_, a, _), (_, b, _)| {
+            a.centroid()[axis].total_cmp(&b.centroid()[axis])
+        });
+
+        // children are filled in once they are built
+        self.nodes.push(LightNode::Leaf {
+            bounds,
+            power,
+            emitter: 0,
+        });
+        let (left_primitives, right_primitives) = primitives.split_at_mut(mid);
+        let left = self.build_node(left_primitives);
+        let right = self.build_node(right_primitives);
+        self.parents[left] = Some(node);
+        self.parents[right] = Some(node);
+        self.nodes[node] = LightNode::Interior {
+            bounds,
+            power,
+            left,
+            right,
+        };
+        node
+    }
+
+    // Emitter (object index) chosen in proportion to its power and the probability of the choice.
+    pub fn sample_power(&self, u: f64) -> Option<(usize, f64)> {
+        let (index, probability) = self.power.as_ref()?.sample(u);
+        Some((self.emitters[index], probability))
+    }
+
+    pub fn power_probability(&self, object_id: usize) -> f64 {
+        match (&self.power, self.emitters.binary_search(&object_id)) {
+            (Some(power), Ok(index)) => power.probability(index),
+            _ => 0.0,
+        }
+    }
+
+    // Emitter (object index) chosen by its estimated contribution at `point` and the
+    // probability of the choice.
+    pub fn sample(&self, point: &Pos3, mut u: f64) -> Option<(usize, f64)> {
+        let mut node = self.nodes.first()?;
+        let mut probability = 1.0;
+        loop {
+            match node {
+                LightNode::Leaf { emitter, .. } => {
+                    return Some((self.emitters[*emitter], probability));
+                }
+                LightNode::Interior { left, right, .. } => {
+                    let p_left = self.p_left(*left, *right, point);
+                    // the rest of `u` picks further down
+                    if u < p_left {
+                        u /= p_left;
+                        probability *= p_left;
+                        node = &self.nodes[*left];
+                    } else {
+                        u = ((u - p_left) / (1.0 - p_left)).min(1.0 - f64::EPSILON);
+                        probability *= 1.0 - p_left;
+                        node = &self.nodes[*right];
+                    }
+                }
+            }
+        }
+    }
+
+    // Probability of `sample` choosing the emitter (object index) at `point`.
+    pub fn pdf(&self, point: &Pos3, object_id: usize) -> f64 {
+        let Ok(index) = self.emitters.binary_search(&object_id) else {
+            return 0.0;
+        };
+        let Some(mut node) = self.leaves[index] else {
+            return 0.0;
+        };
+        let mut probability = 1.0;
+        while let Some(parent) = self.parents[node] {
+            if let LightNode::Interior { left, right, .. } = &self.nodes[parent] {
+                let p_left = self.p_left(*left, *right, point);
+                probability *= if node == *left { p_left } else { 1.0 - p_left };
+            }
+            node = parent;
+        }
+        probability
+    }
+
+    // Probability of descending into the left child at `point`.
+    fn p_left(&self, left: usize, right: usize, point: &Pos3) -> f64 {
+        let importance_left = self.importance(left, point);
+        let importance_right = self.importance(right, point);
+        let total = importance_left + importance_right;
+        if total > 0.0 {
+            importance_left / total
+        } else {
+            0.5
+        }
+    }
+
+    // Power of the cluster over its squared distance, no closer than its own size.
+    fn importance(&self, node: usize, point: &Pos3) -> f64 {
+        let (LightNode::Leaf { bounds, power, .. } | LightNode::Interior { bounds, power, .. }) =
+            &self.nodes[node];
+        let distance_sq = (bounds.centroid() - *point).length_squared();
+        let radius_sq = 0.25 * bounds.extent().length_squared();
+        power / distance_sq.max(radius_sq).max(1e-8)
+    }
+}
+
+// Emitted power (the average of the color channels) from the radiance at a few points
+// on the surface.
+fn estimate_power(object: &dyn Object) -> f64 {
+    let mut radiance = 0.0;
+    for _ in 0..POWER_SAMPLES {
+        let Some(hit) = object.sample_surface() else {
+            return 0.0;
+        };
+        let towards_surface = Ray::new(hit.point + hit.normal, -hit.normal);
+        let emitted = hit.material.emitted(&towards_surface, &hit);
+        radiance += (emitted.x + emitted.y + emitted.z) / 3.0;
+    }
+    radiance / POWER_SAMPLES as f64 * PI * object.area()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::{DiffuseLight, Lambert},
+        objects::{plane::Plane, sphere::Sphere},
+        vec3::Color3,
+    };
+
+    fn light(x: f64, radiance: f64) -> Rc<dyn Object> {
+        Rc::new(Sphere::new(
+            Pos3::new(x, 0.0, 0.0),
+            0.1,
+            DiffuseLight::new(Color3::from_float(radiance)),
+        ))
+    }
+
+    #[test]
+    fn light_choice() {
+        let objects: Vec<Rc<dyn Object>> = vec![
+            light(-10.0, 1.0),
+            Rc::new(Plane::new(Pos3::ZERO, Lambert::new(Color3::WHITE))),
+            light(0.0, 1.0),
+            light(10.0, 3.0),
+            light(11.0, 0.0),
+        ];
+        let tree = LightTree::build(&objects, &[0, 2, 3, 4]);
+
+        // by power, the black light keeps a share
+        let area = 4.0 * PI * 0.01;
+        let floor = 0.01 * 5.0 * PI * area / 3.0;
+        let total = 5.0 * PI * area + floor;
+        assert!((tree.power_probability(3) - 3.0 * PI * area / total).abs() < 1e-12);
+        assert!((tree.power_probability(4) - floor / total).abs() < 1e-12);
+        assert_eq!(tree.power_probability(1), 0.0);
+
+        // near a light it gets most of the samples, the returned probabilities match
+        let point = Pos3::new(-9.0, 1.0, 0.0);
+        let n = 100_000;
+        let mut histogram = [0.0; 5];
+        let mut probabilities = [0.0; 5];
+        for i in 0..n {
+            let u = (i as f64 + 0.5) / n as f64;
+            let (object_id, probability) = tree.sample(&point, u).unwrap();
+            histogram[object_id] += 1.0 / n as f64;
+            probabilities[object_id] = probability;
+        }
+        assert!(histogram[0] > 0.8, "{:?}", histogram);
+        for (object_id, (frequency, probability)) in histogram.iter().zip(probabilities).enumerate()
+        {
+            assert!((frequency - probability).abs() < 1e-3);
+            assert!((tree.pdf(&point, object_id) - frequency).abs() < 1e-3);
+        }
+        assert_eq!(histogram[1], 0.0);
+
+        let empty = LightTree::build(&objects, &[]);
+        assert!(empty.sample(&point, 0.5).is_none() && empty.sample_power(0.5).is_none());
+    }
+}
@@ -0,0 +1,491 @@
+use std::{f64::consts::PI, io::Error, rc::Rc};
+
+use rand::Rng;
+
+use super::object::{HitRecord, Object};
+use crate::{
+    material::{material_id, Material},
+    ray::Ray,
+    sampler,
+    utils::{aabb::Aabb, interval::Interval, onb::Onb},
+    vec3::{Color3, Pos3, Vec3},
+};
+
+// Dense grid of scalar voxels (density, temperature) spanning the unit cube, x varies
+// fastest in memory. Lookups interpolate trilinearly between the voxel centers.
+pub struct VoxelGrid {
+    resolution: [usize; 3],
+    values: Vec<f32>,
+    max: f64,
+}
+
+// Heterogeneous participating medium (smoke, clouds, fire) filling a box: the density
+// grid is stretched over `bounds` and scaled by `sigma_t` into the extinction coefficient.
+// World traces media apart from the surfaces. A hit is a scattering point, sampled by
+// delta tracking against the largest extinction in the grid as majorant, and shadow rays
+// estimate the transmittance by ratio tracking.
+pub struct Medium {
+    bounds: Aabb,
+    density: VoxelGrid,
+    // extinction per unit density and length
+    sigma_t: f64,
+    phase: Rc<PhaseFunction>,
+}
+
+// Henyey-Greenstein scattering at the collisions of a Medium, `g` above 0 scatters
+// forwards. Emission is added at every collision weighted by the absorbed fraction,
+// which makes it the emitted radiance of a fully absorbing (black body) region.
+#[derive(Clone)]
+struct PhaseFunction {
+    // scattering over extinction
+    albedo: Color3,
+    g: f64,
+    bounds: Aabb,
+    emission: Option<(Rc<VoxelGrid>, Color3)>,
+}
+
+impl VoxelGrid {
+    pub fn new(resolution: [usize; 3], values: Vec<f32>) -> Result<Self, Error> {
+        if values.len() != voxel_count(resolution)? {
+            return Err(invalid_data("voxel values do not match the resolution"));
+        }
+        let max = values.iter().fold(0.0f32, |max, value| max.max(*value)) as f64;
+        Ok(Self {
+            resolution,
+            values,
+            max,
+        })
+    }
+
+    // Voxels set from a function of their center in the unit cube.
+    pub fn from_fn(resolution: [usize; 3], f: impl Fn(Pos3) -> f64) -> Result<Self, Error> {
+        let [nx, ny, nz] = resolution;
+        let mut values = Vec::with_capacity(voxel_count(resolution)?);
+        for z in 0..nz {
+            for y in 0..ny {
+                for x in 0..nx {
+                    let center = Pos3::new(
+                        (x as f64 + 0.5) / nx as f64,
+                        (y as f64 + 0.5) / ny as f64,
+                        (z as f64 + 0.5) / nz as f64,
+                    );
+                    values.push(f(center) as f32);
+                }
+            }
+        }
+        Self::new(resolution, values)
+    }
+
+    // Headerless little endian 32 bit floats, as written by most simulation tools.
+    pub fn load_raw(filename: &str, resolution: [usize; 3]) -> Result<Self, Error> {
+        let bytes = std::fs::read(filename)?;
+        let count = voxel_count(resolution)?;
+        if count.checked_mul(4) != Some(bytes.len()) {
+            return Err(invalid_data("raw voxel file does not match the resolution"));
+        }
+        Self::new(resolution, read_floats(&bytes, count))
+    }
+
+    // Dense float grid in the Mitsuba volume format (the usual export of OpenVDB grids to
+    // dense data), with the bounds it was saved with. Only the first channel is kept.
+    pub fn load_vol(filename: &str) -> Result<(Self, Aabb), Error> {
+        let bytes = std::fs::read(filename)?;
+        if bytes.len() < 48 || &bytes[..3] != b"VOL" || bytes[3] != 3 {
+            return Err(invalid_data("not a version 3 volume file"));
+        }
+        let header = |i: usize| i32::from_le_bytes(bytes[4 + 4 * i..8 + 4 * i].try_into().unwrap());
+        // encoding 1 is float32
+        if header(0) != 1 {
+            return Err(invalid_data("only float32 volumes are supported"));
+        }
+        let size = |i: usize| usize::try_from(header(i)).unwrap_or(0);
+        let (resolution, channels) = ([size(1), size(2), size(3)], size(4));
+        let corners = read_floats(&bytes[24..48], 6);
+        let bounds = Aabb::new(
+            Pos3::new(corners[0] as f64, corners[1] as f64, corners[2] as f64),
+            Pos3::new(corners[3] as f64, corners[4] as f64, corners[5] as f64),
+        );
+
+        let count = voxel_count(resolution)?;
+        let floats = count
+            .checked_mul(channels)
+            .filter(|floats| channels > 0 && floats.checked_mul(4) == Some(bytes.len() - 48))
+            .ok_or_else(|| invalid_data("volume data does not match the header"))?;
+        let values = read_floats(&bytes[48..], floats)
+            .into_iter()
+            .step_by(channels)
+            .collect();
+        Ok((Self::new(resolution, values)?, bounds))
+    }
+
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+
+    // `point` in the unit cube, clamped to the outer voxel centers
+    pub fn value(&self, point: &Pos3) -> f64 {
+        let mut base = [0; 3];
+        let mut t = [0.0; 3];
+        for axis in 0..3 {
+            let n = self.resolution[axis];
+            let x = (point[axis] * n as f64 - 0.5).clamp(0.0, (n - 1) as f64);
+            base[axis] = (x as usize).min(n.saturating_sub(2));
+            t[axis] = x - base[axis] as f64;
+        }
+
+        let mut value = 0.0;
+        for corner in 0..8 {
+            let mut index = 0;
+            let mut weight = 1.0;
+            for axis in (0..3).rev() {
+                let offset = (corner >> axis) & 1;
+                let i = (base[axis] + offset).min(self.resolution[axis] - 1);
+                index = index * self.resolution[axis] + i;
+                weight *= if offset == 1 { t[axis] } else { 1.0 - t[axis] };
+            }
+            if weight > 0.0 {
+                value += weight * self.values[index] as f64;
+            }
+        }
+        value
+    }
+}
+
+impl Medium {
+    pub fn new(bounds: Aabb, density: VoxelGrid, sigma_t: f64, albedo: Color3) -> Self {
+        Self {
+            bounds,
+            density,
+            sigma_t,
+            phase: Rc::new(PhaseFunction {
+                albedo,
+                g: 0.0,
+                bounds,
+                emission: None,
+            }),
+        }
+    }
+
+    // `g` in (-1, 1), the mean cosine of the scattering angle
+    pub fn with_anisotropy(self, g: f64) -> Self {
+        self.with_phase(|phase| phase.g = g.clamp(-0.99, 0.99))
+    }
+
+    // Emitted radiance `color` times the grid value (e.g. temperature) over the same bounds.
+    pub fn with_emission(self, emission: VoxelGrid, color: Color3) -> Self {
+        self.with_phase(|phase| phase.emission = Some((Rc::new(emission), color)))
+    }
+
+    fn with_phase(mut self, f: impl FnOnce(&mut PhaseFunction)) -> Self {
+        let mut phase = (*self.phase).clone();
+        f(&mut phase);
+        self.phase = Rc::new(phase);
+        self
+    }
+
+    fn extinction(&self, point: &Pos3) -> f64 {
+        self.sigma_t * self.density.value(&unit_cube(&self.bounds, point))
+    }
+
+    // Distances between tentative collisions inside the medium, sampled against the
+    // majorant; `f` sees each collision until it returns false.
+    fn track(&self, ray: &Ray, t_interval: &Interval, mut f: impl FnMut(f64, f64) -> bool) {
+        let majorant = self.sigma_t * self.density.max();
+        let Some(inside) = self.bounds.clip(ray, t_interval) else {
+            return;
+        };
+        if majorant <= 0.0 {
+            return;
+        }
+        let mut rng = sampler::rng();
+        let speed = ray.dir.length();
+        let mut t = inside.min;
+        loop {
+            t -= (1.0 - rng.gen::<f64>()).ln() / (majorant * speed);
+            if t >= inside.max {
+                return;
+            }
+            // probability of a real collision
+            let real = (self.extinction(&ray.cast(t)) / majorant).clamp(0.0, 1.0);
+            if !f(t, real) {
+                return;
+            }
+        }
+    }
+}
+
+impl Object for Medium {
+    fn hit(&self, ray: &Ray, t_interval: &Interval) -> Option<HitRecord> {
+        let mut collision = None;
+        self.track(ray, t_interval, |t, real| {
+            if sampler::rng().gen::<f64>() < real {
+                collision = Some(t);
+                return false;
+            }
+            true
+        });
+
+        let t = collision?;
+        let facing = -ray.dir.normalize();
+        Some(HitRecord {
+            point: ray.cast(t),
+            normal: facing,
+            geometric_normal: facing,
+            ray_scalar: t,
+            u: 0.0,
+            v: 0.0,
+            dpdu: Vec3::ZERO,
+            dpdv: Vec3::ZERO,
+            dndu: Vec3::ZERO,
+            dndv: Vec3::ZERO,
+            tangent: Vec3::ZERO,
+            footprint: None,
+            front_face: true,
+            material: self.phase.clone(),
+            material_id: material_id(&self.phase),
+            object_id: 0,
+            object: None,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+
+    fn is_medium(&self) -> bool {
+        true
+    }
+
+    fn transmittance(&self, ray: &Ray, t_interval: &Interval) -> f64 {
+        let mut transmittance = 1.0;
+        self.track(ray, t_interval, |_, real| {
+            transmittance *= 1.0 - real;
+            transmittance > 0.0
+        });
+        transmittance
+    }
+}
+
+impl PhaseFunction {
+    // density over the sphere of scattering at `cos_theta` to the propagation direction
+    fn density(&self, cos_theta: f64) -> f64 {
+        let g = self.g;
+        let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+    }
+}
+
+impl Material for PhaseFunction {
+    fn reflect(&self, ray: &Ray, hit: &HitRecord) -> Option<(Color3, Ray)> {
+        let mut rng = sampler::rng();
+        let (u1, u2): (f64, f64) = (rng.gen(), rng.gen());
+        let g = self.g;
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u1
+        } else {
+            let s = (1.0 - g * g) / (1.0 + g - 2.0 * g * u1);
+            ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let phi = 2.0 * PI * u2;
+        let local = Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+        let direction = Onb::from_w(&ray.dir).to_world(&local);
+        Some((self.albedo, ray.scattered(hit.point, direction)))
+    }
+
+    fn albedo(&self, _hit: &HitRecord) -> Color3 {
+        self.albedo
+    }
+
+    fn emitted(&self, _ray: &Ray, hit: &HitRecord) -> Color3 {
+        let Some((grid, color)) = &self.emission else {
+            return Color3::BLACK;
+        };
+        let absorbed = (Color3::WHITE - self.albedo).max(&Color3::BLACK);
+        *color * absorbed * grid.value(&unit_cube(&self.bounds, &hit.point))
+    }
+
+    // there is no cosine inside a medium, only the phase function
+    fn evaluate(&self, ray: &Ray, hit: &HitRecord, direction: &Vec3) -> Color3 {
+        self.albedo * self.pdf(ray, hit, direction)
+    }
+
+    fn pdf(&self, ray: &Ray, _hit: &HitRecord, direction: &Vec3) -> f64 {
+        self.density(Vec3::dot(&ray.dir.normalize(), &direction.normalize()))
+    }
+
+    fn is_specular(&self) -> bool {
+        false
+    }
+}
+
+// position of `point` in `bounds` mapped to the unit cube
+fn unit_cube(bounds: &Aabb, point: &Pos3) -> Pos3 {
+    (*point - bounds.min) / bounds.extent()
+}
+
+fn read_floats(bytes: &[u8], count: usize) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .take(count)
+        .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+        .collect()
+}
+
+// Number of voxels of a grid, every dimension needs at least one.
+fn voxel_count(resolution: [usize; 3]) -> Result<usize, Error> {
+    resolution
+        .iter()
+        .try_fold(1usize, |count, &n| count.checked_mul(n).filter(|_| n > 0))
+        .ok_or_else(|| invalid_data("voxel grid resolution is empty or too large"))
+}
+
+fn invalid_data(message: &str) -> Error {
+    Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{material::Lambert, objects::plane::Plane, world::World};
+
+    fn unit_box() -> Aabb {
+        Aabb::new(Pos3::from_float(-1.0), Pos3::from_float(1.0))
+    }
+
+    #[test]
+    fn voxel_grid() {
+        // linear along x is reproduced between the outer voxel centers
+        let ramp = VoxelGrid::from_fn([8, 2, 3], |p| p.x).unwrap();
+        assert!((ramp.value(&Pos3::new(0.4, 0.3, 0.9)) - 0.4).abs() < 1e-6);
+        assert!((ramp.value(&Pos3::new(0.0, 0.5, 0.5)) - 1.0 / 16.0).abs() < 1e-6);
+        assert!((ramp.max() - 15.0 / 16.0).abs() < 1e-6);
+
+        let values: Vec<f32> = (0..2 * 3 * 4).map(|i| i as f32).collect();
+        let floats: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        let path = std::env::temp_dir().join("rust_raytracing_voxel_grid.raw");
+        let filename = path.to_str().unwrap();
+        std::fs::write(filename, &floats).unwrap();
+        let raw = VoxelGrid::load_raw(filename, [2, 3, 4]).unwrap();
+        assert_eq!(raw.values, values);
+        assert!(VoxelGrid::load_raw(filename, [2, 3, 3]).is_err());
+        assert!(VoxelGrid::load_raw(filename, [0, 3, 4]).is_err());
+        assert!(VoxelGrid::new([2, 3, 3], values.clone()).is_err());
+
+        let mut vol = b"VOL\x03".to_vec();
+        for header in [1i32, 2, 3, 4, 1] {
+            vol.extend(header.to_le_bytes());
+        }
+        for corner in [-1.0f32, 0.0, 0.0, 1.0, 3.0, 2.0] {
+            vol.extend(corner.to_le_bytes());
+        }
+        vol.extend(&floats);
+        let path = std::env::temp_dir().join("rust_raytracing_voxel_grid.vol");
+        let filename = path.to_str().unwrap();
+        std::fs::write(filename, &vol).unwrap();
+        let (grid, bounds) = VoxelGrid::load_vol(filename).unwrap();
+        assert_eq!(grid.values, values);
+        assert_eq!(bounds.max, Pos3::new(1.0, 3.0, 2.0));
+
+        // sizes from the header are checked before they are multiplied
+        vol[12..16].copy_from_slice(&i32::MAX.to_le_bytes());
+        vol[20..24].copy_from_slice(&i32::MAX.to_le_bytes());
+        std::fs::write(filename, &vol).unwrap();
+        assert!(VoxelGrid::load_vol(filename).is_err());
+    }
+
+    #[test]
+    fn tracking() {
+        let homogeneous = Medium::new(
+            unit_box(),
+            VoxelGrid::from_fn([1, 1, 1], |_| 1.0).unwrap(),
+            0.7,
+            Color3::WHITE,
+        );
+        // denser towards +x, optical depth sigma_t * 1 across the box
+        let ramp = Medium::new(
+            unit_box(),
+            VoxelGrid::from_fn([64, 1, 1], |p| p.x).unwrap(),
+            0.9,
+            Color3::WHITE,
+        );
+        let ray = Ray::new(Pos3::new(-5.0, 0.2, 0.1), Vec3::new(2.0, 0.0, 0.0));
+        let interval = Interval::new(0.0001, f64::MAX);
+
+        for (medium, expected) in [(homogeneous, (-1.4f64).exp()), (ramp, (-0.9f64).exp())] {
+            let n = 20_000;
+            let mut transmitted = 0.0;
+            let mut passed = 0;
+            for _ in 0..n {
+                transmitted += medium.transmittance(&ray, &interval);
+                match medium.hit(&ray, &interval) {
+                    Some(hit) => assert!(hit.point.x.abs() <= 1.0),
+                    None => passed += 1,
+                }
+            }
+            let n = n as f64;
+            assert!(
+                (transmitted / n - expected).abs() < 0.01,
+                "{}",
+                transmitted / n
+            );
+            assert!((passed as f64 / n - expected).abs() < 0.02);
+        }
+
+        // the world stops at surfaces inside the medium, shadow rays see the surface
+        let mut world = World::new();
+        world.add_object(Medium::new(
+            unit_box(),
+            VoxelGrid::from_fn([1, 1, 1], |_| 1.0).unwrap(),
+            5.0,
+            Color3::WHITE,
+        ));
+        world.add_object(Plane::new(
+            Pos3::new(0.0, 0.99, 0.0),
+            Lambert::new(Color3::WHITE),
+        ));
+        let down = Ray::new(Pos3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        for _ in 0..100 {
+            let hit = world.hit_objects(&down, &interval).unwrap();
+            assert!(hit.point.y >= 0.99 - 1e-9);
+        }
+        let up = Ray::new(Pos3::new(0.0, -5.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let surface = world.hit_surfaces(&up, &interval).unwrap();
+        assert!(surface.object_id == 1 && (surface.point.y - 0.99).abs() < 1e-9);
+        let through = world.transmittance(&up, &Interval::new(0.0001, surface.ray_scalar));
+        assert!(through < 1e-3);
+    }
+
+    #[test]
+    fn phase_function() {
+        let phase = PhaseFunction {
+            albedo: Color3::WHITE,
+            g: 0.6,
+            bounds: unit_box(),
+            emission: None,
+        };
+        let ray = Ray::new(Pos3::ZERO, Vec3::new(0.0, 0.0, 2.0));
+        let hit = Medium::new(
+            unit_box(),
+            VoxelGrid::from_fn([1, 1, 1], |_| 100.0).unwrap(),
+            1.0,
+            Color3::WHITE,
+        )
+        .hit(&ray, &Interval::new(0.0, f64::MAX))
+        .unwrap();
+
+        let n = 50_000;
+        let (mut mean_cos, mut integral) = (0.0, 0.0);
+        for _ in 0..n {
+            let (_, scattered) = phase.reflect(&ray, &hit).unwrap();
+            mean_cos += scattered.dir.normalize().z;
+            let z = 1.0 - 2.0 * rand::random::<f64>();
+            let r = (1.0 - z * z).sqrt();
+            let phi = 2.0 * PI * rand::random::<f64>();
+            let direction = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+            integral += phase.pdf(&ray, &hit, &direction) * 4.0 * PI;
+        }
+        assert!((mean_cos / n as f64 - 0.6).abs() < 0.01);
+        assert!((integral / n as f64 - 1.0).abs() < 0.05);
+    }
+}
@@ -1,3 +1,6 @@
+pub mod bvh;
+pub mod light_tree;
+pub mod medium;
 pub mod object;
 pub mod plane;
 pub mod sphere;
@@ -3,20 +3,98 @@ use std::rc::Rc;
 use crate::{
     material::Material,
     ray::Ray,
-    utils::interval::Interval,
+    utils::{aabb::Aabb, interval::Interval, onb::Onb},
     vec3::{Pos3, Vec3},
 };
 
+#[derive(Clone)]
 pub struct HitRecord {
     pub point: Pos3,
+    // shading normal, perturbed by normal and bump maps; both normals face the ray origin
     pub normal: Vec3,
+    pub geometric_normal: Vec3,
     pub ray_scalar: f64,
+    // surface (texture) coordinates
+    pub u: f64,
+    pub v: f64,
+    // partial derivatives of the point and the (outward) normal by the surface coordinates
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+    pub dndu: Vec3,
+    pub dndv: Vec3,
+    // direction anisotropic materials stretch their highlights along (brushing), in
+    // or near the tangent plane; dpdu unless the object has its own rule
+    pub tangent: Vec3,
+    // set by World::hit_objects for rays with differentials
+    pub footprint: Option<Footprint>,
     pub front_face: bool,
     pub material: Rc<dyn Material>,
+    // the material the object was built with, see material::material_id
+    pub material_id: usize,
+    // index of the object in the world, assigned by World::hit_objects
+    pub object_id: usize,
+    // the object itself, for materials that trace its boundary (subsurface scattering)
+    pub object: Option<Rc<dyn Object>>,
+}
+
+// Change of the hit point and its surface coordinates from one pixel to the next,
+// in x and y on the film.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Footprint {
+    pub dpdx: Vec3,
+    pub dpdy: Vec3,
+    pub dudx: f64,
+    pub dvdx: f64,
+    pub dudy: f64,
+    pub dvdy: f64,
 }
 
 pub trait Object {
     fn hit(&self, ray: &Ray, t_interval: &Interval) -> Option<HitRecord>;
+    // None for unbounded objects, which are tested for every ray
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+
+    // Emissive objects are sampled directly by the integrators that do light sampling.
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    // Specular objects are the targets of caustic photons.
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    // Direction (normalized) from `origin` towards a point on the object and its solid angle pdf.
+    fn sample_direction(&self, _origin: &Pos3) -> Option<(Vec3, f64)> {
+        None
+    }
+
+    // Solid angle pdf of sample_direction choosing the (normalized) `direction`.
+    fn direction_pdf(&self, _origin: &Pos3, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    // Uniformly distributed point on the surface, seen from outside (front face).
+    fn sample_surface(&self) -> Option<HitRecord> {
+        None
+    }
+
+    fn area(&self) -> f64 {
+        f64::INFINITY
+    }
+
+    // Participating media are traced by World apart from the surfaces, their hits are
+    // scattering points inside.
+    fn is_medium(&self) -> bool {
+        false
+    }
+
+    // Estimate of the fraction of light crossing the object along the ray, for media.
+    fn transmittance(&self, _ray: &Ray, _t_interval: &Interval) -> f64 {
+        1.0
+    }
 }
 
 impl HitRecord {
@@ -27,9 +105,93 @@ impl HitRecord {
         self.normal = if self.front_face {
             *outward_normal
         } else {
+            self.dndu = -self.dndu;
+            self.dndv = -self.dndv;
             -*outward_normal
         };
+        self.geometric_normal = self.normal;
 
         &self.normal
     }
+
+    // Shading frame with `u` along dpdu and `v` towards dpdv, `w` is the shading normal.
+    pub fn tangent_frame(&self) -> Onb {
+        let tangent = self.dpdu - Vec3::dot(&self.dpdu, &self.normal) * self.normal;
+        if tangent.near_zero() {
+            return Onb::from_w(&self.normal);
+        }
+        let u = tangent.normalize();
+        let mut v = Vec3::cross(&self.normal, &u);
+        if Vec3::dot(&v, &self.dpdv) < 0.0 {
+            v = -v;
+        }
+        Onb {
+            u,
+            v,
+            w: self.normal,
+        }
+    }
+
+    // Shading frame with `u` along the tangent, for anisotropic scattering.
+    pub fn anisotropy_frame(&self) -> Onb {
+        let tangent = self.tangent - Vec3::dot(&self.tangent, &self.normal) * self.normal;
+        if tangent.near_zero() {
+            return self.tangent_frame();
+        }
+        let u = tangent.normalize();
+        Onb {
+            u,
+            v: Vec3::cross(&self.normal, &u),
+            w: self.normal,
+        }
+    }
+
+    // Footprint of the ray differentials on the tangent plane of the hit.
+    // https://pbr-book.org/3ed-2018/Texture/Sampling_and_Antialiasing#FindingtheTextureSamplingRate
+    pub fn compute_footprint(&mut self, ray: &Ray) {
+        self.footprint = None;
+        let Some(differentials) = &ray.differentials else {
+            return;
+        };
+        let plane_distance = Vec3::dot(&self.normal, &self.point);
+        let offset = |origin: &Pos3, direction: &Vec3| {
+            let t = (plane_distance - Vec3::dot(&self.normal, origin))
+                / Vec3::dot(&self.normal, direction);
+            (*origin + t * *direction) - self.point
+        };
+        let dpdx = offset(&differentials.rx_origin, &differentials.rx_direction);
+        let dpdy = offset(&differentials.ry_origin, &differentials.ry_direction);
+        if !(dpdx.is_finite() && dpdy.is_finite()) {
+            return;
+        }
+
+        // least squares solution of dp = du * dpdu + dv * dpdv
+        let (a, b, c) = (
+            Vec3::dot(&self.dpdu, &self.dpdu),
+            Vec3::dot(&self.dpdu, &self.dpdv),
+            Vec3::dot(&self.dpdv, &self.dpdv),
+        );
+        let determinant = a * c - b * b;
+        let solve = |dp: &Vec3| {
+            if determinant.abs() < 1e-12 {
+                return (0.0, 0.0);
+            }
+            let (pu, pv) = (Vec3::dot(&self.dpdu, dp), Vec3::dot(&self.dpdv, dp));
+            (
+                (c * pu - b * pv) / determinant,
+                (a * pv - b * pu) / determinant,
+            )
+        };
+        let (dudx, dvdx) = solve(&dpdx);
+        let (dudy, dvdy) = solve(&dpdy);
+
+        self.footprint = Some(Footprint {
+            dpdx,
+            dpdy,
+            dudx,
+            dvdx,
+            dudy,
+            dvdy,
+        });
+    }
 }
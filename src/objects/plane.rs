@@ -2,7 +2,7 @@ use std::rc::Rc;
 
 use super::object::{HitRecord, Object};
 use crate::{
-    material::Material,
+    material::{material_id, Material},
     ray::Ray,
     utils::interval::Interval,
     vec3::{Pos3, Vec3},
@@ -31,12 +31,27 @@ impl Object for Plane {
         if t_interval.contains_including(scalar) {
             // println!("Hit: {}", scalar);
             let hit_point = ray.cast(scalar);
+            // surface coordinates in world units from the plane center, left unwrapped so
+            // that tiling textures and their filters see no seams
+            let local = hit_point - self.center;
             let mut hit_record = HitRecord {
                 ray_scalar: scalar,
+                u: local.x,
+                v: local.z,
+                dpdu: Vec3::new(1.0, 0.0, 0.0),
+                dpdv: Vec3::new(0.0, 0.0, 1.0),
+                dndu: Vec3::ZERO,
+                dndv: Vec3::ZERO,
+                tangent: Vec3::new(1.0, 0.0, 0.0),
+                footprint: None,
                 point: hit_point,
                 normal: self.plane_up,
+                geometric_normal: self.plane_up,
                 front_face: Vec3::dot(&ray.dir, &self.plane_up) > 0.0,
                 material: self.material.clone(),
+                material_id: material_id(&self.material),
+                object_id: 0,
+                object: None,
             };
             hit_record.set_face_normal(ray, &self.plane_up);
             Some(hit_record)
@@ -49,6 +64,11 @@ impl Object for Plane {
 impl Plane {
     // todo: fix lifetime
     pub fn new(position: Vec3, material: impl Material + 'static) -> Self {
+        Self::shared(position, Rc::new(material))
+    }
+
+    // objects sharing a material also share its material id
+    pub fn shared(position: Vec3, material: Rc<dyn Material>) -> Self {
         Self {
             // todo: up vector to input
             plane_up: Vec3 {
@@ -57,7 +77,7 @@ impl Plane {
                 z: 0.0,
             },
             center: position,
-            material: Rc::new(material),
+            material,
         }
     }
 }
@@ -1,10 +1,14 @@
-use std::rc::Rc;
+use std::{f64::consts::PI, rc::Rc};
 
 use super::object::{HitRecord, Object};
 use crate::{
-    material::Material,
+    material::{material_id, Material},
     ray::Ray,
-    utils::interval::Interval,
+    utils::{
+        aabb::Aabb,
+        helpers::{cone_pdf, random_in_unit_sphere_normalized, sample_cone},
+        interval::Interval,
+    },
     vec3::{Pos3, Vec3},
 };
 
@@ -12,6 +16,16 @@ pub struct Sphere {
     center: Pos3,
     radius: f64,
     material: Rc<dyn Material>,
+    tangents: SphereTangents,
+}
+
+// How the tangents, the brushing direction of anisotropic materials, wrap around a sphere.
+#[derive(Copy, Clone, Debug)]
+pub enum SphereTangents {
+    // circles around the axis, like the lines of latitude (dpdu for the y axis)
+    Around(Vec3),
+    // from pole to pole of the axis, like meridians
+    Along(Vec3),
 }
 
 impl Object for Sphere {
@@ -40,26 +54,158 @@ impl Object for Sphere {
 
         let hit_point = ray.cast(root);
         let normal = (hit_point - self.center) / self.radius;
+        let (u, v) = Sphere::uv(&normal);
+        let (dpdu, dpdv) = self.partials(&normal);
         let mut hit_record = HitRecord {
             ray_scalar: root,
+            u,
+            v,
+            dpdu,
+            dpdv,
+            dndu: dpdu / self.radius,
+            dndv: dpdv / self.radius,
+            tangent: self.tangent(&normal),
+            footprint: None,
             point: hit_point,
             normal,
+            geometric_normal: normal,
             front_face: Vec3::dot(&ray.dir, &normal) > 0.0,
             material: self.material.clone(),
+            material_id: material_id(&self.material),
+            object_id: 0,
+            object: None,
         };
         hit_record.set_face_normal(ray, &normal);
 
         Some(hit_record)
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let extent = Vec3::from_float(self.radius);
+        Some(Aabb::new(self.center - extent, self.center + extent))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn is_specular(&self) -> bool {
+        self.material.is_specular()
+    }
+
+    fn sample_direction(&self, origin: &Pos3) -> Option<(Vec3, f64)> {
+        Some(sample_cone(origin, &self.center, self.radius))
+    }
+
+    fn direction_pdf(&self, origin: &Pos3, direction: &Vec3) -> f64 {
+        cone_pdf(origin, &self.center, self.radius, direction)
+    }
+
+    fn sample_surface(&self) -> Option<HitRecord> {
+        let normal = random_in_unit_sphere_normalized();
+        let (u, v) = Sphere::uv(&normal);
+        let (dpdu, dpdv) = self.partials(&normal);
+        Some(HitRecord {
+            point: self.center + self.radius * normal,
+            normal,
+            geometric_normal: normal,
+            ray_scalar: 0.0,
+            u,
+            v,
+            dpdu,
+            dpdv,
+            dndu: dpdu / self.radius,
+            dndv: dpdv / self.radius,
+            tangent: self.tangent(&normal),
+            footprint: None,
+            front_face: true,
+            material: self.material.clone(),
+            material_id: material_id(&self.material),
+            object_id: 0,
+            object: None,
+        })
+    }
+
+    fn area(&self) -> f64 {
+        4.0 * PI * self.radius * self.radius
+    }
 }
 
 impl Sphere {
     // todo: fix lifetime
     pub fn new(position: Vec3, radius: f64, material: impl Material + 'static) -> Self {
+        Self::shared(position, radius, Rc::new(material))
+    }
+
+    // objects sharing a material also share its material id
+    pub fn shared(position: Vec3, radius: f64, material: Rc<dyn Material>) -> Self {
         Self {
             center: position,
             radius,
-            material: Rc::new(material),
+            material,
+            tangents: SphereTangents::Around(Vec3::new(0.0, 1.0, 0.0)),
         }
     }
+
+    pub fn with_tangents(mut self, tangents: SphereTangents) -> Self {
+        self.tangents = tangents;
+        self
+    }
+
+    // zero at the poles of the axis, the materials fall back to dpdu there
+    fn tangent(&self, outward_normal: &Vec3) -> Vec3 {
+        match self.tangents {
+            SphereTangents::Around(axis) => Vec3::cross(&axis, outward_normal),
+            SphereTangents::Along(axis) => {
+                Vec3::cross(outward_normal, &Vec3::cross(&axis, outward_normal))
+            }
+        }
+    }
+
+    fn uv(outward_normal: &Vec3) -> (f64, f64) {
+        // u: angle around the y-axis starting at -x, v: angle from the bottom pole
+        let theta = (-outward_normal.y).acos();
+        let phi = (-outward_normal.z).atan2(outward_normal.x) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    // Derivatives of the point by u and v of the mapping above, they vanish at the poles.
+    fn partials(&self, outward_normal: &Vec3) -> (Vec3, Vec3) {
+        let n = outward_normal;
+        let sin_theta = (n.x * n.x + n.z * n.z).sqrt();
+        let dpdu = 2.0 * PI * self.radius * Vec3::new(n.z, 0.0, -n.x);
+        let dpdv = if sin_theta > 0.0 {
+            PI * self.radius * Vec3::new(-n.y * n.x / sin_theta, sin_theta, -n.y * n.z / sin_theta)
+        } else {
+            Vec3::ZERO
+        };
+        (dpdu, dpdv)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambert;
+
+    #[test]
+    fn tangents() {
+        let sphere = || Sphere::new(Pos3::ZERO, 2.0, Lambert::new(Vec3::from_float(0.5)));
+        let ray = Ray::new(Pos3::new(0.3, 0.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let interval = Interval::new(0.0001, f64::MAX);
+        let normal = |hit: &HitRecord| (hit.point / 2.0).normalize();
+
+        // latitudes by default, same as dpdu
+        let hit = sphere().hit(&ray, &interval).unwrap();
+        assert!(Vec3::cross(&hit.tangent, &hit.dpdu).length() < 1e-9);
+
+        let axis = Vec3::new(1.0, 0.0, 0.0);
+        let hit = sphere()
+            .with_tangents(SphereTangents::Along(axis))
+            .hit(&ray, &interval)
+            .unwrap();
+        // meridians of the x axis: in the plane of the axis and the normal
+        assert!(Vec3::dot(&hit.tangent, &normal(&hit)).abs() < 1e-9);
+        assert!(Vec3::dot(&hit.tangent, &Vec3::cross(&axis, &normal(&hit))).abs() < 1e-9);
+    }
 }
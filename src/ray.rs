@@ -1,13 +1,26 @@
 use crate::{
-    utils::interval::Interval,
+    objects::object::HitRecord,
+    spectrum::SampledWavelengths,
     vec3::{Color3, Pos3, Vec3},
     world::World,
 };
 
+#[derive(Clone)]
 pub struct Ray {
     pub pos: Pos3,
     pub dir: Vec3,
-    pub ray_color: Color3,
+    // set in spectral mode, radiance along the ray is then carried per wavelength
+    pub wavelengths: Option<SampledWavelengths>,
+    // rays through the neighbouring pixels, they give the footprint texture lookups filter over
+    pub differentials: Option<RayDifferential>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RayDifferential {
+    pub rx_origin: Pos3,
+    pub rx_direction: Vec3,
+    pub ry_origin: Pos3,
+    pub ry_direction: Vec3,
 }
 
 impl Ray {
@@ -15,38 +28,117 @@ impl Ray {
         Ray {
             pos: position,
             dir: direction,
-            ray_color: Color3::new(0.5, 0.7, 1.0),
+            wavelengths: None,
+            differentials: None,
         }
     }
 
-    pub fn cast(&self, scalar: f64) -> Pos3 {
-        self.pos + (scalar * self.dir)
+    // Continues the path from a surface, keeping the per-path state of this ray.
+    pub fn scattered(&self, position: Pos3, direction: Vec3) -> Ray {
+        Ray {
+            wavelengths: self.wavelengths,
+            ..Ray::new(position, direction)
+        }
     }
 
-    pub fn ray_color(&self, world: &World, bounces_remaining: u16) -> Color3 {
-        if bounces_remaining == 0 {
-            return Color3::BLACK;
+    // Colors (albedos, emission) are defined in RGB, in spectral mode they are
+    // evaluated at the wavelengths of the ray.
+    pub fn spectral_color(&self, color: &Color3, world: &World) -> Color3 {
+        match &self.wavelengths {
+            Some(wavelengths) => wavelengths.sample_rgb(color, world.working_space),
+            None => *color,
         }
+    }
 
-        let hit = world.hit_objects(self, &Interval::new(0.0001, f64::MAX));
-        if let Some(hit) = hit {
-            return match hit.material.reflect(self, &hit) {
-                Some((attenuation, reflected_ray)) => {
-                    attenuation * reflected_ray.ray_color(world, bounces_remaining - 1)
-                }
-                None => Color3::BLACK,
-            };
-        }
-        let dir_normalized = self.dir.normalize();
-        let y_ratio = 0.5 * (dir_normalized.y + 1.0); // move normalized y-axis from [-1, 1] to [0, 2] and multiply with .5 for [0, 1]
+    // Continues the path with a perfect mirror reflection into `direction`, the differentials
+    // are reflected along with the ray (diffuse scattering drops them).
+    // https://pbr-book.org/3ed-2018/Materials/Specular_Reflection_and_Transmission#
+    pub fn specular_reflection(&self, hit: &HitRecord, direction: Vec3) -> Ray {
+        let mut ray = self.scattered(hit.point, direction);
+        ray.differentials = self.surface_differentials(hit, direction, |wo, wi, dwo, dn, n| {
+            let d_cos = Vec3::dot(&dwo, &n) + Vec3::dot(&wo, &dn);
+            wi - dwo + 2.0 * (Vec3::dot(&wo, &n) * dn + d_cos * n)
+        });
+        ray
+    }
+
+    // Continues the path refracted into `direction`, `eta` is the ratio of the refraction
+    // indices of the incident and the transmitted side.
+    pub fn specular_transmission(&self, hit: &HitRecord, direction: Vec3, eta: f64) -> Ray {
+        let mut ray = self.scattered(hit.point, direction);
+        ray.differentials = self.surface_differentials(hit, direction, |wo, wi, dwo, dn, n| {
+            let cos_o = Vec3::dot(&wo, &n);
+            let cos_t = Vec3::dot(&wi, &n).abs();
+            let d_cos = Vec3::dot(&dwo, &n) + Vec3::dot(&wo, &dn);
+            let mu = eta * cos_o - cos_t;
+            let d_mu = (eta - eta * eta * cos_o / cos_t) * d_cos;
+            wi - eta * dwo + mu * dn + d_mu * n
+        });
+        ray
+    }
+
+    // Differentials of the ray leaving `hit` in `direction`: `scatter` maps the outgoing
+    // direction, its change, the change of the normal and the normal to the scattered direction
+    // of a neighbouring ray.
+    fn surface_differentials(
+        &self,
+        hit: &HitRecord,
+        direction: Vec3,
+        scatter: impl Fn(Vec3, Vec3, Vec3, Vec3, Vec3) -> Vec3,
+    ) -> Option<RayDifferential> {
+        let (differentials, footprint) = (self.differentials?, hit.footprint?);
+        let wo = -self.dir.normalize();
+        let wi = direction.normalize();
+        let dndx = footprint.dudx * hit.dndu + footprint.dvdx * hit.dndv;
+        let dndy = footprint.dudy * hit.dndu + footprint.dvdy * hit.dndv;
+        let dwodx = -differentials.rx_direction.normalize() - wo;
+        let dwody = -differentials.ry_direction.normalize() - wo;
 
-        (1.0 - y_ratio) * Color3::WHITE + y_ratio * self.ray_color
+        Some(RayDifferential {
+            rx_origin: hit.point + footprint.dpdx,
+            rx_direction: scatter(wo, wi, dwodx, dndx, hit.normal),
+            ry_origin: hit.point + footprint.dpdy,
+            ry_direction: scatter(wo, wi, dwody, dndy, hit.normal),
+        })
+    }
+
+    pub fn cast(&self, scalar: f64) -> Pos3 {
+        self.pos + (scalar * self.dir)
+    }
+
+    // A dispersive scattering event kept only the hero wavelength for the rest of the path.
+    pub fn hero_only(&self) -> bool {
+        self.wavelengths
+            .is_some_and(|wavelengths| wavelengths.secondary_terminated())
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::{
+        material::Lambert,
+        objects::{object::Object, plane::Plane, sphere::Sphere},
+        utils::interval::Interval,
+    };
+
+    // ray from `origin` towards `target` with neighbours offset by `offset` in x and z
+    fn differential_ray(origin: Pos3, target: Pos3, offset: f64) -> Ray {
+        let mut ray = Ray::new(origin, target - origin);
+        ray.differentials = Some(RayDifferential {
+            rx_origin: origin,
+            rx_direction: target + Vec3::new(offset, 0.0, 0.0) - origin,
+            ry_origin: origin,
+            ry_direction: target + Vec3::new(0.0, 0.0, offset) - origin,
+        });
+        ray
+    }
+
+    fn hit(object: &dyn Object, ray: &Ray) -> HitRecord {
+        let mut hit = object.hit(ray, &Interval::new(0.0001, f64::MAX)).unwrap();
+        hit.compute_footprint(ray);
+        hit
+    }
 
     #[test]
     fn create_ray() {
@@ -56,16 +148,39 @@ mod test {
     }
 
     #[test]
-    fn default_ray_color() {
-        let mut ray_up = Ray::new(Pos3::new(1.0, 2.0, 3.0), Vec3::new(0.0, 1.0, 0.0));
-        let ray_down = Ray::new(Pos3::new(1.0, 2.0, 3.0), Vec3::new(0.0, -1.0, 0.0));
-        ray_up.ray_color = Color3::new(1.0, 0.0, 0.0);
+    fn footprint() {
+        let plane = Plane::new(Pos3::ZERO, Lambert::new(Color3::WHITE));
+        let ray = differential_ray(Pos3::new(0.2, 2.0, 0.3), Pos3::new(0.5, 1.0, 0.5), 0.01);
+        let footprint = hit(&plane, &ray).footprint.unwrap();
+        // the plane is twice as far as the offset targets
+        assert!((footprint.dudx - 0.02).abs() < 1e-9 && footprint.dvdx.abs() < 1e-9);
+        assert!((footprint.dvdy - 0.02).abs() < 1e-9 && footprint.dudy.abs() < 1e-9);
 
-        let world = World::new();
-        let color_up = ray_up.ray_color(&world, 1);
-        let color_down = ray_down.ray_color(&world, 1);
+        // on a sphere the footprint matches the coordinates where the offset ray lands
+        let sphere = Sphere::new(Pos3::ZERO, 1.0, Lambert::new(Color3::WHITE));
+        let ray = differential_ray(Pos3::new(3.0, 1.0, 2.0), Pos3::new(0.3, 0.4, 0.2), 0.001);
+        let record = hit(&sphere, &ray);
+        let footprint = record.footprint.unwrap();
+        let differentials = ray.differentials.unwrap();
+        let offset = Ray::new(differentials.rx_origin, differentials.rx_direction);
+        let offset_record = hit(&sphere, &offset);
+        assert!((offset_record.u - record.u - footprint.dudx).abs() < 1e-2 * footprint.dudx.abs());
+        assert!((offset_record.v - record.v - footprint.dvdx).abs() < 1e-2 * footprint.dvdx.abs());
+    }
+
+    #[test]
+    fn mirror_differentials() {
+        // a flat mirror reflects the neighbouring rays like the ray itself
+        let plane = Plane::new(Pos3::ZERO, Lambert::new(Color3::WHITE));
+        let ray = differential_ray(Pos3::new(0.0, 1.0, -1.0), Pos3::new(0.0, 0.5, -0.5), 0.01);
+        let record = hit(&plane, &ray);
+        let reflected = ray.specular_reflection(&record, Vec3::new(0.0, 1.0, 1.0));
+        let differentials = reflected.differentials.unwrap();
 
-        assert_eq!(color_up, ray_up.ray_color);
-        assert_eq!(color_down, Color3::new(1.0, 1.0, 1.0));
+        let mirror = |v: Vec3| Vec3::new(v.x, -v.y, v.z).normalize();
+        let incoming = ray.differentials.unwrap();
+        let expected = mirror(incoming.rx_direction);
+        assert!((differentials.rx_direction.normalize() - expected).length() < 1e-9);
+        assert!((differentials.rx_origin - record.point).y.abs() < 1e-9);
     }
 }
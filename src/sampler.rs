@@ -0,0 +1,87 @@
+use std::{cell::RefCell, rc::Rc};
+
+use rand::{Error, RngCore};
+
+// Source of the uniform random numbers a path consumes. Rendering code draws them
+// through `rng()`, so an integrator can install a sampler that decides (and can
+// replay) every random decision of a path, e.g. to mutate it in primary sample space.
+pub trait Sampler {
+    // Next uniform number in [0, 1) of the current sample.
+    fn next_1d(&mut self) -> f64;
+}
+
+thread_local! {
+    static ACTIVE: RefCell<Option<Rc<RefCell<dyn Sampler>>>> = const { RefCell::new(None) };
+}
+
+// Runs `f` with all random numbers on this thread taken from `sampler`.
+pub fn with_sampler<R>(sampler: Rc<RefCell<dyn Sampler>>, f: impl FnOnce() -> R) -> R {
+    let previous = ACTIVE.with(|active| active.replace(Some(sampler)));
+    let result = f();
+    ACTIVE.with(|active| *active.borrow_mut() = previous);
+    result
+}
+
+// Random number generator of the renderer: the installed sampler if there is one,
+// the thread rng otherwise.
+pub fn rng() -> SamplerRng {
+    SamplerRng
+}
+
+pub struct SamplerRng;
+
+impl RngCore for SamplerRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let active = ACTIVE.with(|active| active.borrow().clone());
+        match active {
+            // rand builds an f64 from the 53 high bits, so gen::<f64>() returns the sample unchanged
+            Some(sampler) => ((sampler.borrow_mut().next_1d() * (1u64 << 53) as f64) as u64) << 11,
+            None => rand::thread_rng().next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    struct Sequence(Vec<f64>);
+
+    impl Sampler for Sequence {
+        fn next_1d(&mut self) -> f64 {
+            self.0.remove(0)
+        }
+    }
+
+    #[test]
+    fn installed_sampler_drives_rng() {
+        let sampler = Rc::new(RefCell::new(Sequence(vec![0.25, 0.75, 0.5])));
+        let values = with_sampler(sampler, || {
+            let mut rng = rng();
+            (rng.gen::<f64>(), rng.gen::<f64>(), rng.gen_range(0..4))
+        });
+        assert_eq!(values, (0.25, 0.75, 2));
+
+        // without a sampler the thread rng takes over again
+        let value = rng().gen::<f64>();
+        assert!((0.0..1.0).contains(&value));
+    }
+}
@@ -0,0 +1,352 @@
+use std::f64::consts::PI;
+
+use rand::Rng;
+
+use crate::{
+    color_space::ColorSpace,
+    sampler,
+    utils::{distribution::Distribution2D, helpers::degrees_to_radians, matrix::Mat3, onb::Onb},
+    vec3::{Color3, Vec3},
+};
+
+#[derive(Copy, Clone, Debug)]
+pub struct SkySetup {
+    // degrees above the horizon
+    pub sun_elevation: f64,
+    // degrees from -z towards +x
+    pub sun_azimuth: f64,
+    // haze in the air, 2 is a clear sky and 10 a hazy one (the model covers 1.7 to 10)
+    pub turbidity: f64,
+    // scales the sky and the sun
+    pub intensity: f64,
+}
+
+// Analytic daylight: the Preetham sky and the sun disk seen through the same atmosphere.
+// Below the horizon it is black, the ground belongs to the scene. The sun and the
+// brighter parts of the sky are importance sampled for light sampling.
+// https://courses.cs.duke.edu/cps124/fall01/resources/p91-preetham.pdf
+pub struct Sky {
+    sun_direction: Vec3,
+    cos_sun_radius: f64,
+    sun_radiance: Color3,
+    // Perez coefficients A to E and zenith values of luminance and chromaticity x, y
+    perez: [[f64; 5]; 3],
+    zenith: [f64; 3],
+    // Perez function at the zenith, the model is relative to it
+    perez_zenith: [f64; 3],
+    from_xyz: Mat3,
+    scale: f64,
+    // sky luminance times sin(theta) over the upper hemisphere, theta along the rows
+    distribution: Distribution2D,
+    sun_probability: f64,
+}
+
+impl Default for SkySetup {
+    fn default() -> Self {
+        Self {
+            sun_elevation: 45.0,
+            sun_azimuth: 0.0,
+            turbidity: 3.0,
+            intensity: 1.0,
+        }
+    }
+}
+
+impl Sky {
+    // angular radius of the sun
+    const SUN_RADIUS: f64 = 0.00465;
+    // luminance of the sun above the atmosphere in kcd/m^2, the unit of the sky model
+    const SUN_LUMINANCE: f64 = 1.6e6;
+    // kcd/m^2 to radiance: a white diffuse surface under a high sun comes out about white
+    const SCALE: f64 = 0.03;
+    const WIDTH: usize = 64;
+    const HEIGHT: usize = 32;
+
+    pub fn new(setup: SkySetup, working_space: ColorSpace) -> Self {
+        let elevation = degrees_to_radians(setup.sun_elevation.clamp(0.5, 90.0));
+        let azimuth = degrees_to_radians(setup.sun_azimuth);
+        let sun_direction = Vec3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        );
+        let theta_s = PI / 2.0 - elevation;
+        let t = setup.turbidity.clamp(1.7, 10.0);
+
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let chromaticity = |m: [[f64; 4]; 3]| {
+            let thetas = [theta_s.powi(3), theta_s.powi(2), theta_s, 1.0];
+            let ts = [t * t, t, 1.0];
+            (0..3)
+                .map(|i| ts[i] * (0..4).map(|j| m[i][j] * thetas[j]).sum::<f64>())
+                .sum::<f64>()
+        };
+        let zenith = [
+            ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0),
+            chromaticity([
+                [0.00166, -0.00375, 0.00209, 0.0],
+                [-0.02903, 0.06377, -0.03202, 0.00394],
+                [0.11693, -0.21196, 0.06052, 0.25886],
+            ]),
+            chromaticity([
+                [0.00275, -0.00610, 0.00317, 0.0],
+                [-0.04214, 0.08970, -0.04153, 0.00516],
+                [0.15346, -0.26756, 0.06670, 0.26688],
+            ]),
+        ];
+        let perez_zenith = perez.map(|coefficients| perez_function(&coefficients, 1.0, theta_s));
+
+        // sun light attenuated by Rayleigh and aerosol scattering along the relative air mass,
+        // at wavelengths (in micrometers) standing in for red, green and blue
+        let air_mass = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+        let beta = 0.04608 * t - 0.04586;
+        let transmittance = |lambda: f64| {
+            let rayleigh = 0.008735 * lambda.powf(-4.08);
+            let aerosol = beta * lambda.powf(-1.3);
+            (-(rayleigh + aerosol) * air_mass).exp()
+        };
+        let scale = Self::SCALE * setup.intensity.max(0.0);
+        let sun_radiance = Self::SUN_LUMINANCE
+            * scale
+            * Color3::new(
+                transmittance(0.68),
+                transmittance(0.55),
+                transmittance(0.44),
+            );
+
+        let mut sky = Self {
+            sun_direction,
+            cos_sun_radius: Self::SUN_RADIUS.cos(),
+            sun_radiance,
+            perez,
+            zenith,
+            perez_zenith,
+            from_xyz: working_space.from_xyz(),
+            scale,
+            distribution: Distribution2D::new(&[1.0], 1),
+            sun_probability: 0.0,
+        };
+
+        let mut luminance = Vec::with_capacity(Self::WIDTH * Self::HEIGHT);
+        for row in 0..Self::HEIGHT {
+            for column in 0..Self::WIDTH {
+                let (x, y) = (
+                    (column as f64 + 0.5) / Self::WIDTH as f64,
+                    (row as f64 + 0.5) / Self::HEIGHT as f64,
+                );
+                let (direction, sin_theta) = Self::direction(x, y);
+                luminance.push(sky.sky_luminance(&direction) * sin_theta);
+            }
+        }
+        sky.distribution = Distribution2D::new(&luminance, Self::WIDTH);
+
+        // share of the light (integrated luminance) from the sun, kept away from 0 and 1
+        let sky_power = sky.distribution.integral() * PI * PI;
+        let sun_power =
+            working_space.luminance(&sky.sun_radiance) * 2.0 * PI * (1.0 - sky.cos_sun_radius);
+        sky.sun_probability = (sun_power / (sun_power + sky_power)).clamp(0.1, 0.9);
+        sky
+    }
+
+    pub fn sun_direction(&self) -> Vec3 {
+        self.sun_direction
+    }
+
+    // `direction` normalized
+    pub fn radiance(&self, direction: &Vec3) -> Color3 {
+        let sky = self.sky_radiance(direction);
+        if Vec3::dot(direction, &self.sun_direction) >= self.cos_sun_radius {
+            sky + self.sun_radiance
+        } else {
+            sky
+        }
+    }
+
+    // Normalized direction and its solid angle density.
+    pub fn sample_direction(&self) -> (Vec3, f64) {
+        let mut rng = sampler::rng();
+        let direction = if rng.gen::<f64>() < self.sun_probability {
+            // uniform in the cone of the sun disk
+            let cos_theta = 1.0 - rng.gen::<f64>() * (1.0 - self.cos_sun_radius);
+            let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+            let phi = 2.0 * PI * rng.gen::<f64>();
+            Onb::from_w(&self.sun_direction).to_world(&Vec3::new(
+                sin_theta * phi.cos(),
+                sin_theta * phi.sin(),
+                cos_theta,
+            ))
+        } else {
+            let ((x, y), _) = self.distribution.sample(rng.gen(), rng.gen());
+            Self::direction(x, y).0
+        };
+        (direction, self.pdf(&direction))
+    }
+
+    pub fn pdf(&self, direction: &Vec3) -> f64 {
+        let sun = if Vec3::dot(direction, &self.sun_direction) >= self.cos_sun_radius {
+            1.0 / (2.0 * PI * (1.0 - self.cos_sun_radius))
+        } else {
+            0.0
+        };
+        let sky = match Self::coordinates(direction) {
+            Some((x, y, sin_theta)) if sin_theta > 0.0 => {
+                // the square maps to theta in [0, pi / 2] and phi in [0, 2 pi]
+                self.distribution.pdf(x, y) / (PI * PI * sin_theta)
+            }
+            _ => 0.0,
+        };
+        self.sun_probability * sun + (1.0 - self.sun_probability) * sky
+    }
+
+    fn sky_radiance(&self, direction: &Vec3) -> Color3 {
+        let [luminance, x, y] = self.sky_values(direction);
+        if luminance <= 0.0 || y <= 0.0 {
+            return Color3::BLACK;
+        }
+        let xyz = Vec3::new(x * luminance / y, luminance, (1.0 - x - y) * luminance / y);
+        (self.from_xyz * xyz).max(&Color3::BLACK) * self.scale
+    }
+
+    fn sky_luminance(&self, direction: &Vec3) -> f64 {
+        self.sky_values(direction)[0] * self.scale
+    }
+
+    // luminance and chromaticity, zero below the horizon
+    fn sky_values(&self, direction: &Vec3) -> [f64; 3] {
+        let cos_theta = direction.y;
+        if cos_theta <= 0.0 {
+            return [0.0; 3];
+        }
+        let gamma = Vec3::dot(direction, &self.sun_direction)
+            .clamp(-1.0, 1.0)
+            .acos();
+        std::array::from_fn(|i| {
+            self.zenith[i] * perez_function(&self.perez[i], cos_theta, gamma) / self.perez_zenith[i]
+        })
+    }
+
+    // point of the unit square to the upper hemisphere, with sin(theta)
+    fn direction(x: f64, y: f64) -> (Vec3, f64) {
+        let theta = y * PI / 2.0;
+        let phi = x * 2.0 * PI;
+        let sin_theta = theta.sin();
+        (
+            Vec3::new(sin_theta * phi.sin(), theta.cos(), -sin_theta * phi.cos()),
+            sin_theta,
+        )
+    }
+
+    fn coordinates(direction: &Vec3) -> Option<(f64, f64, f64)> {
+        if direction.y <= 0.0 {
+            return None;
+        }
+        let theta = direction.y.clamp(-1.0, 1.0).acos();
+        let phi = direction.x.atan2(-direction.z).rem_euclid(2.0 * PI);
+        Some((phi / (2.0 * PI), theta / (PI / 2.0), theta.sin()))
+    }
+}
+
+// Perez et al. sky luminance distribution, relative to the direction
+fn perez_function(coefficients: &[f64; 5], cos_theta: f64, gamma: f64) -> f64 {
+    let [a, b, c, d, e] = *coefficients;
+    (1.0 + a * (b / cos_theta.max(1e-3)).exp())
+        * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sky(elevation: f64) -> Sky {
+        Sky::new(
+            SkySetup {
+                sun_elevation: elevation,
+                sun_azimuth: 30.0,
+                ..Default::default()
+            },
+            ColorSpace::Srgb,
+        )
+    }
+
+    #[test]
+    fn daylight() {
+        let noon = sky(60.0);
+        let sun = noon.sun_direction();
+        assert!((sun.y - 60f64.to_radians().sin()).abs() < 1e-12);
+        assert!(sun.x > 0.0 && sun.z < 0.0);
+
+        // brighter around the sun than away from it, blue overhead, nothing below
+        let near_sun = (sun + Vec3::new(0.0, 0.1, 0.0)).normalize();
+        let away = Vec3::new(-sun.x, sun.y, -sun.z);
+        let luminance = |c: Color3| ColorSpace::Srgb.luminance(&c);
+        assert!(luminance(noon.radiance(&near_sun)) > 2.0 * luminance(noon.radiance(&away)));
+        let zenith = noon.radiance(&Vec3::new(0.0, 1.0, 0.0));
+        assert!(zenith.z > zenith.x, "{:?}", zenith);
+        assert_eq!(noon.radiance(&Vec3::new(0.0, -1.0, 0.0)), Color3::BLACK);
+
+        // the sun disk is much brighter than the sky, and redder at sunset
+        assert!(luminance(noon.radiance(&sun)) > 1e3 * luminance(zenith));
+        let sunset = sky(2.0);
+        let red = |c: Color3| c.x / c.z;
+        assert!(red(sunset.radiance(&sunset.sun_direction())) > 2.0 * red(noon.radiance(&sun)));
+    }
+
+    #[test]
+    fn importance_sampling() {
+        let sky = sky(35.0);
+        let n = 100_000;
+        let mut sampled = Color3::BLACK;
+        for _ in 0..n {
+            let (direction, pdf) = sky.sample_direction();
+            assert!((direction.length() - 1.0).abs() < 1e-9);
+            assert!((pdf - sky.pdf(&direction)).abs() <= 1e-9 * pdf);
+            sampled += sky.radiance(&direction) / pdf;
+        }
+
+        // the same integral with the sky uniformly sampled and the sun disk added
+        let mut uniform = Color3::BLACK;
+        let mut pdf = 0.0;
+        for _ in 0..n {
+            let direction = crate::utils::helpers::random_in_unit_sphere_normalized();
+            uniform += sky.sky_radiance(&direction) * 4.0 * PI;
+            // the rare hits of the sun disk would add its whole share at once
+            if Vec3::dot(&direction, &sky.sun_direction) < sky.cos_sun_radius {
+                pdf += sky.pdf(&direction) * 4.0 * PI;
+            }
+        }
+        let sun = sky.sun_radiance * 2.0 * PI * (1.0 - sky.cos_sun_radius);
+        let expected = uniform / n as f64 + sun;
+        let sampled = sampled / n as f64;
+        assert!(
+            (sampled - expected).length() < 0.02 * expected.length(),
+            "{:?} {:?}",
+            sampled,
+            expected
+        );
+        // the sky part of the density integrates to the probability of sampling it
+        assert!((pdf / n as f64 - (1.0 - sky.sun_probability)).abs() < 0.02);
+    }
+}
@@ -0,0 +1,243 @@
+use crate::{
+    color_space::{chromatic_adaptation, ColorSpace},
+    vec3::{Color3, Vec3},
+};
+
+pub const LAMBDA_MIN: f64 = 380.0;
+pub const LAMBDA_MAX: f64 = 720.0;
+
+// Integral of the CIE y matching function fit over [LAMBDA_MIN, LAMBDA_MAX]
+const CIE_Y_INTEGRAL: f64 = 106.911868;
+// XYZ of the constant (equal energy) spectrum, normalized to Y = 1
+const WHITE_E: Vec3 = Vec3 {
+    x: 0.998586,
+    y: 1.0,
+    z: 0.999191,
+};
+
+// Wavelengths (in nm) carried by a path in spectral mode. The first one is the hero
+// wavelength, the others are spread evenly over the visible range so that one path
+// estimates three spectral samples at once; their values travel in a Color3.
+// https://cgg.mff.cuni.cz/~wilkie/Website/EGSR_14_files/WNDWH14HWSS.pdf
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SampledWavelengths {
+    pub lambda: [f64; 3],
+    pub pdf: [f64; 3],
+}
+
+impl SampledWavelengths {
+    pub fn sample_uniform(u: f64) -> Self {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let hero = LAMBDA_MIN + u * range;
+        let mut lambda = [hero; 3];
+        for (i, l) in lambda.iter_mut().enumerate().skip(1) {
+            *l = LAMBDA_MIN + (hero - LAMBDA_MIN + i as f64 * range / 3.0) % range;
+        }
+
+        SampledWavelengths {
+            lambda,
+            pdf: [1.0 / range; 3],
+        }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    pub fn secondary_terminated(&self) -> bool {
+        self.pdf[1] == 0.0
+    }
+
+    // Wavelength dependent scattering (dispersion) can only follow one wavelength,
+    // the hero takes over the contribution of the others.
+    pub fn terminate_secondary(&mut self) {
+        if self.secondary_terminated() {
+            return;
+        }
+        self.pdf[1] = 0.0;
+        self.pdf[2] = 0.0;
+        self.pdf[0] /= 3.0;
+    }
+
+    // Values of the spectrum matching `rgb` (in `space`) at the sampled wavelengths.
+    pub fn sample_rgb(&self, rgb: &Color3, space: ColorSpace) -> Color3 {
+        let srgb = space.convert(rgb, ColorSpace::Srgb).max(&Color3::ZERO);
+        Color3::new(
+            rgb_to_spectrum(&srgb, self.lambda[0]),
+            rgb_to_spectrum(&srgb, self.lambda[1]),
+            rgb_to_spectrum(&srgb, self.lambda[2]),
+        )
+    }
+
+    // Monte Carlo estimate of the XYZ color of spectral samples `values`.
+    pub fn to_xyz(&self, values: &Color3) -> Vec3 {
+        let mut xyz = Vec3::ZERO;
+        for i in 0..3 {
+            if self.pdf[i] > 0.0 {
+                xyz += cie_xyz(self.lambda[i]) * (values[i] / self.pdf[i]);
+            }
+        }
+        xyz / (3.0 * CIE_Y_INTEGRAL)
+    }
+
+    pub fn to_rgb(&self, values: &Color3, space: ColorSpace) -> Color3 {
+        // the spectra are relative to an equal energy white, adapt it to the white of the space
+        let adaptation = chromatic_adaptation(&WHITE_E, &space.white_point());
+        space.from_xyz() * (adaptation * self.to_xyz(values))
+    }
+}
+
+// Multi-lobe gaussian fit of the CIE 1931 color matching functions.
+// https://jcgt.org/published/0002/02/01/
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    let g = |mu: f64, sigma_low: f64, sigma_high: f64| {
+        let sigma = if lambda < mu { sigma_low } else { sigma_high };
+        (-0.5 * ((lambda - mu) / sigma).powi(2)).exp()
+    };
+
+    Vec3::new(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+// Linear sRGB color of a reflectance spectrum under equal energy light, a constant
+// spectrum of 1 maps to white.
+pub fn reflectance_to_rgb(reflectance: impl Fn(f64) -> f64) -> Color3 {
+    let from_xyz = ColorSpace::Srgb.from_xyz();
+    let mut xyz = Vec3::ZERO;
+    let mut white = Vec3::ZERO;
+    let mut lambda = LAMBDA_MIN;
+    while lambda <= LAMBDA_MAX {
+        let cmf = cie_xyz(lambda);
+        xyz += cmf * reflectance(lambda);
+        white += cmf;
+        lambda += 5.0;
+    }
+    (from_xyz * xyz / (from_xyz * white)).max(&Color3::ZERO)
+}
+
+// Smits' RGB to spectrum basis, 10 bins over [LAMBDA_MIN, LAMBDA_MAX].
+// https://www.cs.utah.edu/~bes/papers/color/
+const SMITS_WHITE: [f64; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [f64; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [f64; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f64; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [f64; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+fn smits_basis(table: &[f64; 10], lambda: f64) -> f64 {
+    // linear interpolation between bin centers
+    let bin_width = (LAMBDA_MAX - LAMBDA_MIN) / 10.0;
+    let x = ((lambda - LAMBDA_MIN) / bin_width - 0.5).clamp(0.0, 9.0);
+    let i = (x as usize).min(8);
+    let t = x - i as f64;
+    (1.0 - t) * table[i] + t * table[i + 1]
+}
+
+// Smooth spectrum that reproduces a (linear sRGB) color, its value at `lambda`.
+pub fn rgb_to_spectrum(rgb: &Color3, lambda: f64) -> f64 {
+    let (r, g, b) = (rgb.x, rgb.y, rgb.z);
+    let basis = |table: &[f64; 10]| smits_basis(table, lambda);
+
+    // white for the smallest component, then the secondary and primary color of the rest
+    if r <= g && r <= b {
+        r * basis(&SMITS_WHITE)
+            + if g <= b {
+                (g - r) * basis(&SMITS_CYAN) + (b - g) * basis(&SMITS_BLUE)
+            } else {
+                (b - r) * basis(&SMITS_CYAN) + (g - b) * basis(&SMITS_GREEN)
+            }
+    } else if g <= r && g <= b {
+        g * basis(&SMITS_WHITE)
+            + if r <= b {
+                (r - g) * basis(&SMITS_MAGENTA) + (b - r) * basis(&SMITS_BLUE)
+            } else {
+                (b - g) * basis(&SMITS_MAGENTA) + (r - b) * basis(&SMITS_RED)
+            }
+    } else {
+        b * basis(&SMITS_WHITE)
+            + if r <= g {
+                (r - b) * basis(&SMITS_YELLOW) + (g - r) * basis(&SMITS_GREEN)
+            } else {
+                (g - b) * basis(&SMITS_YELLOW) + (r - g) * basis(&SMITS_RED)
+            }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // average the estimate over stratified hero wavelengths
+    fn spectral_round_trip(rgb: &Color3, space: ColorSpace) -> Color3 {
+        let count = 1000;
+        let mut sum = Color3::ZERO;
+        for i in 0..count {
+            let wavelengths = SampledWavelengths::sample_uniform((i as f64 + 0.5) / count as f64);
+            sum += wavelengths.to_rgb(&wavelengths.sample_rgb(rgb, space), space);
+        }
+        sum / count as f64
+    }
+
+    #[test]
+    fn wavelengths_cover_range() {
+        let wavelengths = SampledWavelengths::sample_uniform(0.9);
+        for lambda in wavelengths.lambda {
+            assert!((LAMBDA_MIN..LAMBDA_MAX).contains(&lambda));
+        }
+        assert!((wavelengths.lambda[1] - wavelengths.lambda[0]).abs() > 100.0);
+    }
+
+    #[test]
+    fn white_round_trip() {
+        for space in [ColorSpace::Srgb, ColorSpace::AcesCg] {
+            let white = spectral_round_trip(&Color3::WHITE, space);
+            assert!((white - Color3::WHITE).length() < 0.01, "{:?}", white);
+        }
+    }
+
+    #[test]
+    fn color_round_trip() {
+        // Smits' basis is not exact for saturated colors, but keeps the hue
+        let color = Color3::new(0.8, 0.3, 0.1);
+        let result = spectral_round_trip(&color, ColorSpace::Srgb);
+        assert!((result - color).length() < 0.1, "{:?}", result);
+    }
+
+    #[test]
+    fn reflectance_spectrum() {
+        assert!((reflectance_to_rgb(|_| 1.0) - Color3::WHITE).length() < 1e-9);
+        let color = Color3::new(0.8, 0.3, 0.1);
+        let result = reflectance_to_rgb(|lambda| rgb_to_spectrum(&color, lambda));
+        assert!((result - color).length() < 0.1, "{:?}", result);
+    }
+
+    #[test]
+    fn terminated_secondary() {
+        let mut wavelengths = SampledWavelengths::sample_uniform(0.5);
+        wavelengths.terminate_secondary();
+        assert!(wavelengths.secondary_terminated());
+
+        // the hero wavelength alone is weighted as if it was all three samples
+        let y = wavelengths.to_xyz(&Color3::WHITE).y;
+        let expected = cie_xyz(wavelengths.hero()).y * (LAMBDA_MAX - LAMBDA_MIN) / CIE_Y_INTEGRAL;
+        assert!((y - expected).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,324 @@
+use std::{io::Error, rc::Rc};
+
+use crate::{
+    color_space::ColorSpace,
+    film::Framebuffer,
+    objects::object::{Footprint, HitRecord},
+    vec3::Color3,
+};
+
+pub trait Texture {
+    fn value(&self, hit: &HitRecord) -> Color3;
+}
+
+pub struct SolidColor {
+    color: Color3,
+}
+
+// Image stored linear in the working color space, with a mip-map pyramid: lookups with a
+// footprint blend the two levels whose texel size matches it (trilinear filtering).
+pub struct ImageTexture {
+    // full resolution first, every level halves the size down to 1x1
+    levels: Vec<Framebuffer>,
+}
+
+// Checkerboard over the surface coordinates, `scale` squares per unit. Lookups with a
+// footprint return the box filtered average of the pattern under it.
+pub struct CheckerTexture {
+    scale: f64,
+    even: Rc<dyn Texture>,
+    odd: Rc<dyn Texture>,
+}
+
+impl SolidColor {
+    pub fn new(color: Color3) -> Self {
+        Self { color }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _hit: &HitRecord) -> Color3 {
+        self.color
+    }
+}
+
+impl ImageTexture {
+    // Loads a PPM (display encoded) or PFM (linear) image authored in `source_space`
+    // and converts it to the renderer's working space.
+    pub fn load(
+        filename: &str,
+        source_space: ColorSpace,
+        working_space: ColorSpace,
+    ) -> Result<Self, Error> {
+        let (image, encoded) = if filename.to_lowercase().ends_with(".pfm") {
+            (Framebuffer::read_pfm(filename)?, false)
+        } else {
+            (Framebuffer::read_ppm(filename)?, true)
+        };
+
+        Ok(Self::from_framebuffer(
+            &image,
+            encoded,
+            source_space,
+            working_space,
+        ))
+    }
+
+    // Loads non-color data (normal or height maps), values are used as stored.
+    pub fn load_data(filename: &str) -> Result<Self, Error> {
+        let image = if filename.to_lowercase().ends_with(".pfm") {
+            Framebuffer::read_pfm(filename)?
+        } else {
+            Framebuffer::read_ppm(filename)?
+        };
+        Ok(Self::from_framebuffer(
+            &image,
+            false,
+            ColorSpace::Srgb,
+            ColorSpace::Srgb,
+        ))
+    }
+
+    pub fn from_framebuffer(
+        image: &Framebuffer,
+        encoded: bool,
+        source_space: ColorSpace,
+        working_space: ColorSpace,
+    ) -> Self {
+        let conversion = source_space.conversion_matrix(working_space);
+        let mut converted = Framebuffer::new(image.width, image.height);
+        for y in 0..image.height {
+            for x in 0..image.width {
+                let mut color = image.get(x, y);
+                if encoded {
+                    color = Color3::new(
+                        source_space.decode(color.x),
+                        source_space.decode(color.y),
+                        source_space.decode(color.z),
+                    );
+                }
+                converted.set(x, y, conversion * color);
+            }
+        }
+
+        let mut levels = vec![converted];
+        while let Some(level) = levels.last().and_then(downsample) {
+            levels.push(level);
+        }
+        Self { levels }
+    }
+
+    // (0, 0) is the bottom left corner of the image, coordinates wrap around.
+    pub fn sample(&self, u: f64, v: f64) -> Color3 {
+        self.bilinear(0, u, v)
+    }
+
+    // Average of the image over a footprint `width` (in surface coordinates) wide.
+    pub fn sample_filtered(&self, u: f64, v: f64, width: f64) -> Color3 {
+        let size = self.levels[0].width.max(self.levels[0].height) as f64;
+        let level = (width * size).max(1.0).log2();
+        let last = (self.levels.len() - 1) as f64;
+        if level >= last {
+            return self.bilinear(self.levels.len() - 1, u, v);
+        }
+
+        let lower = level.floor();
+        let t = level - lower;
+        (1.0 - t) * self.bilinear(lower as usize, u, v)
+            + t * self.bilinear(lower as usize + 1, u, v)
+    }
+
+    fn bilinear(&self, level: usize, u: f64, v: f64) -> Color3 {
+        let image = &self.levels[level];
+        let (width, height) = (image.width, image.height);
+        if width == 0 || height == 0 {
+            return Color3::BLACK;
+        }
+
+        let x = u.rem_euclid(1.0) * width as f64 - 0.5;
+        let y = (1.0 - v.rem_euclid(1.0)) * height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+
+        let texel = |x: f64, y: f64| {
+            image.get(
+                (x as i64).rem_euclid(width as i64) as usize,
+                (y as i64).rem_euclid(height as i64) as usize,
+            )
+        };
+        let top = (1.0 - tx) * texel(x0, y0) + tx * texel(x0 + 1.0, y0);
+        let bottom = (1.0 - tx) * texel(x0, y0 + 1.0) + tx * texel(x0 + 1.0, y0 + 1.0);
+        (1.0 - ty) * top + ty * bottom
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, hit: &HitRecord) -> Color3 {
+        match &hit.footprint {
+            Some(footprint) => self.sample_filtered(hit.u, hit.v, footprint_width(footprint)),
+            None => self.sample(hit.u, hit.v),
+        }
+    }
+}
+
+impl CheckerTexture {
+    pub fn new(scale: f64, even: Color3, odd: Color3) -> Self {
+        Self::textured(scale, SolidColor::new(even), SolidColor::new(odd))
+    }
+
+    pub fn textured(scale: f64, even: impl Texture + 'static, odd: impl Texture + 'static) -> Self {
+        Self {
+            scale,
+            even: Rc::new(even),
+            odd: Rc::new(odd),
+        }
+    }
+
+    // Fraction of the odd squares in the box [u - du, u + du] x [v - dv, v + dv]
+    // (in squares), from the integral of the 1D pattern.
+    // https://pbr-book.org/3ed-2018/Texture/Solid_and_Procedural_Texturing#ClosedformBoxFiltering
+    pub fn odd_fraction(u: f64, v: f64, du: f64, dv: f64) -> f64 {
+        let is_odd = |u: f64, v: f64| (u.floor() + v.floor()).rem_euclid(2.0) == 1.0;
+        if (u - du).floor() == (u + du).floor() && (v - dv).floor() == (v + dv).floor() {
+            return if is_odd(u, v) { 1.0 } else { 0.0 };
+        }
+        if du > 1.0 || dv > 1.0 {
+            return 0.5;
+        }
+
+        // odd fraction of the 1D pattern (odd on [1, 2) of every period) from its integral
+        let fraction = |x: f64, dx: f64| {
+            if dx <= 0.0 {
+                return x.floor().rem_euclid(2.0);
+            }
+            let integral =
+                |x: f64| (x / 2.0).floor() + 2.0 * (x / 2.0 - (x / 2.0).floor() - 0.5).max(0.0);
+            (integral(x + dx) - integral(x - dx)) / (2.0 * dx)
+        };
+        let (odd_u, odd_v) = (fraction(u, du), fraction(v, dv));
+        odd_u + odd_v - 2.0 * odd_u * odd_v
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, hit: &HitRecord) -> Color3 {
+        let (u, v) = (hit.u * self.scale, hit.v * self.scale);
+        let (du, dv) = match &hit.footprint {
+            Some(f) => (
+                self.scale * f.dudx.abs().max(f.dudy.abs()),
+                self.scale * f.dvdx.abs().max(f.dvdy.abs()),
+            ),
+            None => (0.0, 0.0),
+        };
+
+        let odd = CheckerTexture::odd_fraction(u, v, du, dv);
+        let even = 1.0 - odd;
+        match (even > 0.0, odd > 0.0) {
+            (true, false) => self.even.value(hit),
+            (false, true) => self.odd.value(hit),
+            _ => even * self.even.value(hit) + odd * self.odd.value(hit),
+        }
+    }
+}
+
+// Size of the footprint along its widest direction, in surface coordinates.
+fn footprint_width(footprint: &Footprint) -> f64 {
+    footprint
+        .dudx
+        .abs()
+        .max(footprint.dvdx.abs())
+        .max(footprint.dudy.abs())
+        .max(footprint.dvdy.abs())
+}
+
+// Next smaller mip-map level, None once the image is down to one texel.
+fn downsample(image: &Framebuffer) -> Option<Framebuffer> {
+    if image.width <= 1 && image.height <= 1 {
+        return None;
+    }
+    let width = image.width.div_ceil(2);
+    let height = image.height.div_ceil(2);
+    let mut level = Framebuffer::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            // 2x2 box, the last row / column is repeated for odd sizes
+            let mut sum = Color3::ZERO;
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let sx = (2 * x + dx).min(image.width - 1);
+                let sy = (2 * y + dy).min(image.height - 1);
+                sum += image.get(sx, sy);
+            }
+            level.set(x, y, sum / 4.0);
+        }
+    }
+    Some(level)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::Lambert,
+        objects::plane::Plane,
+        ray::Ray,
+        utils::interval::Interval,
+        vec3::{Pos3, Vec3},
+        world::World,
+    };
+
+    #[test]
+    fn mip_map_average() {
+        // single texel stripes average to grey in the coarse levels
+        let mut image = Framebuffer::new(8, 8);
+        for y in 0..8 {
+            for x in (0..8).step_by(2) {
+                image.set(x, y, Color3::WHITE);
+            }
+        }
+        let texture =
+            ImageTexture::from_framebuffer(&image, false, ColorSpace::Srgb, ColorSpace::Srgb);
+        assert_eq!(texture.levels.len(), 4);
+
+        let sharp = texture.sample_filtered(0.5 / 8.0, 0.5, 0.0);
+        assert_eq!(sharp, Color3::WHITE);
+        for width in [0.25, 0.3, 1.0, 10.0] {
+            let filtered = texture.sample_filtered(0.5 / 8.0, 0.5, width);
+            assert!(
+                (filtered - Color3::from_float(0.5)).length() < 1e-9,
+                "{:?}",
+                filtered
+            );
+        }
+    }
+
+    #[test]
+    fn checker_box_filter() {
+        assert_eq!(CheckerTexture::odd_fraction(0.5, 0.5, 0.1, 0.1), 0.0);
+        assert_eq!(CheckerTexture::odd_fraction(1.5, 0.5, 0.1, 0.1), 1.0);
+        // a box over a vertical edge covers half of each
+        assert!((CheckerTexture::odd_fraction(1.0, 0.5, 0.2, 0.1) - 0.5).abs() < 1e-9);
+        assert!((CheckerTexture::odd_fraction(1.0, 0.5, 0.2, 0.0) - 0.5).abs() < 1e-9);
+        // wide footprints converge to the average
+        assert!((CheckerTexture::odd_fraction(0.3, 0.7, 0.9, 0.9) - 0.5).abs() < 0.1);
+        assert_eq!(CheckerTexture::odd_fraction(0.3, 0.7, 3.0, 3.0), 0.5);
+    }
+
+    #[test]
+    fn checker_on_a_plane() {
+        let mut world = World::new();
+        world.add_object(Plane::new(Pos3::ZERO, Lambert::new(Color3::WHITE)));
+        // two units per square, the plane's coordinates have to run past 1
+        let checker = CheckerTexture::new(0.5, Color3::BLACK, Color3::WHITE);
+        let color_at = |x: f64| {
+            let ray = Ray::new(Pos3::new(x, 1.0, 0.5), Vec3::new(0.0, -1.0, 0.0));
+            let hit = world
+                .hit_objects(&ray, &Interval::new(0.0001, f64::MAX))
+                .unwrap();
+            checker.value(&hit)
+        };
+        assert_eq!(color_at(0.5), Color3::BLACK);
+        assert_eq!(color_at(1.5), Color3::BLACK);
+        assert_eq!(color_at(2.5), Color3::WHITE);
+        assert_eq!(color_at(-0.5), Color3::WHITE);
+    }
+}
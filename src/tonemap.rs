@@ -0,0 +1,173 @@
+use crate::{
+    utils::matrix::Mat3,
+    vec3::{Color3, Vec3},
+};
+
+// Compresses the unbounded scene radiance into the [0, 1] display range.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ToneMapping {
+    Clamp,
+    Reinhard,
+    // luminance at `white_point` and above maps to pure white
+    ExtendedReinhard { white_point: f64 },
+    AcesFilmic,
+    AgX,
+}
+
+pub struct ToneMapper {
+    pub operator: ToneMapping,
+    // exposure adjustment in EV stops, each stop doubles the scene brightness
+    pub exposure: f64,
+}
+
+impl ToneMapper {
+    pub fn new(operator: ToneMapping, exposure: f64) -> Self {
+        ToneMapper { operator, exposure }
+    }
+
+    // Linear scene color to linear display color in [0, 1], the output color
+    // space applies its transfer function afterwards.
+    pub fn map(&self, color: &Color3) -> Color3 {
+        let exposed = color * 2.0_f64.powf(self.exposure);
+        let mapped = match self.operator {
+            ToneMapping::Clamp => exposed,
+            ToneMapping::Reinhard => scale_luminance(&exposed, |l| l / (1.0 + l)),
+            ToneMapping::ExtendedReinhard { white_point } => scale_luminance(&exposed, |l| {
+                l * (1.0 + l / (white_point * white_point)) / (1.0 + l)
+            }),
+            ToneMapping::AcesFilmic => aces_filmic(&exposed),
+            ToneMapping::AgX => agx(&exposed),
+        };
+
+        mapped.max(&Color3::ZERO).min(&Color3::WHITE)
+    }
+}
+
+pub fn luminance(color: &Color3) -> f64 {
+    // Rec. 709 luma coefficients
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+fn scale_luminance(color: &Color3, curve: impl Fn(f64) -> f64) -> Color3 {
+    // tone map the luminance only to keep the hue of saturated highlights
+    let l = luminance(color);
+    if l <= 0.0 {
+        return Color3::ZERO;
+    }
+    color * (curve(l) / l)
+}
+
+// Stephen Hill's fit of the ACES reference rendering and output transforms.
+// https://github.com/TheRealMJP/BakingLab/blob/master/BakingLab/ACES.hlsl
+fn aces_filmic(color: &Color3) -> Color3 {
+    const ACES_INPUT: Mat3 = Mat3::new([
+        [0.59719, 0.35458, 0.04823],
+        [0.07600, 0.90834, 0.01566],
+        [0.02840, 0.13383, 0.83777],
+    ]);
+    const ACES_OUTPUT: Mat3 = Mat3::new([
+        [1.60475, -0.53108, -0.07367],
+        [-0.10208, 1.10813, -0.00605],
+        [-0.00327, -0.07276, 1.07602],
+    ]);
+    let rrt_and_odt = |v: f64| {
+        let a = v * (v + 0.0245786) - 0.000090537;
+        let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+        a / b
+    };
+
+    let v = &ACES_INPUT * color;
+    let v = Vec3::new(rrt_and_odt(v.x), rrt_and_odt(v.y), rrt_and_odt(v.z));
+    ACES_OUTPUT * v
+}
+
+// Minimal AgX with the polynomial fit of the default contrast curve.
+// https://iolite-engine.com/blog_posts/minimal_agx_implementation
+fn agx(color: &Color3) -> Color3 {
+    const AGX_INSET: Mat3 = Mat3::new([
+        [0.842479062253094, 0.0784335999999992, 0.0792237451477643],
+        [0.0423282422610123, 0.878468636469772, 0.0791661274605434],
+        [0.0423756549057051, 0.0784336, 0.879142973793104],
+    ]);
+    const AGX_OUTSET: Mat3 = Mat3::new([
+        [1.19687900512017, -0.0980208811401368, -0.0990297440797205],
+        [-0.0528968517574562, 1.15190312990417, -0.0989611768448433],
+        [-0.0529716355144438, -0.0980434501171241, 1.15107367264116],
+    ]);
+    const MIN_EV: f64 = -12.47393;
+    const MAX_EV: f64 = 4.026069;
+
+    let contrast = |v: f64| {
+        let log = (v.max(1e-10).log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+        let x2 = log * log;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * log + 31.96 * x4 - 6.868 * x2 * log
+            + 0.4298 * x2
+            + 0.1191 * log
+            - 0.00232
+    };
+
+    let v = &AGX_INSET * color;
+    let v = Vec3::new(contrast(v.x), contrast(v.y), contrast(v.z));
+    let v = AGX_OUTSET * v;
+    // the curve produces display encoded values, return to linear for the output transfer
+    Vec3::new(
+        v.x.max(0.0).powf(2.2),
+        v.y.max(0.0).powf(2.2),
+        v.z.max(0.0).powf(2.2),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPERATORS: [ToneMapping; 5] = [
+        ToneMapping::Clamp,
+        ToneMapping::Reinhard,
+        ToneMapping::ExtendedReinhard { white_point: 4.0 },
+        ToneMapping::AcesFilmic,
+        ToneMapping::AgX,
+    ];
+
+    #[test]
+    fn display_range() {
+        for operator in OPERATORS {
+            let tone_mapper = ToneMapper::new(operator, 0.0);
+            for value in [0.0, 0.01, 0.5, 1.0, 10.0, 1000.0] {
+                let mapped = tone_mapper.map(&Color3::from_float(value));
+                assert!(mapped.x >= 0.0 && mapped.x <= 1.0, "{:?}", operator);
+            }
+        }
+    }
+
+    #[test]
+    fn monotonic() {
+        for operator in OPERATORS {
+            let tone_mapper = ToneMapper::new(operator, 0.0);
+            let mut previous = 0.0;
+            for i in 1..100 {
+                let mapped = tone_mapper.map(&Color3::from_float(i as f64 * 0.05)).y;
+                assert!(mapped >= previous, "{:?}", operator);
+                previous = mapped;
+            }
+        }
+    }
+
+    #[test]
+    fn exposure() {
+        let brighter = ToneMapper::new(ToneMapping::Clamp, 1.0);
+        let neutral = ToneMapper::new(ToneMapping::Clamp, 0.0);
+        assert_eq!(
+            brighter.map(&Color3::from_float(0.25)),
+            neutral.map(&Color3::from_float(0.5))
+        );
+    }
+
+    #[test]
+    fn extended_reinhard_white_point() {
+        let tone_mapper = ToneMapper::new(ToneMapping::ExtendedReinhard { white_point: 4.0 }, 0.0);
+        let white = tone_mapper.map(&Color3::from_float(4.0));
+        assert!((white.x - 1.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,77 @@
+use crate::{
+    ray::Ray,
+    utils::interval::Interval,
+    vec3::{Pos3, Vec3},
+};
+
+// Axis aligned bounding box.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Pos3,
+    pub max: Pos3,
+}
+
+impl Aabb {
+    pub fn new(a: Pos3, b: Pos3) -> Self {
+        Aabb {
+            min: a.min(&b),
+            max: a.max(&b),
+        }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.min(&other.min),
+            max: self.max.max(&other.max),
+        }
+    }
+
+    pub fn centroid(&self) -> Pos3 {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn extent(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        let extent = self.extent();
+        if extent.x > extent.y && extent.x > extent.z {
+            0
+        } else if extent.y > extent.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let e = self.extent();
+        2.0 * (e.x * e.y + e.y * e.z + e.z * e.x)
+    }
+
+    // Slab test, returns whether the ray enters the box within the interval.
+    pub fn hit(&self, ray: &Ray, t_interval: &Interval) -> bool {
+        self.clip(ray, t_interval).is_some()
+    }
+
+    // Part of the interval the ray spends inside the box.
+    pub fn clip(&self, ray: &Ray, t_interval: &Interval) -> Option<Interval> {
+        let mut t_min = t_interval.min;
+        let mut t_max = t_interval.max;
+        for axis in 0..3 {
+            let inv_dir = 1.0 / ray.dir[axis];
+            let mut t0 = (self.min[axis] - ray.pos[axis]) * inv_dir;
+            let mut t1 = (self.max[axis] - ray.pos[axis]) * inv_dir;
+            if inv_dir < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_max < t_min {
+                return None;
+            }
+        }
+        Some(Interval::new(t_min, t_max))
+    }
+}
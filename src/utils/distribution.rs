@@ -0,0 +1,223 @@
+// Piecewise constant density over [0, 1) proportional to a list of non-negative values,
+// sampled by inverting its cumulative distribution.
+pub struct Distribution1D {
+    values: Vec<f64>,
+    // normalized, one more entry than values
+    cdf: Vec<f64>,
+    // integral of the step function over [0, 1)
+    integral: f64,
+}
+
+// Piecewise constant density over the unit square, `values` in rows of `width` along x:
+// y is sampled from the row integrals, x from the chosen row.
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+// Discrete distribution over the indices in proportion to non-negative weights, sampled
+// in constant time (Vose's alias method): every bin keeps its own index up to its
+// threshold and hands the rest over to its alias.
+pub struct AliasTable {
+    probabilities: Vec<f64>,
+    thresholds: Vec<f64>,
+    aliases: Vec<usize>,
+}
+
+impl Distribution1D {
+    pub fn new(values: Vec<f64>) -> Self {
+        assert!(!values.is_empty());
+        let n = values.len() as f64;
+        let mut cdf = Vec::with_capacity(values.len() + 1);
+        cdf.push(0.0);
+        for value in &values {
+            cdf.push(cdf.last().unwrap() + value.max(0.0) / n);
+        }
+        let integral = *cdf.last().unwrap();
+        for (i, c) in cdf.iter_mut().enumerate() {
+            // all zero: uniform
+            *c = if integral > 0.0 {
+                *c / integral
+            } else {
+                i as f64 / n
+            };
+        }
+        Self {
+            values,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    // Point in [0, 1) for the uniform `u` and its density.
+    pub fn sample(&self, u: f64) -> (f64, f64) {
+        let n = self.values.len();
+        let i = (self.cdf.partition_point(|c| *c <= u) - 1).min(n - 1);
+        let width = self.cdf[i + 1] - self.cdf[i];
+        let offset = if width > 0.0 {
+            ((u - self.cdf[i]) / width).clamp(0.0, 1.0)
+        } else {
+            0.5
+        };
+        let x = ((i as f64 + offset) / n as f64).min(1.0 - f64::EPSILON);
+        (x, self.pdf(x))
+    }
+
+    pub fn pdf(&self, x: f64) -> f64 {
+        if self.integral <= 0.0 {
+            return 1.0;
+        }
+        let i = ((x * self.values.len() as f64) as usize).min(self.values.len() - 1);
+        self.values[i].max(0.0) / self.integral
+    }
+}
+
+impl Distribution2D {
+    pub fn new(values: &[f64], width: usize) -> Self {
+        let rows: Vec<Distribution1D> = values
+            .chunks_exact(width)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(rows.iter().map(|row| row.integral()).collect());
+        Self { rows, marginal }
+    }
+
+    // integral of the step function over the unit square
+    pub fn integral(&self) -> f64 {
+        self.marginal.integral()
+    }
+
+    // Point in the unit square and its density.
+    pub fn sample(&self, u1: f64, u2: f64) -> ((f64, f64), f64) {
+        let (y, pdf_y) = self.marginal.sample(u2);
+        let (x, pdf_x) = self.rows[self.row(y)].sample(u1);
+        ((x, y), pdf_x * pdf_y)
+    }
+
+    pub fn pdf(&self, x: f64, y: f64) -> f64 {
+        self.rows[self.row(y)].pdf(x) * self.marginal.pdf(y)
+    }
+
+    fn row(&self, y: f64) -> usize {
+        ((y * self.rows.len() as f64) as usize).min(self.rows.len() - 1)
+    }
+}
+
+impl AliasTable {
+    pub fn new(weights: &[f64]) -> Self {
+        assert!(!weights.is_empty());
+        let n = weights.len();
+        let total: f64 = weights.iter().map(|w| w.max(0.0)).sum();
+        // all zero: uniform
+        let probabilities: Vec<f64> = weights
+            .iter()
+            .map(|w| {
+                if total > 0.0 {
+                    w.max(0.0) / total
+                } else {
+                    1.0 / n as f64
+                }
+            })
+            .collect();
+
+        let mut scaled: Vec<f64> = probabilities.iter().map(|p| p * n as f64).collect();
+        let (mut small, mut large): (Vec<usize>, Vec<usize>) =
+            (0..n).partition(|&i| scaled[i] < 1.0);
+        let mut thresholds = vec![1.0; n];
+        let mut aliases: Vec<usize> = (0..n).collect();
+        while let (Some(&s), Some(&l)) = (small.last(), large.last()) {
+            small.pop();
+            large.pop();
+            thresholds[s] = scaled[s];
+            aliases[s] = l;
+            scaled[l] += scaled[s] - 1.0;
+            if scaled[l] < 1.0 {
+                small.push(l);
+            } else {
+                large.push(l);
+            }
+        }
+        // the rest is 1 up to rounding
+
+        Self {
+            probabilities,
+            thresholds,
+            aliases,
+        }
+    }
+
+    // Index for the uniform `u` and its probability.
+    pub fn sample(&self, u: f64) -> (usize, f64) {
+        let n = self.probabilities.len();
+        let x = u * n as f64;
+        let bin = (x as usize).min(n - 1);
+        let index = if x - (bin as f64) < self.thresholds[bin] {
+            bin
+        } else {
+            self.aliases[bin]
+        };
+        (index, self.probabilities[index])
+    }
+
+    pub fn probability(&self, index: usize) -> f64 {
+        self.probabilities[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn piecewise_constant() {
+        let distribution = Distribution1D::new(vec![1.0, 0.0, 3.0, 4.0]);
+        assert_eq!(distribution.integral(), 2.0);
+        assert_eq!(distribution.pdf(0.1), 0.5);
+        assert_eq!(distribution.pdf(0.3), 0.0);
+        assert_eq!(distribution.sample(0.0), (0.0, 0.5));
+        // the cdf is 0.125 after the first value, the empty bin is skipped
+        let (x, pdf) = distribution.sample(0.125 + 0.375 / 2.0);
+        assert!((x - 0.625).abs() < 1e-12 && pdf == 1.5);
+
+        let mut histogram = [0; 4];
+        let n = 40_000;
+        for i in 0..n {
+            let (x, _) = distribution.sample((i as f64 + 0.5) / n as f64);
+            histogram[(x * 4.0) as usize] += 1;
+        }
+        assert_eq!(histogram, [5000, 0, 15000, 20000]);
+
+        let uniform = Distribution1D::new(vec![0.0; 3]);
+        assert_eq!(uniform.sample(0.5), (0.5, 1.0));
+
+        let grid = Distribution2D::new(&[1.0, 3.0, 0.0, 0.0, 2.0, 2.0], 2);
+        // rows of integral 2, 0 and 2 (out of 4 / 3 in total)
+        let ((x, y), pdf) = grid.sample(0.9, 0.1);
+        assert!(x >= 0.5 && y < 1.0 / 3.0);
+        assert!((pdf - grid.pdf(x, y)).abs() < 1e-12);
+        assert!((pdf - 1.5 * 1.5).abs() < 1e-12);
+        assert_eq!(grid.pdf(0.2, 0.5), 0.0);
+        assert!((grid.integral() - 4.0 / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn alias_table() {
+        let table = AliasTable::new(&[1.0, 0.0, 3.0, 4.0, 2.0]);
+        assert_eq!(table.probability(2), 0.3);
+        let mut histogram = [0; 5];
+        let n = 10_000;
+        for i in 0..n {
+            let (index, probability) = table.sample((i as f64 + 0.5) / n as f64);
+            assert_eq!(probability, table.probability(index));
+            histogram[index] += 1;
+        }
+        assert_eq!(histogram, [1000, 0, 3000, 4000, 2000]);
+
+        let uniform = AliasTable::new(&[0.0, 0.0]);
+        assert_eq!(uniform.sample(0.7), (1, 0.5));
+    }
+}
@@ -1,6 +1,12 @@
+use std::f64::consts::PI;
+
 use rand::Rng;
 
-use crate::vec3::{Color3, Vec3};
+use crate::{
+    sampler,
+    utils::onb::Onb,
+    vec3::{Color3, Pos3, Vec3},
+};
 
 pub fn degrees_to_radians(deg: f64) -> f64 {
     deg * std::f64::consts::PI / 180.0
@@ -20,7 +26,7 @@ pub fn random_in_unit_sphere_normalized() -> Vec3 {
 }
 
 pub fn random_in_unit_disk() -> Vec3 {
-    let mut rng = rand::thread_rng();
+    let mut rng = sampler::rng();
     loop {
         let v = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
 
@@ -30,11 +36,55 @@ pub fn random_in_unit_disk() -> Vec3 {
     }
 }
 
-pub fn linear_to_gamma_color(value: f64) -> f64 {
-    if value > 0.0 {
-        value.sqrt()
+// Direction from `origin` uniformly within the cone the sphere (`center`, `radius`) covers,
+// with its solid angle pdf. Inside the sphere every direction is possible.
+pub fn sample_cone(origin: &Pos3, center: &Pos3, radius: f64) -> (Vec3, f64) {
+    let to_center = *center - *origin;
+    let distance_sq = to_center.length_squared();
+    if distance_sq <= radius * radius {
+        return (random_in_unit_sphere_normalized(), 1.0 / (4.0 * PI));
+    }
+
+    let mut rng = sampler::rng();
+    let cos_theta_max = (1.0 - radius * radius / distance_sq).sqrt();
+    let cos_theta = 1.0 - rng.gen::<f64>() * (1.0 - cos_theta_max);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * rng.gen::<f64>();
+    let local = Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta);
+    let direction = Onb::from_w(&to_center).to_world(&local);
+
+    (direction, 1.0 / (2.0 * PI * (1.0 - cos_theta_max)))
+}
+
+// Density of sample_cone choosing the (normalized) `direction`.
+pub fn cone_pdf(origin: &Pos3, center: &Pos3, radius: f64, direction: &Vec3) -> f64 {
+    let to_center = *center - *origin;
+    let distance_sq = to_center.length_squared();
+    if distance_sq <= radius * radius {
+        return 1.0 / (4.0 * PI);
+    }
+
+    let cos_theta_max = (1.0 - radius * radius / distance_sq).sqrt();
+    if Vec3::dot(direction, &to_center) < cos_theta_max * distance_sq.sqrt() {
+        return 0.0;
+    }
+    1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+}
+
+pub fn linear_to_srgb(value: f64) -> f64 {
+    // sRGB transfer function (IEC 61966-2-1)
+    if value <= 0.0031308 {
+        12.92 * value.max(0.0)
     } else {
-        0.0
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+pub fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
     }
 }
 
@@ -51,6 +101,42 @@ pub fn refract_vector(vec: &Vec3, normal: &Vec3, refraction_ratio: f64) -> Vec3
     ref_vec_perp + ref_vec_para
 }
 
+// Shading normal `normal` turned towards the geometric normal just enough that the mirror
+// reflection of the (normalized, outgoing) direction `wo` stays above the surface.
+// https://github.com/blender/cycles/blob/main/src/kernel/geom/shader_data.h (ensure_valid_reflection)
+pub fn ensure_valid_reflection(geometric_normal: &Vec3, wo: &Vec3, normal: &Vec3) -> Vec3 {
+    let reflected = 2.0 * Vec3::dot(normal, wo) * *normal - *wo;
+    // reflections may always be at least as shallow as the incoming direction
+    let wo_z = Vec3::dot(wo, geometric_normal);
+    let threshold = (0.9 * wo_z).min(0.01);
+    if Vec3::dot(geometric_normal, &reflected) >= threshold {
+        return *normal;
+    }
+
+    // frame with the geometric normal as z and the shading normal in the x-z plane,
+    // the new normal is the one in that plane reflecting `wo` to exactly the threshold
+    let x = *normal - Vec3::dot(normal, geometric_normal) * *geometric_normal;
+    if x.near_zero() {
+        return *geometric_normal;
+    }
+    let x = x.normalize();
+    let wo_x = Vec3::dot(wo, &x);
+    let a = wo_x * wo_x + wo_z * wo_z;
+    if a < 1e-12 {
+        return *geometric_normal;
+    }
+    let b = 2.0 * (a + wo_z * threshold);
+    let c = (threshold + wo_z).powi(2);
+    let root = (b * b - 4.0 * a * c).max(0.0).sqrt();
+    let z_sq = if wo_x < 0.0 {
+        0.25 * (b + root) / a
+    } else {
+        0.25 * (b - root) / a
+    };
+    let z_sq = z_sq.clamp(0.0, 1.0);
+    (1.0 - z_sq).sqrt() * x + z_sq.sqrt() * *geometric_normal
+}
+
 pub fn reflectance(cosine: f64, refraction_index: f64) -> f64 {
     // Schlick's approximation
     // https://en.wikipedia.org/wiki/Schlick%27s_approximation
@@ -59,9 +145,9 @@ pub fn reflectance(cosine: f64, refraction_index: f64) -> f64 {
 }
 
 pub fn color_to_ppm(color: &Color3) -> String {
-    // transform from linear to gamma (gamma = 2)
-    let r = (linear_to_gamma_color(color.x).min(0.999999) * 256.0) as i32;
-    let g = (linear_to_gamma_color(color.y).min(0.999999) * 256.0) as i32;
-    let b = (linear_to_gamma_color(color.z).min(0.999999) * 256.0) as i32;
+    // expects a display encoded color, see ToneMapper
+    let r = (color.x.clamp(0.0, 0.999999) * 256.0) as i32;
+    let g = (color.y.clamp(0.0, 0.999999) * 256.0) as i32;
+    let b = (color.z.clamp(0.0, 0.999999) * 256.0) as i32;
     format!("{} {} {}\n", r, g, b)
 }
@@ -0,0 +1,102 @@
+use std::ops::Mul;
+
+use crate::vec3::Vec3;
+
+// Row-major 3x3 matrix, used for color space transforms.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Mat3 {
+    pub rows: [[f64; 3]; 3],
+}
+
+impl Mat3 {
+    pub const IDENTITY: Mat3 = Mat3 {
+        rows: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+    };
+
+    pub const fn new(rows: [[f64; 3]; 3]) -> Self {
+        Mat3 { rows }
+    }
+
+    pub fn from_columns(a: &Vec3, b: &Vec3, c: &Vec3) -> Self {
+        Mat3::new([[a.x, b.x, c.x], [a.y, b.y, c.y], [a.z, b.z, c.z]])
+    }
+
+    pub fn diagonal(v: &Vec3) -> Self {
+        Mat3::new([[v.x, 0.0, 0.0], [0.0, v.y, 0.0], [0.0, 0.0, v.z]])
+    }
+
+    pub fn row(&self, i: usize) -> Vec3 {
+        Vec3::new(self.rows[i][0], self.rows[i][1], self.rows[i][2])
+    }
+
+    pub fn determinant(&self) -> f64 {
+        let m = &self.rows;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    pub fn inverse(&self) -> Mat3 {
+        // adjugate divided by the determinant, rows of the inverse are cross products of the columns
+        let m = &self.rows;
+        let inv_det = 1.0 / self.determinant();
+        let c0 = Vec3::new(m[0][0], m[1][0], m[2][0]);
+        let c1 = Vec3::new(m[0][1], m[1][1], m[2][1]);
+        let c2 = Vec3::new(m[0][2], m[1][2], m[2][2]);
+        let r0 = Vec3::cross(&c1, &c2) * inv_det;
+        let r1 = Vec3::cross(&c2, &c0) * inv_det;
+        let r2 = Vec3::cross(&c0, &c1) * inv_det;
+        Mat3::new([[r0.x, r0.y, r0.z], [r1.x, r1.y, r1.z], [r2.x, r2.y, r2.z]])
+    }
+}
+
+impl Mul<&Vec3> for &Mat3 {
+    type Output = Vec3;
+
+    fn mul(self, v: &Vec3) -> Vec3 {
+        Vec3::new(
+            Vec3::dot(&self.row(0), v),
+            Vec3::dot(&self.row(1), v),
+            Vec3::dot(&self.row(2), v),
+        )
+    }
+}
+
+impl Mul<Vec3> for Mat3 {
+    type Output = Vec3;
+
+    #[inline]
+    fn mul(self, v: Vec3) -> Vec3 {
+        &self * &v
+    }
+}
+
+impl Mul<Mat3> for Mat3 {
+    type Output = Mat3;
+
+    fn mul(self, other: Mat3) -> Mat3 {
+        let mut rows = [[0.0; 3]; 3];
+        for (i, row) in rows.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..3).map(|k| self.rows[i][k] * other.rows[k][j]).sum();
+            }
+        }
+        Mat3::new(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inverse() {
+        let m = Mat3::new([[2.0, 0.0, 1.0], [1.0, 3.0, 0.0], [0.0, 1.0, 4.0]]);
+        let identity = m * m.inverse();
+        for i in 0..3 {
+            for j in 0..3 {
+                assert!((identity.rows[i][j] - Mat3::IDENTITY.rows[i][j]).abs() < 1e-12);
+            }
+        }
+    }
+}
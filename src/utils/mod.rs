@@ -1,3 +1,8 @@
+pub mod aabb;
+pub mod complex;
+pub mod distribution;
 pub mod helpers;
 pub mod interval;
 mod macros;
+pub mod matrix;
+pub mod onb;
@@ -0,0 +1,35 @@
+use crate::vec3::Vec3;
+
+// Orthonormal basis, `w` is the "up" (normal) direction of the local frame.
+#[derive(Copy, Clone, Debug)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn from_w(w: &Vec3) -> Self {
+        let w = w.normalize();
+        let helper = if w.x.abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = Vec3::cross(&w, &helper).normalize();
+        let u = Vec3::cross(&v, &w);
+        Onb { u, v, w }
+    }
+
+    pub fn to_world(&self, local: &Vec3) -> Vec3 {
+        local.x * self.u + local.y * self.v + local.z * self.w
+    }
+
+    pub fn to_local(&self, world: &Vec3) -> Vec3 {
+        Vec3::new(
+            Vec3::dot(world, &self.u),
+            Vec3::dot(world, &self.v),
+            Vec3::dot(world, &self.w),
+        )
+    }
+}
@@ -4,7 +4,7 @@ use std::ops::{
 
 use rand::Rng;
 
-use crate::{impl_binary_operations, impl_op_assign, impl_unary_operations};
+use crate::{impl_binary_operations, impl_op_assign, impl_unary_operations, sampler};
 
 #[derive(Debug, Copy, Clone)]
 pub enum Axis {
@@ -112,7 +112,7 @@ impl Vec3 {
     }
 
     pub fn random(min: f64, max: f64) -> Vec3 {
-        let mut rng = rand::thread_rng();
+        let mut rng = sampler::rng();
         Vec3::new(
             rng.gen_range(min..max),
             rng.gen_range(min..max),
@@ -124,6 +124,10 @@ impl Vec3 {
         let tolerance = 0.00000001;
         self.x.abs() < tolerance && self.y.abs() < tolerance && self.z.abs() < tolerance
     }
+
+    pub fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite() && self.z.is_finite()
+    }
 }
 
 impl Default for Vec3 {
@@ -329,6 +333,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::unnecessary_cast)]
     fn length() {
         let a = Vec3::new(3.0, 2.0, 1.0);
         assert_eq!(
@@ -1,37 +1,168 @@
-use std::rc::Rc;
+use std::{cell::OnceCell, rc::Rc};
+
+use rand::Rng;
 
 use crate::{
-    objects::object::{HitRecord, Object},
+    color_space::ColorSpace,
+    environment::Environment,
+    light::Light,
+    objects::{
+        bvh::{Bvh, TraversalStats},
+        light_tree::LightTree,
+        object::{HitRecord, Object},
+    },
     ray::Ray,
-    utils::interval::Interval,
+    sampler,
+    utils::{aabb::Aabb, interval::Interval},
 };
 
 pub struct World {
+    // add objects through add_object, it keeps the indices and structures below up to date
     pub objects: Vec<Rc<dyn Object>>,
+    // indices of the objects that emit light
+    emitters: Vec<usize>,
+    // indices of the participating media
+    media: Vec<usize>,
+    // point, spot and directional lights, apart from the objects
+    lights: Vec<Light>,
+    // built on the first hit test after objects were added
+    bvh: OnceCell<Bvh>,
+    // built on the first light sample, like the bvh
+    light_tree: OnceCell<LightTree>,
+    pub environment: Environment,
+    // color space of all material and light colors, textures and the environment are
+    // converted into it when they are built
+    pub working_space: ColorSpace,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        World::new()
+    }
 }
 
 impl World {
     pub fn new() -> Self {
         World {
             objects: Vec::new(),
+            emitters: Vec::new(),
+            media: Vec::new(),
+            lights: Vec::new(),
+            bvh: OnceCell::new(),
+            light_tree: OnceCell::new(),
+            environment: Environment::gradient(ColorSpace::Srgb),
+            working_space: ColorSpace::Srgb,
         }
     }
 
     pub fn add_object<T: 'static + Object>(&mut self, object: T) {
+        if object.is_emissive() {
+            self.emitters.push(self.objects.len());
+        }
+        if object.is_medium() {
+            self.media.push(self.objects.len());
+        }
         self.objects.push(Rc::new(object));
+        self.bvh.take();
+        self.light_tree.take();
+    }
+
+    pub fn emitters(&self) -> &[usize] {
+        &self.emitters
+    }
+
+    // Picks the emitters for light sampling.
+    pub fn light_tree(&self) -> &LightTree {
+        self.light_tree
+            .get_or_init(|| LightTree::build(&self.objects, &self.emitters))
+    }
+
+    pub fn add_light(&mut self, light: Light) {
+        self.lights.push(light);
+    }
+
+    pub fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    // Bounds of the objects, unbounded ones (planes) are not included.
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.media
+            .iter()
+            .filter_map(|&object_id| self.objects[object_id].bounding_box())
+            .fold(self.bvh().bounds(), |bounds, medium| {
+                Some(bounds.map_or(medium, |bounds| bounds.union(&medium)))
+            })
+    }
+
+    fn bvh(&self) -> &Bvh {
+        self.bvh.get_or_init(|| Bvh::build(&self.objects))
     }
 
     pub fn hit_objects(&self, ray: &Ray, t_interval: &Interval) -> Option<HitRecord> {
-        let mut hit: Option<HitRecord> = None;
-        let mut nearest_hit = t_interval.max;
+        self.hit_objects_with_stats(ray, t_interval, &mut TraversalStats::default())
+    }
+
+    pub fn hit_objects_with_stats(
+        &self,
+        ray: &Ray,
+        t_interval: &Interval,
+        stats: &mut TraversalStats,
+    ) -> Option<HitRecord> {
+        let mut hit = self.hit_surface(ray, t_interval, stats);
+        // media scatter the ray before it reaches the surface
+        for &object_id in &self.media {
+            let max = hit.as_ref().map_or(t_interval.max, |hit| hit.ray_scalar);
+            let interval = Interval::new(t_interval.min, max);
+            if let Some(mut collision) = self.objects[object_id].hit(ray, &interval) {
+                collision.object_id = object_id;
+                hit = Some(collision);
+            }
+        }
+        hit.map(|hit| self.prepare_hit(ray, hit))
+    }
+
+    // Nearest surface along the ray, passing through the media; shadow rays weight it by
+    // the transmittance of the media in between.
+    pub fn hit_surfaces(&self, ray: &Ray, t_interval: &Interval) -> Option<HitRecord> {
+        self.hit_surface(ray, t_interval, &mut TraversalStats::default())
+            .map(|hit| self.prepare_hit(ray, hit))
+    }
 
-        for obj in &self.objects {
-            if let Some(h) = obj.hit(ray, &Interval::new(t_interval.min, nearest_hit)) {
-                nearest_hit = h.ray_scalar;
-                hit = Some(h);
+    // Fraction of the light crossing the media along the ray, estimated by ratio tracking.
+    pub fn transmittance(&self, ray: &Ray, t_interval: &Interval) -> f64 {
+        self.media
+            .iter()
+            .map(|&object_id| self.objects[object_id].transmittance(ray, t_interval))
+            .product()
+    }
+
+    fn hit_surface(
+        &self,
+        ray: &Ray,
+        t_interval: &Interval,
+        stats: &mut TraversalStats,
+    ) -> Option<HitRecord> {
+        let bvh = self.bvh();
+        let mut interval = Interval::new(t_interval.min, t_interval.max);
+        loop {
+            let hit = bvh.hit(&self.objects, ray, &interval, stats)?;
+            // cutouts: the ray continues behind a hit with its transparency as probability
+            let opacity = hit.material.opacity(&hit);
+            if opacity >= 1.0 || sampler::rng().gen::<f64>() < opacity {
+                return Some(hit);
             }
+            interval = Interval::new(hit.ray_scalar + 0.0001, interval.max);
         }
+    }
 
+    fn prepare_hit(&self, ray: &Ray, mut hit: HitRecord) -> HitRecord {
+        hit.object = Some(self.objects[hit.object_id].clone());
+        if ray.differentials.is_some() {
+            hit.compute_footprint(ray);
+        }
+        let material = hit.material.clone();
+        material.prepare_hit(ray, &mut hit);
         hit
     }
 }